@@ -111,7 +111,9 @@ fn main() {
             }
 
             let serve = h2.serve(sock);
-            reactor.spawn(serve.map_err(|e| error!("h2 error: {:?}", e)));
+            reactor.spawn(serve
+                .map(|closed| info!("connection closed: {:?}", closed))
+                .map_err(|e| error!("h2 error: {:?}", e)));
 
             Ok((h2, reactor))
         });
@@ -1,13 +1,21 @@
+use buf::SendBuf;
+
 use h2;
 use bytes::{IntoBuf, Bytes};
 use futures::{Async, Poll};
+use futures::future::Either;
 use http::HeaderMap;
 
-use std::fmt;
+use std::{fmt, mem};
 
 /// A generic h2 client/server request/response body.
 pub trait Body {
     /// The body chunk type
+    ///
+    /// This may be any `IntoBuf`, including any `Buf` such as a `Chain` of
+    /// buffers or a file-backed buffer. Chunks are handed to h2 as they are,
+    /// without being copied into a `Bytes`, unless the server has been
+    /// configured to coalesce small chunks.
     type Data: IntoBuf + 'static;
 
     /// Returns `true` when the end of stream has been reached.
@@ -30,6 +38,9 @@ pub trait Body {
     }
 }
 
+/// The buffer type a body's data chunks are sent as.
+pub(crate) type BodyBuf<B> = SendBuf<<<B as Body>::Data as IntoBuf>::Buf>;
+
 /// Dynamic `Send` body object.
 pub struct BoxBody<T = Bytes> {
     inner: Box<Body<Data = T> + Send + 'static>,
@@ -61,6 +72,136 @@ impl Body for () {
     }
 }
 
+impl Body for &'static [u8] {
+    type Data = &'static [u8];
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.is_empty()
+    }
+
+    #[inline]
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        if self.is_empty() {
+            return Ok(Async::Ready(None));
+        }
+
+        Ok(Async::Ready(Some(mem::replace(self, &[]))))
+    }
+}
+
+impl Body for Bytes {
+    type Data = Bytes;
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.is_empty()
+    }
+
+    #[inline]
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        if self.is_empty() {
+            return Ok(Async::Ready(None));
+        }
+
+        Ok(Async::Ready(Some(mem::replace(self, Bytes::new()))))
+    }
+}
+
+impl Body for Vec<u8> {
+    type Data = Bytes;
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.is_empty()
+    }
+
+    #[inline]
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        if self.is_empty() {
+            return Ok(Async::Ready(None));
+        }
+
+        let data = mem::replace(self, Vec::new());
+        Ok(Async::Ready(Some(data.into())))
+    }
+}
+
+impl Body for String {
+    type Data = Bytes;
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.is_empty()
+    }
+
+    #[inline]
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        if self.is_empty() {
+            return Ok(Async::Ready(None));
+        }
+
+        let data = mem::replace(self, String::new());
+        Ok(Async::Ready(Some(data.into())))
+    }
+}
+
+/// `None` is an empty body.
+impl<B: Body> Body for Option<B> {
+    type Data = B::Data;
+
+    fn is_end_stream(&self) -> bool {
+        match *self {
+            Some(ref body) => body.is_end_stream(),
+            None => true,
+        }
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        match *self {
+            Some(ref mut body) => body.poll_data(),
+            None => Ok(Async::Ready(None)),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, h2::Error> {
+        match *self {
+            Some(ref mut body) => body.poll_trailers(),
+            None => Ok(Async::Ready(None)),
+        }
+    }
+}
+
+/// Allows a service to respond with one of two body types, as long as both
+/// produce the same type of data.
+impl<A, B> Body for Either<A, B>
+where A: Body,
+      B: Body<Data = A::Data>,
+{
+    type Data = A::Data;
+
+    fn is_end_stream(&self) -> bool {
+        match *self {
+            Either::A(ref body) => body.is_end_stream(),
+            Either::B(ref body) => body.is_end_stream(),
+        }
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        match *self {
+            Either::A(ref mut body) => body.poll_data(),
+            Either::B(ref mut body) => body.poll_data(),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, h2::Error> {
+        match *self {
+            Either::A(ref mut body) => body.poll_trailers(),
+            Either::B(ref mut body) => body.poll_trailers(),
+        }
+    }
+}
+
 // ===== impl BoxBody =====
 
 impl<T> BoxBody<T> {
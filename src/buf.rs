@@ -0,0 +1,137 @@
+use bytes::{Buf, Bytes};
+use iovec::IoVec;
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::io::Cursor;
+
+/// A chunk of body data sent on an h2 stream.
+///
+/// This is either a chunk yielded by the body, several of them coalesced into
+/// a single buffer, or several chunks queued to be sent as a single frame
+/// without being copied.
+#[derive(Debug)]
+pub struct SendBuf<B> {
+    inner: Inner<B>,
+}
+
+#[derive(Debug)]
+enum Inner<B> {
+    Buf(B),
+    Bytes(Cursor<Bytes>),
+    Queue(VecDeque<SendBuf<B>>),
+    Empty,
+}
+
+// ===== impl SendBuf =====
+
+impl<B> SendBuf<B> {
+    pub(crate) fn buf(buf: B) -> Self {
+        SendBuf { inner: Inner::Buf(buf) }
+    }
+
+    pub(crate) fn bytes(bytes: Bytes) -> Self {
+        SendBuf { inner: Inner::Bytes(Cursor::new(bytes)) }
+    }
+
+    pub(crate) fn empty() -> Self {
+        SendBuf { inner: Inner::Empty }
+    }
+
+    /// Returns the number of chunks in the buffer.
+    pub(crate) fn chunks(&self) -> usize {
+        match self.inner {
+            Inner::Queue(ref queue) => queue.len(),
+            Inner::Empty => 0,
+            _ => 1,
+        }
+    }
+
+    /// Returns a buffer of this buffer's chunks followed by `next`.
+    pub(crate) fn push(self, next: SendBuf<B>) -> Self {
+        let mut queue = match self.inner {
+            Inner::Queue(queue) => queue,
+            Inner::Empty => VecDeque::new(),
+            inner => {
+                let mut queue = VecDeque::new();
+                queue.push_back(SendBuf { inner });
+                queue
+            }
+        };
+
+        queue.push_back(next);
+        SendBuf { inner: Inner::Queue(queue) }
+    }
+}
+
+impl<B: Buf> Buf for SendBuf<B> {
+    fn remaining(&self) -> usize {
+        match self.inner {
+            Inner::Buf(ref b) => b.remaining(),
+            Inner::Bytes(ref b) => b.remaining(),
+            Inner::Queue(ref q) => q.iter().map(Buf::remaining).sum(),
+            Inner::Empty => 0,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self.inner {
+            Inner::Buf(ref b) => b.bytes(),
+            Inner::Bytes(ref b) => b.bytes(),
+            Inner::Queue(ref q) => {
+                q.iter()
+                    .find(|b| b.has_remaining())
+                    .map(Buf::bytes)
+                    .unwrap_or(&[])
+            }
+            Inner::Empty => &[],
+        }
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        match self.inner {
+            Inner::Buf(ref mut b) => b.advance(cnt),
+            Inner::Bytes(ref mut b) => b.advance(cnt),
+            Inner::Queue(ref mut q) => {
+                let mut cnt = cnt;
+
+                while cnt > 0 {
+                    let consumed = {
+                        let front = q.front_mut().expect("advanced past end of buffer");
+                        let n = cmp::min(cnt, front.remaining());
+
+                        front.advance(n);
+                        cnt -= n;
+                        !front.has_remaining()
+                    };
+
+                    if consumed {
+                        q.pop_front();
+                    }
+                }
+            }
+            Inner::Empty => assert_eq!(cnt, 0, "advanced past end of empty buffer"),
+        }
+    }
+
+    fn bytes_vec<'a>(&'a self, dst: &mut [&'a IoVec]) -> usize {
+        match self.inner {
+            Inner::Buf(ref b) => b.bytes_vec(dst),
+            Inner::Bytes(ref b) => b.bytes_vec(dst),
+            Inner::Queue(ref q) => {
+                let mut n = 0;
+
+                for b in q {
+                    if n == dst.len() {
+                        break;
+                    }
+
+                    n += b.bytes_vec(&mut dst[n..]);
+                }
+
+                n
+            }
+            Inner::Empty => 0,
+        }
+    }
+}
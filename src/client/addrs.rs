@@ -0,0 +1,227 @@
+//! Connecting to one of several addresses of a host.
+
+use futures::{Async, Future, Poll};
+use tokio_connect;
+use tokio_core::net::{TcpStream, TcpStreamNew};
+use tokio_core::reactor::Handle;
+use tokio_timer::{Sleep, Timer};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// The delay between starting connection attempts, recommended by RFC 8305.
+const DEFAULT_DELAY_MS: u64 = 250;
+
+/// Connects to the first of several addresses that accepts a connection.
+///
+/// Connection attempts are raced as described by RFC 8305 ("Happy
+/// Eyeballs"): addresses are tried alternating between IPv6 and IPv4,
+/// starting with IPv6, and each attempt is started once the previous one has
+/// failed or has been pending for the connection attempt delay. The first
+/// connection established is used, and the other attempts are dropped.
+///
+/// `ConnectAddrs` implements `tokio_connect::Connect`, so it may be used as
+/// the transport of a `client::Connect`.
+#[derive(Clone)]
+pub struct ConnectAddrs {
+    addrs: Vec<SocketAddr>,
+    handle: Handle,
+    timer: Timer,
+    delay: Duration,
+}
+
+/// Completes with the first connection established by a `ConnectAddrs`.
+pub struct ConnectAddrsFuture {
+    remaining: VecDeque<SocketAddr>,
+    attempts: Vec<(SocketAddr, TcpStreamNew)>,
+    next_attempt: Option<Sleep>,
+    handle: Handle,
+    timer: Timer,
+    delay: Duration,
+    error: Option<io::Error>,
+}
+
+// ===== impl ConnectAddrs =====
+
+impl ConnectAddrs {
+    /// Create a new `ConnectAddrs` that connects to one of `addrs`, using
+    /// `handle` to open TCP connections.
+    pub fn new<I>(addrs: I, handle: Handle) -> Self
+    where I: IntoIterator<Item = SocketAddr>,
+    {
+        ConnectAddrs {
+            addrs: interleave(addrs),
+            handle,
+            timer: Timer::default(),
+            delay: Duration::from_millis(DEFAULT_DELAY_MS),
+        }
+    }
+
+    /// Sets the delay after which the next connection attempt is started,
+    /// while the previous attempts are pending.
+    ///
+    /// The default is 250 milliseconds.
+    pub fn connection_attempt_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Sets the timer used to delay connection attempts.
+    pub fn timer(mut self, timer: Timer) -> Self {
+        self.timer = timer;
+        self
+    }
+
+    /// Returns the addresses, in the order they are tried.
+    pub fn addrs(&self) -> &[SocketAddr] {
+        &self.addrs
+    }
+}
+
+impl tokio_connect::Connect for ConnectAddrs {
+    type Connected = TcpStream;
+    type Error = io::Error;
+    type Future = ConnectAddrsFuture;
+
+    fn connect(&self) -> Self::Future {
+        ConnectAddrsFuture {
+            remaining: self.addrs.iter().cloned().collect(),
+            attempts: Vec::new(),
+            next_attempt: None,
+            handle: self.handle.clone(),
+            timer: self.timer.clone(),
+            delay: self.delay,
+            error: None,
+        }
+    }
+}
+
+impl fmt::Debug for ConnectAddrs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectAddrs")
+            .field("addrs", &self.addrs)
+            .field("delay", &self.delay)
+            .finish()
+    }
+}
+
+/// Orders `addrs` so that IPv6 and IPv4 addresses alternate, starting with
+/// IPv6, keeping the relative order of each family.
+fn interleave<I>(addrs: I) -> Vec<SocketAddr>
+where I: IntoIterator<Item = SocketAddr>,
+{
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+
+    let mut v6 = v6.into_iter();
+    let mut v4 = v4.into_iter();
+    let mut addrs = Vec::new();
+
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return addrs,
+            (a, b) => {
+                addrs.extend(a);
+                addrs.extend(b);
+            }
+        }
+    }
+}
+
+// ===== impl ConnectAddrsFuture =====
+
+impl ConnectAddrsFuture {
+    /// Start a connection attempt to the next address, if there is one.
+    fn start_attempt(&mut self) {
+        self.next_attempt = None;
+
+        if let Some(addr) = self.remaining.pop_front() {
+            trace!("connecting; addr={}", addr);
+            let attempt = TcpStream::connect(&addr, &self.handle);
+            self.attempts.push((addr, attempt));
+
+            if !self.remaining.is_empty() {
+                self.next_attempt = Some(self.timer.sleep(self.delay));
+            }
+        }
+    }
+
+    /// Returns `true` if the next attempt is due.
+    fn poll_next_attempt(&mut self) -> bool {
+        match self.next_attempt {
+            Some(ref mut sleep) => match sleep.poll() {
+                Ok(Async::Ready(())) => true,
+                Ok(Async::NotReady) => false,
+                Err(e) => {
+                    warn!("connection attempt timer failed; starting next attempt: {:?}", e);
+                    true
+                }
+            },
+            None => false,
+        }
+    }
+}
+
+impl Future for ConnectAddrsFuture {
+    type Item = TcpStream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<TcpStream, io::Error> {
+        if self.attempts.is_empty() {
+            self.start_attempt();
+        }
+
+        loop {
+            let mut i = 0;
+
+            while i < self.attempts.len() {
+                let res = self.attempts[i].1.poll();
+
+                match res {
+                    Ok(Async::Ready(stream)) => {
+                        debug!("connected; addr={}", self.attempts[i].0);
+                        // Dropping the other attempts aborts them.
+                        return Ok(Async::Ready(stream));
+                    }
+                    Ok(Async::NotReady) => i += 1,
+                    Err(e) => {
+                        let (addr, _) = self.attempts.swap_remove(i);
+                        debug!("connection attempt failed; addr={}: {}", addr, e);
+                        self.error = Some(e);
+
+                        // Start the next attempt without waiting for the delay.
+                        self.start_attempt();
+                    }
+                }
+            }
+
+            if self.poll_next_attempt() {
+                self.start_attempt();
+                continue;
+            }
+
+            if self.attempts.is_empty() {
+                let error = self.error.take().unwrap_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "no addresses to connect to")
+                });
+
+                return Err(error);
+            }
+
+            return Ok(Async::NotReady);
+        }
+    }
+}
+
+impl fmt::Debug for ConnectAddrsFuture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let attempts: Vec<_> = self.attempts.iter().map(|&(addr, _)| addr).collect();
+
+        f.debug_struct("ConnectAddrsFuture")
+            .field("attempts", &attempts)
+            .field("remaining", &self.remaining)
+            .finish()
+    }
+}
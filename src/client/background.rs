@@ -1,4 +1,4 @@
-use Body;
+use body::{Body, BodyBuf};
 use flush::Flush;
 
 use futures::{Future, Poll};
@@ -18,7 +18,7 @@ where S: Body,
 enum Task<T, S>
 where S: Body,
 {
-    Connection(Connection<T, S::Data>),
+    Connection(Connection<T, BodyBuf<S>>),
     Flush(Flush<S>),
 }
 
@@ -27,7 +27,7 @@ where S: Body,
 impl<T, S> Background<T, S>
 where S: Body,
 {
-    pub(crate) fn connection(connection: Connection<T, S::Data>) -> Self {
+    pub(crate) fn connection(connection: Connection<T, BodyBuf<S>>) -> Self {
         let task = Task::Connection(connection);
         Background { task }
     }
@@ -51,7 +51,9 @@ where T: AsyncRead + AsyncWrite,
         match self.task {
             // TODO: Log error?
             Connection(ref mut f) => f.poll().map_err(|_| ()),
-            Flush(ref mut f) => f.poll(),
+            Flush(ref mut f) => f.poll().map_err(|e| {
+                debug!("error flushing request body: {:?}", e);
+            }),
         }
     }
 }
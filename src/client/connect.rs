@@ -1,7 +1,7 @@
 use {Body, RecvBody};
 use super::{Connection, Background, Handshake, HandshakeError};
 
-use futures::{Future, Poll};
+use futures::{Future, Poll, Async};
 use futures::future::Executor;
 use h2;
 use http::{Request, Response};
@@ -15,6 +15,11 @@ use std::marker::PhantomData;
 ///
 /// Has a builder-like API for configuring client connections.  Currently this only allows
 /// the configuration of TLS transport on new services created by this factory.
+///
+/// HTTP/2.0 is started immediately on new connections, assuming the server
+/// supports it (prior knowledge). Upgrading from HTTP/1.1 with `Upgrade:
+/// h2c` is not supported: the response to the upgrade request is received
+/// on stream 1, which h2 does not allow a client to reserve.
 pub struct Connect<C, E, S> {
     /// Establish new session layer values (usually TCP sockets w/ TLS).
     inner: C,
@@ -90,6 +95,19 @@ where
             _p: PhantomData,
         }
     }
+
+    /// Establish a new session layer connection and perform the HTTP/2.0
+    /// handshake over it.
+    fn connect(&self) -> ConnectFuture<C, E, S> {
+        let state = State::Connect(self.inner.connect());
+        let builder = self.builder.clone();
+
+        ConnectFuture {
+            state,
+            builder,
+            executor: Some(self.executor.clone()),
+        }
+    }
 }
 
 impl<C, E, S> ::tower::NewService for Connect<C, E, S>
@@ -107,14 +125,29 @@ where
 
     /// Obtains a Connection on a single plaintext h2 connection to a remote.
     fn new_service(&self) -> Self::Future {
-        let state = State::Connect(self.inner.connect());
-        let builder = self.builder.clone();
+        self.connect()
+    }
+}
 
-        ConnectFuture {
-            state,
-            builder,
-            executor: Some(self.executor.clone()),
-        }
+/// `Connect` may also be used as a `Service` whose requests are `()` and whose
+/// responses are the established client `Connection`s.
+impl<C, E, S> ::tower::Service for Connect<C, E, S>
+where
+    C: tokio_connect::Connect + 'static,
+    E: Executor<Background<C::Connected, S>> + Clone,
+    S: Body + 'static,
+{
+    type Request = ();
+    type Response = Connection<C::Connected, E, S>;
+    type Error = ConnectError<C::Error>;
+    type Future = ConnectFuture<C, E, S>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, _: ()) -> Self::Future {
+        self.connect()
     }
 }
 
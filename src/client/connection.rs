@@ -1,4 +1,5 @@
-use {Body, RecvBody};
+use {content_length, Body, RecvBody};
+use body::BodyBuf;
 use super::Background;
 use flush::Flush;
 
@@ -18,7 +19,7 @@ use std::marker::PhantomData;
 pub struct Connection<T, E, S>
 where S: Body,
 {
-    client: SendRequest<S::Data>,
+    client: SendRequest<BodyBuf<S>>,
     executor: E,
     _p: PhantomData<(T, S)>,
 }
@@ -27,7 +28,7 @@ where S: Body,
 pub struct Handshake<T, E, S>
 where S: Body,
 {
-    inner: h2::client::Handshake<T, S::Data>,
+    inner: h2::client::Handshake<T, BodyBuf<S>>,
     executor: E,
 }
 
@@ -68,6 +69,8 @@ pub enum HandshakeError {
 #[derive(Debug)]
 enum Kind {
     Inner(h2::Error),
+    /// The request could not be sent, so the server never saw it.
+    NotSent(h2::Error),
     Spawn,
 }
 
@@ -80,7 +83,7 @@ where S: Body,
       T: AsyncRead + AsyncWrite,
 {
     /// Builds Connection on an H2 client connection.
-    pub fn new(client: SendRequest<S::Data>, executor: E) -> Self {
+    pub fn new(client: SendRequest<BodyBuf<S>>, executor: E) -> Self {
         let _p = PhantomData;
 
         Connection {
@@ -130,11 +133,14 @@ where S: Body + 'static,
 
         // Split the request from the body
         let (parts, body) = request.into_parts();
+        let content_length = content_length::parse(&parts.headers);
         let request = http::Request::from_parts(parts, ());
 
         // If there is no body, then there is no point spawning a task to flush
-        // it.
-        let end_of_stream = body.is_end_stream();
+        // it, unless a non-zero length was declared, in which case the flush
+        // resets the stream.
+        let end_of_stream = body.is_end_stream() &&
+            content_length.map_or(true, |len| len == 0);
 
         // Initiate the H2 request
         let res = self.client.send_request(request, end_of_stream);
@@ -142,14 +148,17 @@ where S: Body + 'static,
         let (response, send_body) = match res {
             Ok(success) => success,
             Err(e) => {
-                let e = Error { kind: Kind::Inner(e) };
+                let e = Error { kind: Kind::NotSent(e) };
                 let inner = Inner::Error(Some(e));
                 return ResponseFuture { inner };
             }
         };
 
         if !end_of_stream {
-            let flush = Flush::new(body, send_body);
+            let mut flush = Flush::new(body, send_body);
+            if let Some(len) = content_length {
+                flush.set_content_length(len);
+            }
             let res = self.executor.execute(Background::flush(flush));
 
             if let Err(_) = res {
@@ -177,7 +186,11 @@ impl Future for ResponseFuture {
                 let response = try_ready!(fut.poll());
 
                 let (parts, body) = response.into_parts();
-                let body = RecvBody::new(body);
+                let mut body = RecvBody::new(body);
+
+                if let Some(len) = content_length::parse(&parts.headers) {
+                    body.set_declared_length(len);
+                }
 
                 Ok(Response::from_parts(parts, body).into())
             }
@@ -233,10 +246,29 @@ where T: AsyncRead + AsyncWrite,
 impl Error {
     pub fn reason(&self) -> Option<h2::Reason> {
         match self.kind {
-            Kind::Inner(ref h2) => h2.reason(),
+            Kind::Inner(ref h2) | Kind::NotSent(ref h2) => h2.reason(),
             _ => None,
         }
     }
+
+    /// Returns `true` if the server did not process the request, so it may
+    /// safely be sent again, on a new connection.
+    ///
+    /// This is the case if the request could not be sent at all, or if the
+    /// server refused the stream with `REFUSED_STREAM`.
+    ///
+    /// Streams failed with `NO_ERROR` are not retryable. A server may reset a
+    /// stream with `NO_ERROR` after it has fully responded, for example to
+    /// stop receiving the request body. h2 also fails the streams above the
+    /// last stream ID of a graceful GOAWAY with `NO_ERROR`, but it does not
+    /// expose that stream ID, so the two cannot be told apart.
+    pub fn is_retryable(&self) -> bool {
+        match self.kind {
+            Kind::NotSent(_) => true,
+            Kind::Inner(ref h2) => h2.reason() == Some(h2::Reason::REFUSED_STREAM),
+            Kind::Spawn => false,
+        }
+    }
 }
 
 impl From<h2::Error> for Error {
@@ -256,6 +288,8 @@ impl fmt::Display for Error {
         match self.kind {
             Kind::Inner(ref h2) => 
                 write!(f, "Error caused by underlying HTTP/2 error: {}", h2),
+            Kind::NotSent(ref h2) =>
+                write!(f, "Error sending request: {}", h2),
             Kind::Spawn => 
                 write!(f, "Error spawning background task"),
         }
@@ -264,16 +298,16 @@ impl fmt::Display for Error {
 
 impl error::Error for Error {
     fn cause(&self) -> Option<&error::Error> {
-        if let Kind::Inner(ref h2) = self.kind {
-            Some(h2)
-        } else {
-            None
+        match self.kind {
+            Kind::Inner(ref h2) | Kind::NotSent(ref h2) => Some(h2),
+            Kind::Spawn => None,
         }
     }
 
     fn description(&self) -> &str {
         match self.kind {
             Kind::Inner(ref h2) => h2.description(),
+            Kind::NotSent(_) => "error sending request",
             Kind::Spawn => "error spawning worker task"
         }
     }
@@ -323,3 +357,22 @@ impl error::Error for HandshakeError {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+
+    use h2;
+
+    #[test]
+    fn refused_streams_are_retryable() {
+        assert!(Error::from(h2::Reason::REFUSED_STREAM).is_retryable());
+    }
+
+    #[test]
+    fn reset_streams_are_not_retryable() {
+        assert!(!Error::from(h2::Reason::NO_ERROR).is_retryable());
+        assert!(!Error::from(h2::Reason::CANCEL).is_retryable());
+        assert!(!Error::from(h2::Reason::INTERNAL_ERROR).is_retryable());
+    }
+}
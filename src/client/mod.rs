@@ -1,7 +1,13 @@
+mod addrs;
 mod background;
 mod connect;
 mod connection;
+pub mod pool;
+pub mod reconnect;
 
+pub use self::addrs::{ConnectAddrs, ConnectAddrsFuture};
 pub use self::background::Background;
 pub use self::connect::{Connect, ConnectFuture, ConnectError};
 pub use self::connection::{Connection, Handshake, ResponseFuture, Error, HandshakeError};
+pub use self::pool::SingleOriginPool;
+pub use self::reconnect::Reconnect;
@@ -0,0 +1,277 @@
+//! Multiplexes requests over a bounded set of client connections.
+
+use futures::{Async, Future, Poll};
+use futures::task::AtomicTask;
+use tower::{NewService, Service};
+
+use std::{error, fmt};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The default value of `SingleOriginPool::max_concurrent_streams`.
+///
+/// This is the minimum limit RFC 7540 recommends a peer to advertise.
+pub const DEFAULT_MAX_CONCURRENT_STREAMS: usize = 100;
+
+/// A pool of HTTP/2.0 connections to the single origin that `new_service`
+/// connects to.
+///
+/// The pool does not look at the URIs of its requests: every request is
+/// sent to a connection obtained from the same `NewService`, such as
+/// `client::Connect`. A pool is needed for each origin.
+///
+/// Because HTTP/2.0 multiplexes many streams over a single connection,
+/// requests are dispatched to any established connection that has fewer
+/// than `max_concurrent_streams` requests in flight and is ready to open a
+/// new stream. A new connection is only established once every existing
+/// connection is saturated, and only while there are fewer than
+/// `max_connections` connections.
+pub struct SingleOriginPool<N>
+where N: NewService,
+{
+    new_service: N,
+    max_connections: usize,
+    max_concurrent_streams: usize,
+    connections: Vec<Pooled<N::Service>>,
+    connecting: Vec<N::Future>,
+    ready: Option<usize>,
+    task: Arc<AtomicTask>,
+}
+
+/// The response future returned by a `SingleOriginPool`.
+pub struct ResponseFuture<F, C> {
+    inner: F,
+    _stream: Stream,
+    _p: ::std::marker::PhantomData<C>,
+}
+
+/// An established connection, with the number of its requests in flight.
+struct Pooled<S> {
+    service: S,
+    streams: Arc<AtomicUsize>,
+}
+
+/// Counts a request as in flight on its connection until it is dropped.
+struct Stream {
+    streams: Arc<AtomicUsize>,
+    task: Arc<AtomicTask>,
+}
+
+/// Errors produced by a `SingleOriginPool`.
+#[derive(Debug)]
+pub enum Error<S, C> {
+    /// A connection failed to process a request.
+    Service(S),
+
+    /// A new connection could not be established.
+    Connect(C),
+}
+
+// ===== impl SingleOriginPool =====
+
+impl<N> SingleOriginPool<N>
+where N: NewService,
+{
+    /// Create a new `SingleOriginPool` that establishes at most
+    /// `max_connections` connections with `new_service`.
+    pub fn new(new_service: N, max_connections: usize) -> Self {
+        assert!(max_connections > 0, "max_connections must be at least 1");
+
+        SingleOriginPool {
+            new_service,
+            max_connections,
+            max_concurrent_streams: DEFAULT_MAX_CONCURRENT_STREAMS,
+            connections: Vec::new(),
+            connecting: Vec::new(),
+            ready: None,
+            task: Arc::new(AtomicTask::new()),
+        }
+    }
+
+    /// Set the number of requests each connection may have in flight before
+    /// the pool dispatches to, or establishes, another connection.
+    ///
+    /// This should not exceed the `SETTINGS_MAX_CONCURRENT_STREAMS` limit of
+    /// the origin. h2 does not expose the limit advertised by the peer, so
+    /// it cannot be learned from the connection and must be configured.
+    ///
+    /// A request is counted from the time it is dispatched until its
+    /// response future completes or is dropped. The stream may remain open
+    /// while the response body is received.
+    ///
+    /// The default is `DEFAULT_MAX_CONCURRENT_STREAMS`.
+    pub fn max_concurrent_streams(&mut self, max: usize) -> &mut Self {
+        assert!(max > 0, "max_concurrent_streams must be at least 1");
+        self.max_concurrent_streams = max;
+        self
+    }
+
+    /// Returns the number of established connections.
+    pub fn connections(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Moves connections that have been established into the pool.
+    fn poll_connecting(&mut self) -> Result<(), N::InitError> {
+        let mut i = 0;
+
+        while i < self.connecting.len() {
+            match self.connecting[i].poll() {
+                Ok(Async::Ready(connection)) => {
+                    trace!("connection established");
+                    self.connecting.swap_remove(i);
+                    self.connections.push(Pooled {
+                        service: connection,
+                        streams: Arc::new(AtomicUsize::new(0)),
+                    });
+                }
+                Ok(Async::NotReady) => i += 1,
+                Err(e) => {
+                    self.connecting.swap_remove(i);
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the index of a connection that can accept a new stream,
+    /// discarding connections that have failed.
+    ///
+    /// The current task is notified when a request completes, so that
+    /// connections at `max_concurrent_streams` are checked again.
+    fn poll_ready_connection(&mut self) -> Option<usize> {
+        // Register before checking the counts so that a request completing
+        // concurrently is not missed.
+        self.task.register();
+
+        let mut i = 0;
+
+        while i < self.connections.len() {
+            if self.connections[i].streams.load(Ordering::Acquire) >= self.max_concurrent_streams {
+                i += 1;
+                continue;
+            }
+
+            match self.connections[i].service.poll_ready() {
+                Ok(Async::Ready(())) => return Some(i),
+                Ok(Async::NotReady) => i += 1,
+                Err(_) => {
+                    debug!("discarding failed connection");
+                    self.connections.swap_remove(i);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<N> Service for SingleOriginPool<N>
+where N: NewService,
+{
+    type Request = N::Request;
+    type Response = N::Response;
+    type Error = Error<N::Error, N::InitError>;
+    type Future = ResponseFuture<<N::Service as Service>::Future, N::InitError>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        if self.ready.is_some() {
+            return Ok(Async::Ready(()));
+        }
+
+        loop {
+            self.poll_connecting().map_err(Error::Connect)?;
+
+            if let Some(i) = self.poll_ready_connection() {
+                self.ready = Some(i);
+                return Ok(Async::Ready(()));
+            }
+
+            // Every connection is saturated. Establish at most one new
+            // connection at a time, up to the limit.
+            let total = self.connections.len() + self.connecting.len();
+            if !self.connecting.is_empty() || total >= self.max_connections {
+                return Ok(Async::NotReady);
+            }
+
+            debug!("all connections saturated; connecting; total={}", total);
+            self.connecting.push(self.new_service.new_service());
+        }
+    }
+
+    fn call(&mut self, request: Self::Request) -> Self::Future {
+        let i = self.ready.take()
+            .expect("poll_ready must be called before call");
+
+        let connection = &mut self.connections[i];
+        connection.streams.fetch_add(1, Ordering::AcqRel);
+
+        let stream = Stream {
+            streams: connection.streams.clone(),
+            task: self.task.clone(),
+        };
+
+        ResponseFuture {
+            inner: connection.service.call(request),
+            _stream: stream,
+            _p: ::std::marker::PhantomData,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, C> Future for ResponseFuture<F, C>
+where F: Future,
+{
+    type Item = F::Item;
+    type Error = Error<F::Error, C>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.inner.poll().map_err(Error::Service)
+    }
+}
+
+// ===== impl Stream =====
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.streams.fetch_sub(1, Ordering::AcqRel);
+        self.task.notify();
+    }
+}
+
+// ===== impl Error =====
+
+impl<S, C> fmt::Display for Error<S, C>
+where S: fmt::Display,
+      C: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Service(ref why) => fmt::Display::fmt(why, f),
+            Error::Connect(ref why) => fmt::Display::fmt(why, f),
+        }
+    }
+}
+
+impl<S, C> error::Error for Error<S, C>
+where S: error::Error,
+      C: error::Error,
+{
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Service(ref why) => Some(why),
+            Error::Connect(ref why) => Some(why),
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::Service(_) => "error returned by pooled connection",
+            Error::Connect(_) => "error establishing pooled connection",
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! Re-establishes a client connection after it fails.
+
+use futures::{Async, Future, Poll};
+use tower::{NewService, Service};
+
+use std::{error, fmt};
+
+/// Re-establishes a connection from a `NewService` whenever it fails.
+///
+/// When the connection fails, for instance because the peer sent a GOAWAY or
+/// the transport was closed, the next call to `poll_ready` transparently
+/// establishes a new connection. The `Policy` determines how requests are
+/// treated while that happens.
+pub struct Reconnect<N>
+where N: NewService,
+{
+    new_service: N,
+    policy: Policy,
+    state: State<N>,
+}
+
+/// Determines how `Reconnect` treats requests while it is not connected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// `poll_ready` is not ready until a connection has been established, so
+    /// callers hold on to their requests until they can be sent.
+    Wait,
+
+    /// `poll_ready` is always ready while (re)connecting, and requests made
+    /// before a connection has been established fail with
+    /// `Error::NotConnected`.
+    FailFast,
+}
+
+/// The response future returned by `Reconnect`.
+pub struct ResponseFuture<F, C> {
+    inner: Option<F>,
+    _p: ::std::marker::PhantomData<C>,
+}
+
+/// Errors produced by `Reconnect`.
+#[derive(Debug)]
+pub enum Error<S, C> {
+    /// The connection failed to process a request.
+    Service(S),
+
+    /// The connection could not be established.
+    Connect(C),
+
+    /// The request was made while no connection was established.
+    NotConnected,
+}
+
+enum State<N>
+where N: NewService,
+{
+    Idle,
+    Connecting(N::Future),
+    Connected(N::Service),
+}
+
+// ===== impl Reconnect =====
+
+impl<N> Reconnect<N>
+where N: NewService,
+{
+    /// Create a new `Reconnect` that obtains connections with `new_service`.
+    pub fn new(new_service: N, policy: Policy) -> Self {
+        Reconnect {
+            new_service,
+            policy,
+            state: State::Idle,
+        }
+    }
+
+    /// Returns `true` if a connection is currently established.
+    pub fn is_connected(&self) -> bool {
+        match self.state {
+            State::Connected(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<N> Service for Reconnect<N>
+where N: NewService,
+{
+    type Request = N::Request;
+    type Response = N::Response;
+    type Error = Error<N::Error, N::InitError>;
+    type Future = ResponseFuture<<N::Service as Service>::Future, N::InitError>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        loop {
+            let next = match self.state {
+                State::Idle => {
+                    trace!("connecting");
+                    State::Connecting(self.new_service.new_service())
+                }
+                State::Connecting(ref mut fut) => match fut.poll() {
+                    Ok(Async::Ready(service)) => {
+                        trace!("connected");
+                        State::Connected(service)
+                    }
+                    Ok(Async::NotReady) => {
+                        return match self.policy {
+                            Policy::Wait => Ok(Async::NotReady),
+                            Policy::FailFast => Ok(Async::Ready(())),
+                        };
+                    }
+                    Err(e) => {
+                        debug!("failed to connect");
+                        self.state = State::Idle;
+                        return Err(Error::Connect(e));
+                    }
+                },
+                State::Connected(ref mut service) => match service.poll_ready() {
+                    Ok(ready) => return Ok(ready),
+                    Err(_) => {
+                        debug!("connection failed; reconnecting");
+                        State::Idle
+                    }
+                },
+            };
+
+            self.state = next;
+        }
+    }
+
+    fn call(&mut self, request: Self::Request) -> Self::Future {
+        let inner = match self.state {
+            State::Connected(ref mut service) => Some(service.call(request)),
+            _ => None,
+        };
+
+        ResponseFuture {
+            inner,
+            _p: ::std::marker::PhantomData,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, C> Future for ResponseFuture<F, C>
+where F: Future,
+{
+    type Item = F::Item;
+    type Error = Error<F::Error, C>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.inner {
+            Some(ref mut inner) => inner.poll().map_err(Error::Service),
+            None => Err(Error::NotConnected),
+        }
+    }
+}
+
+// ===== impl Error =====
+
+impl<S, C> fmt::Display for Error<S, C>
+where S: fmt::Display,
+      C: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Service(ref why) => fmt::Display::fmt(why, f),
+            Error::Connect(ref why) => fmt::Display::fmt(why, f),
+            Error::NotConnected =>
+                write!(f, "Request made while not connected"),
+        }
+    }
+}
+
+impl<S, C> error::Error for Error<S, C>
+where S: error::Error,
+      C: error::Error,
+{
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Service(ref why) => Some(why),
+            Error::Connect(ref why) => Some(why),
+            Error::NotConnected => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::Service(_) => "error returned by connection",
+            Error::Connect(_) => "error establishing connection",
+            Error::NotConnected => "request made while not connected",
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use {RecvBody, RecvError};
+use pool;
+
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{Async, Future, Poll};
+
+use std::{cmp, error, fmt};
+
+/// Buffers an entire `RecvBody` into a single `Bytes`.
+///
+/// Created by `RecvBody::collect`.
+#[derive(Debug)]
+pub struct Collect {
+    body: RecvBody,
+    buf: BytesMut,
+    max: usize,
+}
+
+/// Error produced by `Collect`.
+#[derive(Debug)]
+pub enum CollectError {
+    /// The body was larger than the configured maximum size.
+    BodyTooLarge,
+
+    /// An error occurred while receiving the body.
+    Recv(RecvError),
+}
+
+// ===== impl Collect =====
+
+impl Collect {
+    pub(crate) fn new(body: RecvBody, max: usize) -> Self {
+        // Size the buffer for the declared length, if the body has one.
+        let capacity = body.content_length()
+            .map(|len| cmp::min(len, max as u64) as usize)
+            .unwrap_or(0);
+
+        let buf = pool::get(body.buffer_pool(), capacity);
+
+        Collect {
+            body,
+            buf,
+            max,
+        }
+    }
+}
+
+impl Future for Collect {
+    type Item = Bytes;
+    type Error = CollectError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let data = match try_ready!(self.body.poll_chunk().map_err(RecvError::from)) {
+                Some(data) => data,
+                None => return Ok(Async::Ready(self.buf.take().freeze())),
+            };
+
+            if self.buf.len() + data.remaining() > self.max {
+                debug!("body exceeds maximum size; max={}", self.max);
+                return Err(CollectError::BodyTooLarge);
+            }
+
+            // Dropping `data` releases its flow control capacity.
+            self.buf.extend_from_slice(data.bytes());
+        }
+    }
+}
+
+// ===== impl CollectError =====
+
+impl From<RecvError> for CollectError {
+    fn from(src: RecvError) -> Self {
+        CollectError::Recv(src)
+    }
+}
+
+impl fmt::Display for CollectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CollectError::BodyTooLarge =>
+                write!(f, "Body exceeded the maximum size"),
+            CollectError::Recv(ref why) =>
+                write!(f, "{}", why),
+        }
+    }
+}
+
+impl error::Error for CollectError {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            CollectError::BodyTooLarge => None,
+            CollectError::Recv(ref why) => Some(why),
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            CollectError::BodyTooLarge => "body exceeded the maximum size",
+            CollectError::Recv(_) => "error receiving body",
+        }
+    }
+}
@@ -0,0 +1,576 @@
+//! Compression of response bodies.
+//!
+//! h2 does not compress DATA frames, so `Compress` wraps a service to compress
+//! the bodies of its responses with an encoding negotiated from the request's
+//! `accept-encoding` header. `gzip` and `deflate` are supported. `br` is not,
+//! as there is no Brotli encoder among the crate's dependencies, so clients
+//! that only accept `br` receive uncompressed responses.
+//! `server::Builder::build_compressed` builds a server whose services are all
+//! wrapped this way.
+//!
+//! Responses that already have a `content-encoding`, responses to `HEAD`
+//! requests, and responses whose body is empty or that never have a body are
+//! sent as they are.
+
+use {Body, RecvBody};
+
+use bytes::{Buf, Bytes, IntoBuf};
+use flate2::Compression;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use futures::{Async, Future, Poll};
+use h2::{self, Reason};
+use http::{HeaderMap, Method, Request, Response, StatusCode};
+use http::header::{self, HeaderValue};
+use tower::{NewService, Service};
+
+use std::{fmt, io, mem};
+use std::io::Write;
+
+/// A content coding supported by `Compress`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+/// A response body compressed with an `Encoding`.
+///
+/// Whenever the wrapped body is not ready, the data compressed so far is
+/// flushed, so that streaming responses are not delayed.
+pub struct CompressBody<B> {
+    inner: B,
+    encoder: Option<Encoder>,
+    identity: bool,
+    pending: bool,
+}
+
+/// A chunk of a `CompressBody`.
+#[derive(Debug)]
+pub struct CompressData<T> {
+    inner: DataInner<T>,
+}
+
+/// Wraps a service so that its response bodies are compressed.
+#[derive(Clone, Debug)]
+pub struct Compress<S> {
+    inner: S,
+}
+
+/// Produces a `Compress` for each service produced by the inner `NewService`.
+#[derive(Clone, Debug)]
+pub struct NewCompress<N> {
+    inner: N,
+}
+
+/// Completes with the `Compress` produced by a `NewCompress`.
+pub struct NewServiceFuture<F> {
+    inner: F,
+}
+
+/// The response future of a `Compress`.
+pub struct ResponseFuture<F> {
+    inner: F,
+
+    /// The negotiated encoding, unless the response is never compressed.
+    encoding: Option<Encoding>,
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+}
+
+#[derive(Debug)]
+enum DataInner<T> {
+    Identity(T),
+    Compressed(io::Cursor<Bytes>),
+}
+
+// ===== impl Encoding =====
+
+impl Encoding {
+    /// Returns the preferred supported encoding listed by an
+    /// `accept-encoding` header, if any.
+    ///
+    /// A coding listed with `q=0` is never chosen, even if `*` is listed
+    /// too. When codings have the same quality, a coding listed explicitly is
+    /// preferred over one only matched by `*`, and `gzip` over `deflate`.
+    pub fn from_accept_encoding(headers: &HeaderMap) -> Option<Encoding> {
+        // The quality given to `gzip`, `deflate` and `*`, if listed.
+        let mut gzip = None;
+        let mut deflate = None;
+        let mut any = None;
+
+        for value in headers.get_all(header::ACCEPT_ENCODING) {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+
+            for coding in value.split(',') {
+                let mut parts = coding.split(';');
+                let name = parts.next().unwrap_or("").trim();
+
+                let q = parts
+                    .filter_map(|param| {
+                        let param = param.trim();
+                        if param.starts_with("q=") {
+                            param[2..].parse::<f32>().ok()
+                        } else {
+                            None
+                        }
+                    })
+                    .next()
+                    .unwrap_or(1.0);
+
+                let quality = if name.eq_ignore_ascii_case("gzip") ||
+                    name.eq_ignore_ascii_case("x-gzip")
+                {
+                    &mut gzip
+                } else if name.eq_ignore_ascii_case("deflate") {
+                    &mut deflate
+                } else if name == "*" {
+                    &mut any
+                } else {
+                    continue;
+                };
+
+                *quality = Some(q);
+            }
+        }
+
+        // Qualities are compared with explicit codings ranked above `*`.
+        let rank = |quality: Option<f32>| match (quality, any) {
+            (Some(q), _) if q > 0.0 => Some((q, 1)),
+            (None, Some(q)) if q > 0.0 => Some((q, 0)),
+            _ => None,
+        };
+
+        let mut best: Option<(Encoding, (f32, u8))> = None;
+
+        for &(encoding, quality) in &[(Encoding::Gzip, gzip), (Encoding::Deflate, deflate)] {
+            let rank = match rank(quality) {
+                Some(rank) => rank,
+                None => continue,
+            };
+
+            let better = match best {
+                Some((_, best_rank)) => rank > best_rank,
+                None => true,
+            };
+
+            if better {
+                best = Some((encoding, rank));
+            }
+        }
+
+        best.map(|(encoding, _)| encoding)
+    }
+
+    /// Returns the supported encoding named by a `content-encoding` header,
+    /// if any.
+    ///
+    /// Returns `None` if the header is missing, is `identity`, names an
+    /// unsupported encoding, or lists more than one encoding.
+    pub fn from_content_encoding(headers: &HeaderMap) -> Option<Encoding> {
+        let mut values = headers.get_all(header::CONTENT_ENCODING).iter();
+
+        let value = match values.next() {
+            Some(value) => value,
+            None => return None,
+        };
+
+        if values.next().is_some() {
+            return None;
+        }
+
+        match value.to_str().map(|value| value.trim()) {
+            Ok("gzip") | Ok("x-gzip") => Some(Encoding::Gzip),
+            Ok("deflate") => Some(Encoding::Deflate),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the encoding, as used in `content-encoding`.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+// ===== impl CompressBody =====
+
+impl<B> CompressBody<B> {
+    /// Compress `inner` with `encoding`.
+    pub fn new(inner: B, encoding: Encoding) -> Self {
+        let encoder = match encoding {
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(Vec::new(), Compression::default())),
+            Encoding::Deflate => {
+                Encoder::Deflate(ZlibEncoder::new(Vec::new(), Compression::default()))
+            }
+        };
+
+        CompressBody {
+            inner,
+            encoder: Some(encoder),
+            identity: false,
+            pending: false,
+        }
+    }
+
+    /// Send `inner` without compressing it.
+    pub fn identity(inner: B) -> Self {
+        CompressBody {
+            inner,
+            encoder: None,
+            identity: true,
+            pending: false,
+        }
+    }
+
+    /// Returns the compressed output produced so far, flushing the encoder
+    /// first if `flush` is set.
+    fn take(&mut self, flush: bool) -> Result<Vec<u8>, h2::Error> {
+        let encoder = self.encoder.as_mut().expect("encoder taken");
+
+        if flush {
+            encoder.flush().map_err(encode_error)?;
+        }
+
+        Ok(encoder.take())
+    }
+}
+
+impl<B: Body> Body for CompressBody<B> {
+    type Data = CompressData<<B::Data as IntoBuf>::Buf>;
+
+    fn is_end_stream(&self) -> bool {
+        (self.identity || self.encoder.is_none()) && self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        if self.identity {
+            let data = try_ready!(self.inner.poll_data());
+            return Ok(Async::Ready(data.map(|data| CompressData {
+                inner: DataInner::Identity(data.into_buf()),
+            })));
+        }
+
+        loop {
+            if self.encoder.is_none() {
+                return Ok(Async::Ready(None));
+            }
+
+            let res = self.inner.poll_data()?;
+
+            match res {
+                Async::Ready(Some(data)) => {
+                    let mut buf = data.into_buf();
+
+                    {
+                        let encoder = self.encoder.as_mut().expect("encoder taken");
+
+                        while buf.has_remaining() {
+                            let n = {
+                                let bytes = buf.bytes();
+                                encoder.write_all(bytes).map_err(encode_error)?;
+                                bytes.len()
+                            };
+                            buf.advance(n);
+                        }
+                    }
+
+                    self.pending = true;
+
+                    if let Some(data) = compressed(self.take(false)?) {
+                        return Ok(Async::Ready(Some(data)));
+                    }
+                }
+                Async::Ready(None) => {
+                    let encoder = self.encoder.take().expect("encoder taken");
+                    let out = encoder.finish().map_err(encode_error)?;
+                    return Ok(Async::Ready(compressed(out)));
+                }
+                Async::NotReady => {
+                    if self.pending {
+                        self.pending = false;
+
+                        if let Some(data) = compressed(self.take(true)?) {
+                            return Ok(Async::Ready(Some(data)));
+                        }
+                    }
+
+                    return Ok(Async::NotReady);
+                }
+            }
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, h2::Error> {
+        self.inner.poll_trailers()
+    }
+}
+
+impl<B> fmt::Debug for CompressBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CompressBody")
+            .field("identity", &self.identity)
+            .finish()
+    }
+}
+
+fn compressed<T>(out: Vec<u8>) -> Option<CompressData<T>> {
+    if out.is_empty() {
+        return None;
+    }
+
+    Some(CompressData {
+        inner: DataInner::Compressed(io::Cursor::new(Bytes::from(out))),
+    })
+}
+
+fn encode_error(e: io::Error) -> h2::Error {
+    warn!("error compressing body: {}", e);
+    Reason::INTERNAL_ERROR.into()
+}
+
+// ===== impl CompressData =====
+
+impl<T: Buf> Buf for CompressData<T> {
+    fn remaining(&self) -> usize {
+        match self.inner {
+            DataInner::Identity(ref buf) => buf.remaining(),
+            DataInner::Compressed(ref buf) => buf.remaining(),
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self.inner {
+            DataInner::Identity(ref buf) => buf.bytes(),
+            DataInner::Compressed(ref buf) => buf.bytes(),
+        }
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        match self.inner {
+            DataInner::Identity(ref mut buf) => buf.advance(cnt),
+            DataInner::Compressed(ref mut buf) => buf.advance(cnt),
+        }
+    }
+}
+
+// ===== impl Encoder =====
+
+impl Encoder {
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match *self {
+            Encoder::Gzip(ref mut encoder) => encoder.write_all(buf),
+            Encoder::Deflate(ref mut encoder) => encoder.write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Encoder::Gzip(ref mut encoder) => encoder.flush(),
+            Encoder::Deflate(ref mut encoder) => encoder.flush(),
+        }
+    }
+
+    /// Takes the output produced so far.
+    fn take(&mut self) -> Vec<u8> {
+        match *self {
+            Encoder::Gzip(ref mut encoder) => mem::replace(encoder.get_mut(), Vec::new()),
+            Encoder::Deflate(ref mut encoder) => mem::replace(encoder.get_mut(), Vec::new()),
+        }
+    }
+
+    /// Writes the end of the compressed stream, returning the remaining
+    /// output.
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Deflate(encoder) => encoder.finish(),
+        }
+    }
+}
+
+// ===== impl Compress =====
+
+impl<S> Compress<S> {
+    /// Wraps `inner`.
+    pub fn new(inner: S) -> Self {
+        Compress { inner }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S, B> Service for Compress<S>
+where S: Service<Request = Request<RecvBody>, Response = Response<B>>,
+      B: Body,
+{
+    type Request = Request<RecvBody>;
+    type Response = Response<CompressBody<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, request: Self::Request) -> Self::Future {
+        // The response to a `HEAD` request has no body to compress, but its
+        // headers must match those of a `GET`, so they are left as they are.
+        let encoding = if request.method() == Method::HEAD {
+            None
+        } else {
+            Encoding::from_accept_encoding(request.headers())
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(request),
+            encoding,
+        }
+    }
+}
+
+// ===== impl NewCompress =====
+
+impl<N> NewCompress<N> {
+    /// Wraps each service produced by `inner` in a `Compress`.
+    pub fn new(inner: N) -> Self {
+        NewCompress { inner }
+    }
+}
+
+impl<N, B> NewService for NewCompress<N>
+where N: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      B: Body,
+{
+    type Request = Request<RecvBody>;
+    type Response = Response<CompressBody<B>>;
+    type Error = N::Error;
+    type Service = Compress<N::Service>;
+    type InitError = N::InitError;
+    type Future = NewServiceFuture<N::Future>;
+
+    fn new_service(&self) -> Self::Future {
+        NewServiceFuture { inner: self.inner.new_service() }
+    }
+}
+
+// ===== impl NewServiceFuture =====
+
+impl<F> Future for NewServiceFuture<F>
+where F: Future,
+{
+    type Item = Compress<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        Ok(Async::Ready(Compress::new(inner)))
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where F: Future<Item = Response<B>>,
+      B: Body,
+{
+    type Item = Response<CompressBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let (mut parts, body) = try_ready!(self.inner.poll()).into_parts();
+
+        let body = match self.encoding {
+            Some(encoding) if should_compress(parts.status, &parts.headers, &body) => {
+                trace!("compressing response body; encoding={}", encoding.as_str());
+
+                parts.headers.insert(
+                    header::CONTENT_ENCODING,
+                    HeaderValue::from_static(encoding.as_str()));
+                parts.headers.remove(header::CONTENT_LENGTH);
+                parts.headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+
+                CompressBody::new(body, encoding)
+            }
+            _ => CompressBody::identity(body),
+        };
+
+        Ok(Async::Ready(Response::from_parts(parts, body)))
+    }
+}
+
+/// Returns `true` if a response may be compressed.
+fn should_compress<B: Body>(status: StatusCode, headers: &HeaderMap, body: &B) -> bool {
+    let has_body = !status.is_informational() &&
+        status != StatusCode::NO_CONTENT &&
+        status != StatusCode::NOT_MODIFIED;
+
+    // Compressing an empty body would make it larger.
+    let empty = body.is_end_stream() ||
+        headers.get(header::CONTENT_LENGTH).map_or(false, |len| len == "0");
+
+    has_body && !empty && !headers.contains_key(header::CONTENT_ENCODING)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Encoding;
+
+    use http::HeaderMap;
+    use http::header::{self, HeaderValue};
+
+    fn negotiate(accept: &[&'static str]) -> Option<Encoding> {
+        let mut headers = HeaderMap::new();
+        for value in accept {
+            headers.append(header::ACCEPT_ENCODING, HeaderValue::from_static(*value));
+        }
+        Encoding::from_accept_encoding(&headers)
+    }
+
+    #[test]
+    fn from_accept_encoding_none() {
+        assert_eq!(negotiate(&[]), None);
+        assert_eq!(negotiate(&["identity"]), None);
+        assert_eq!(negotiate(&["br"]), None);
+    }
+
+    #[test]
+    fn from_accept_encoding_quality() {
+        assert_eq!(negotiate(&["gzip"]), Some(Encoding::Gzip));
+        assert_eq!(negotiate(&["x-gzip"]), Some(Encoding::Gzip));
+        assert_eq!(negotiate(&["deflate"]), Some(Encoding::Deflate));
+        assert_eq!(negotiate(&["gzip;q=0.5, deflate"]), Some(Encoding::Deflate));
+        assert_eq!(negotiate(&["gzip;q=0.5", "deflate;q=0.8"]), Some(Encoding::Deflate));
+    }
+
+    #[test]
+    fn from_accept_encoding_ties_prefer_gzip() {
+        assert_eq!(negotiate(&["deflate, gzip"]), Some(Encoding::Gzip));
+        assert_eq!(negotiate(&["*"]), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn from_accept_encoding_q_zero() {
+        assert_eq!(negotiate(&["gzip;q=0"]), None);
+        assert_eq!(negotiate(&["gzip;q=0, *"]), Some(Encoding::Deflate));
+        assert_eq!(negotiate(&["gzip;q=0, deflate;q=0, *"]), None);
+        assert_eq!(negotiate(&["gzip, *;q=0"]), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn from_accept_encoding_prefers_explicit_codings() {
+        assert_eq!(negotiate(&["deflate, *"]), Some(Encoding::Deflate));
+        assert_eq!(negotiate(&["deflate;q=0.5, *;q=0.5"]), Some(Encoding::Deflate));
+        assert_eq!(negotiate(&["deflate;q=0.5, *"]), Some(Encoding::Gzip));
+    }
+}
@@ -0,0 +1,18 @@
+use http::HeaderMap;
+use http::header::CONTENT_LENGTH;
+
+/// Returns the length declared by the `content-length` header of `headers`.
+///
+/// Returns `None` if the header is missing, repeated, or malformed, in which
+/// case the length of the body is not validated.
+pub(crate) fn parse(headers: &HeaderMap) -> Option<u64> {
+    let mut values = headers.get_all(CONTENT_LENGTH).iter();
+
+    let value = values.next()?;
+
+    if values.next().is_some() {
+        return None;
+    }
+
+    value.to_str().ok()?.trim().parse().ok()
+}
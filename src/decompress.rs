@@ -0,0 +1,432 @@
+//! Decompression of request bodies.
+//!
+//! `Decompress` wraps a service so that request bodies sent with a
+//! `content-encoding` of `gzip` or `deflate` are inflated before the service
+//! reads them. The `content-encoding` and `content-length` headers of those
+//! requests are removed, since they describe the compressed body.
+//! `server::Builder::build_decompressed` builds a server whose services are
+//! all wrapped this way.
+//!
+//! If a body cannot be inflated, it fails and the stream is reset with
+//! `PROTOCOL_ERROR`. Requests with other encodings are passed to the service
+//! as they are.
+//!
+//! A small compressed body may inflate to a very large one, so the size of
+//! inflated bodies is bounded by `Limits`. The compressed data is fed to the
+//! decoder only as the inflated data is read, and a body that inflates past
+//! its limit fails and resets the stream with `CANCEL`, as a `LimitRecvBody`
+//! does.
+
+use {Body, RecvBody, RecvError};
+use compress::Encoding;
+
+use bytes::Bytes;
+use flate2::write::{GzDecoder, ZlibDecoder};
+use futures::{Async, Future, Poll, Stream};
+use h2::{self, Reason};
+use http::{HeaderMap, Request};
+use http::header;
+use tower::{NewService, Service};
+
+use std::{fmt, io, mem};
+use std::io::Write;
+
+/// The default maximum size of an inflated chunk.
+const DEFAULT_MAX_CHUNK: usize = 64 * 1024;
+
+/// The default maximum size of an inflated body.
+const DEFAULT_MAX_LEN: u64 = 8 * 1024 * 1024;
+
+/// A request body that is inflated as it is received.
+pub struct DecompressRecvBody {
+    inner: RecvBody,
+    decoder: Option<Decoder>,
+    identity: bool,
+
+    /// The reason the stream was reset with, once the body failed.
+    failed: Option<Reason>,
+
+    /// Compressed data received but not fed to the decoder yet.
+    pending: Bytes,
+
+    /// The number of inflated bytes yielded so far.
+    inflated: u64,
+
+    limits: Limits,
+}
+
+/// Limits on the size of inflated request bodies.
+///
+/// By default, inflated chunks are at most 64 KiB, and inflated bodies at
+/// most 8 MiB.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    max_chunk: usize,
+    max_len: u64,
+}
+
+/// Wraps a service so that its request bodies are decompressed.
+#[derive(Clone, Debug)]
+pub struct Decompress<S> {
+    inner: S,
+    limits: Limits,
+}
+
+/// Produces a `Decompress` for each service produced by the inner
+/// `NewService`.
+#[derive(Clone, Debug)]
+pub struct NewDecompress<N> {
+    inner: N,
+    limits: Limits,
+}
+
+/// Completes with the `Decompress` produced by a `NewDecompress`.
+pub struct NewServiceFuture<F> {
+    inner: F,
+    limits: Limits,
+}
+
+enum Decoder {
+    Gzip(GzDecoder<Vec<u8>>),
+    Deflate(ZlibDecoder<Vec<u8>>),
+}
+
+// ===== impl DecompressRecvBody =====
+
+impl DecompressRecvBody {
+    /// Inflate `inner`, which is compressed with `encoding`, within the
+    /// default `Limits`.
+    pub fn new(inner: RecvBody, encoding: Encoding) -> Self {
+        DecompressRecvBody::with_limits(inner, encoding, Limits::default())
+    }
+
+    /// Inflate `inner`, which is compressed with `encoding`, within `limits`.
+    pub fn with_limits(inner: RecvBody, encoding: Encoding, limits: Limits) -> Self {
+        let decoder = match encoding {
+            Encoding::Gzip => Decoder::Gzip(GzDecoder::new(Vec::new())),
+            Encoding::Deflate => Decoder::Deflate(ZlibDecoder::new(Vec::new())),
+        };
+
+        DecompressRecvBody {
+            inner,
+            decoder: Some(decoder),
+            identity: false,
+            failed: None,
+            pending: Bytes::new(),
+            inflated: 0,
+            limits,
+        }
+    }
+
+    /// Receive `inner` without inflating it.
+    pub fn identity(inner: RecvBody) -> Self {
+        DecompressRecvBody {
+            inner,
+            decoder: None,
+            identity: true,
+            failed: None,
+            pending: Bytes::new(),
+            inflated: 0,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Returns a reference to the wrapped body.
+    pub fn get_ref(&self) -> &RecvBody {
+        &self.inner
+    }
+
+    /// Fails the body and resets the stream after a decoding error.
+    fn malformed(&mut self, e: io::Error) -> h2::Error {
+        debug!("error decompressing request body; resetting stream: {}", e);
+        self.fail(Reason::PROTOCOL_ERROR)
+    }
+
+    /// Fails the body and resets the stream with `reason`.
+    fn fail(&mut self, reason: Reason) -> h2::Error {
+        self.failed = Some(reason);
+        self.decoder = None;
+        self.pending = Bytes::new();
+        self.inner.reset(reason);
+        reason.into()
+    }
+
+    /// Yields inflated data, unless the body inflated past its limit.
+    fn yield_data(&mut self, out: Vec<u8>) -> Poll<Option<Bytes>, h2::Error> {
+        self.inflated += out.len() as u64;
+
+        if self.inflated > self.limits.max_len {
+            debug!(
+                "inflated request body too long; resetting stream; max={}",
+                self.limits.max_len,
+            );
+            return Err(self.fail(Reason::CANCEL));
+        }
+
+        Ok(Async::Ready(Some(out.into())))
+    }
+}
+
+impl Body for DecompressRecvBody {
+    type Data = Bytes;
+
+    fn is_end_stream(&self) -> bool {
+        (self.identity || self.decoder.is_none()) && self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Bytes>, h2::Error> {
+        if let Some(reason) = self.failed {
+            return Err(reason.into());
+        }
+
+        if self.identity {
+            let data = try_ready!(self.inner.poll_data());
+            return Ok(Async::Ready(data.map(Bytes::from)));
+        }
+
+        loop {
+            if self.decoder.is_none() {
+                return Ok(Async::Ready(None));
+            }
+
+            if self.pending.is_empty() {
+                match try_ready!(self.inner.poll_data()) {
+                    Some(data) => self.pending = Bytes::from(data),
+                    None => {
+                        let decoder = self.decoder.take().expect("decoder taken");
+
+                        let out = match decoder.finish() {
+                            Ok(out) => out,
+                            Err(e) => return Err(self.malformed(e)),
+                        };
+
+                        if out.is_empty() {
+                            return Ok(Async::Ready(None));
+                        }
+
+                        return self.yield_data(out);
+                    }
+                }
+            }
+
+            let max_chunk = self.limits.max_chunk;
+            let res = self.decoder.as_mut().expect("decoder taken")
+                .feed(&mut self.pending, max_chunk);
+
+            if let Err(e) = res {
+                return Err(self.malformed(e));
+            }
+
+            let out = self.decoder.as_mut().expect("decoder taken").take();
+
+            if !out.is_empty() {
+                return self.yield_data(out);
+            }
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, h2::Error> {
+        if let Some(reason) = self.failed {
+            return Err(reason.into());
+        }
+
+        self.inner.poll_trailers()
+    }
+}
+
+/// Yields the inflated data chunks, like the `Stream` implementation of
+/// `RecvBody`.
+impl Stream for DecompressRecvBody {
+    type Item = Bytes;
+    type Error = RecvError;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, RecvError> {
+        if self.identity {
+            return self.inner.poll();
+        }
+
+        self.poll_data().map_err(RecvError::from)
+    }
+}
+
+impl fmt::Debug for DecompressRecvBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DecompressRecvBody")
+            .field("inner", &self.inner)
+            .field("identity", &self.identity)
+            .field("inflated", &self.inflated)
+            .field("limits", &self.limits)
+            .finish()
+    }
+}
+
+// ===== impl Limits =====
+
+impl Limits {
+    /// Limit inflated chunks to about `max_chunk` bytes, and inflated bodies
+    /// to `max_len` bytes.
+    ///
+    /// The decoder may produce a few more bytes than `max_chunk` before the
+    /// chunk is yielded. A body that inflates past `max_len` fails.
+    pub fn new(max_chunk: usize, max_len: u64) -> Self {
+        Limits {
+            max_chunk,
+            max_len,
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits::new(DEFAULT_MAX_CHUNK, DEFAULT_MAX_LEN)
+    }
+}
+
+// ===== impl Decoder =====
+
+impl Decoder {
+    /// Feeds `input` to the decoder until it is consumed or about `max`
+    /// bytes of output are buffered, removing the consumed bytes.
+    ///
+    /// Each write inflates a bounded amount of data, so a small input that
+    /// inflates to a large output is only inflated as the output is taken.
+    fn feed(&mut self, input: &mut Bytes, max: usize) -> io::Result<()> {
+        while !input.is_empty() && self.buffered() < max {
+            let n = match *self {
+                Decoder::Gzip(ref mut decoder) => decoder.write(&input[..])?,
+                Decoder::Deflate(ref mut decoder) => decoder.write(&input[..])?,
+            };
+
+            if n == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "data after end of compressed stream",
+                ));
+            }
+
+            input.split_to(n);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of bytes of output produced but not taken yet.
+    fn buffered(&self) -> usize {
+        match *self {
+            Decoder::Gzip(ref decoder) => decoder.get_ref().len(),
+            Decoder::Deflate(ref decoder) => decoder.get_ref().len(),
+        }
+    }
+
+    /// Takes the output produced so far.
+    fn take(&mut self) -> Vec<u8> {
+        match *self {
+            Decoder::Gzip(ref mut decoder) => mem::replace(decoder.get_mut(), Vec::new()),
+            Decoder::Deflate(ref mut decoder) => mem::replace(decoder.get_mut(), Vec::new()),
+        }
+    }
+
+    /// Checks that the compressed stream is complete, returning the remaining
+    /// output.
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Decoder::Gzip(decoder) => decoder.finish(),
+            Decoder::Deflate(decoder) => decoder.finish(),
+        }
+    }
+}
+
+// ===== impl Decompress =====
+
+impl<S> Decompress<S> {
+    /// Wraps `inner`, inflating request bodies within the default `Limits`.
+    pub fn new(inner: S) -> Self {
+        Decompress::with_limits(inner, Limits::default())
+    }
+
+    /// Wraps `inner`, inflating request bodies within `limits`.
+    pub fn with_limits(inner: S, limits: Limits) -> Self {
+        Decompress { inner, limits }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S> Service for Decompress<S>
+where S: Service<Request = Request<DecompressRecvBody>>,
+{
+    type Request = Request<RecvBody>;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, request: Self::Request) -> Self::Future {
+        let (mut parts, body) = request.into_parts();
+
+        let body = match Encoding::from_content_encoding(&parts.headers) {
+            Some(encoding) => {
+                trace!("decompressing request body; encoding={}", encoding.as_str());
+                parts.headers.remove(header::CONTENT_ENCODING);
+                parts.headers.remove(header::CONTENT_LENGTH);
+                DecompressRecvBody::with_limits(body, encoding, self.limits)
+            }
+            None => DecompressRecvBody::identity(body),
+        };
+
+        self.inner.call(Request::from_parts(parts, body))
+    }
+}
+
+// ===== impl NewDecompress =====
+
+impl<N> NewDecompress<N> {
+    /// Wraps each service produced by `inner` in a `Decompress`.
+    pub fn new(inner: N) -> Self {
+        NewDecompress::with_limits(inner, Limits::default())
+    }
+
+    /// Wraps each service produced by `inner` in a `Decompress` that
+    /// inflates request bodies within `limits`.
+    pub fn with_limits(inner: N, limits: Limits) -> Self {
+        NewDecompress { inner, limits }
+    }
+}
+
+impl<N> NewService for NewDecompress<N>
+where N: NewService<Request = Request<DecompressRecvBody>>,
+{
+    type Request = Request<RecvBody>;
+    type Response = N::Response;
+    type Error = N::Error;
+    type Service = Decompress<N::Service>;
+    type InitError = N::InitError;
+    type Future = NewServiceFuture<N::Future>;
+
+    fn new_service(&self) -> Self::Future {
+        NewServiceFuture {
+            inner: self.inner.new_service(),
+            limits: self.limits,
+        }
+    }
+}
+
+// ===== impl NewServiceFuture =====
+
+impl<F> Future for NewServiceFuture<F>
+where F: Future,
+{
+    type Item = Decompress<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        Ok(Async::Ready(Decompress::with_limits(inner, self.limits)))
+    }
+}
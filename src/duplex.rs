@@ -0,0 +1,147 @@
+use {BodySender, RecvBody, SendBody};
+
+use bytes::{Buf, Bytes};
+use futures::{Async, AsyncSink, Poll, Sink, Stream};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use std::{cmp, fmt, io};
+
+/// A bidirectional byte stream over a request body and a response body.
+///
+/// Reads yield the data of a request's `RecvBody`, and writes are sent as the
+/// data of a response's `SendBody`, so that tunneling and proxy services may
+/// use `tokio_io::io::copy` rather than polling the bodies themselves.
+///
+/// Shutting down the write half ends the response body. If the `Duplex` is
+/// dropped before its write half is shut down, the response body fails and
+/// the stream is reset.
+pub struct Duplex {
+    recv: RecvBody,
+    read_buf: Bytes,
+    send: Option<BodySender>,
+}
+
+/// The tunnel of a `CONNECT` request.
+///
+/// The service accepts a `CONNECT` request by answering it with a `2xx`
+/// response whose body is the `SendBody` returned by `Upgraded::new`.
+pub type Upgraded = Duplex;
+
+// ===== impl Duplex =====
+
+impl Duplex {
+    /// Create a new `Duplex` that reads from `recv`, returning the body of
+    /// the response that it writes to.
+    ///
+    /// At most `buffer` writes are queued in addition to the one write that
+    /// is always accepted before writes return `WouldBlock`.
+    pub fn new(recv: RecvBody, buffer: usize) -> (Duplex, SendBody) {
+        let (send, body) = SendBody::channel(buffer);
+        (Duplex::from_parts(recv, send), body)
+    }
+
+    /// Create a new `Duplex` that reads from `recv` and writes to `send`.
+    pub fn from_parts(recv: RecvBody, send: BodySender) -> Self {
+        Duplex {
+            recv,
+            read_buf: Bytes::new(),
+            send: Some(send),
+        }
+    }
+
+    /// Returns the request body, and the response body's sender unless the
+    /// write half has been shut down.
+    ///
+    /// Data that was received but not yet read is lost.
+    pub fn into_parts(self) -> (RecvBody, Option<BodySender>) {
+        (self.recv, self.send)
+    }
+}
+
+impl io::Read for Duplex {
+    fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        while self.read_buf.is_empty() {
+            let res = self.recv.poll()
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+
+            match res? {
+                Async::Ready(Some(data)) => self.read_buf = data,
+                Async::Ready(None) => return Ok(0),
+                Async::NotReady => return Err(io::ErrorKind::WouldBlock.into()),
+            }
+        }
+
+        let n = cmp::min(dst.len(), self.read_buf.len());
+        dst[..n].copy_from_slice(&self.read_buf.split_to(n));
+        Ok(n)
+    }
+}
+
+impl io::Write for Duplex {
+    fn write(&mut self, src: &[u8]) -> io::Result<usize> {
+        let send = match self.send {
+            Some(ref mut send) => send,
+            None => return Err(io::ErrorKind::BrokenPipe.into()),
+        };
+
+        let res = send.start_send(Bytes::from(src))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe));
+
+        match res? {
+            AsyncSink::Ready => Ok(src.len()),
+            AsyncSink::NotReady(_) => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let res = match self.send {
+            Some(ref mut send) => send.poll_complete(),
+            None => return Ok(()),
+        };
+
+        match res {
+            Ok(Async::Ready(())) => Ok(()),
+            Ok(Async::NotReady) => Err(io::ErrorKind::WouldBlock.into()),
+            Err(_) => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+}
+
+impl AsyncRead for Duplex {}
+
+impl AsyncWrite for Duplex {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match io::Write::flush(self) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+            Err(e) => return Err(e),
+        }
+
+        if let Some(send) = self.send.take() {
+            send.finish();
+        }
+
+        Ok(Async::Ready(()))
+    }
+
+    fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+        let n = match io::Write::write(self, buf.bytes()) {
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+            Err(e) => return Err(e),
+        };
+
+        buf.advance(n);
+        Ok(Async::Ready(n))
+    }
+}
+
+impl fmt::Debug for Duplex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Duplex")
+            .field("recv", &self.recv)
+            .field("buffered", &self.read_buf.len())
+            .field("write_closed", &self.send.is_none())
+            .finish()
+    }
+}
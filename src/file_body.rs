@@ -0,0 +1,95 @@
+use Body;
+
+use bytes::{Bytes, BytesMut};
+use futures::{Async, Poll};
+use h2;
+use tokio_fs::File;
+use tokio_io::AsyncRead;
+
+use std::fmt;
+
+/// The default number of bytes read from the file for each chunk.
+const DEFAULT_CHUNK_SIZE: usize = 16 * 1024;
+
+/// A body that streams the contents of a file.
+///
+/// The file is read with `tokio-fs`, so reads are performed on the blocking
+/// pool of the tokio thread pool rather than blocking the reactor. This means
+/// the body must be polled from a task running on a tokio thread pool.
+///
+/// Each chunk is read only when the previous one has been handed to h2, so
+/// at most one chunk is buffered at a time.
+///
+/// This type is only available when the `fs` feature is enabled.
+pub struct FileBody {
+    file: Option<File>,
+    chunk_size: usize,
+}
+
+// ===== impl FileBody =====
+
+impl FileBody {
+    /// Create a new `FileBody` that streams `file` until its end.
+    pub fn new(file: File) -> Self {
+        FileBody {
+            file: Some(file),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Sets the maximum number of bytes read from the file for each chunk.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        assert!(chunk_size > 0, "chunk size must be greater than zero");
+        self.chunk_size = chunk_size;
+    }
+
+    /// Returns the maximum number of bytes read from the file for each chunk.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+}
+
+impl Body for FileBody {
+    type Data = Bytes;
+
+    fn is_end_stream(&self) -> bool {
+        self.file.is_none()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Bytes>, h2::Error> {
+        let chunk = {
+            let file = match self.file {
+                Some(ref mut file) => file,
+                None => return Ok(Async::Ready(None)),
+            };
+
+            let mut buf = BytesMut::with_capacity(self.chunk_size);
+
+            match file.read_buf(&mut buf) {
+                Ok(Async::Ready(0)) => None,
+                Ok(Async::Ready(_)) => Some(buf.freeze()),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(e) => {
+                    debug!("error reading file body: {}", e);
+                    return Err(h2::Reason::INTERNAL_ERROR.into());
+                }
+            }
+        };
+
+        if chunk.is_none() {
+            trace!("file body complete");
+            self.file = None;
+        }
+
+        Ok(Async::Ready(chunk))
+    }
+}
+
+impl fmt::Debug for FileBody {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FileBody")
+            .field("chunk_size", &self.chunk_size)
+            .field("done", &self.file.is_none())
+            .finish()
+    }
+}
@@ -1,16 +1,89 @@
-use Body;
+use body::{Body, BodyBuf};
+use buf::SendBuf;
+use pool::{self, SharedPool};
 
+use bytes::{BufMut, BytesMut, Buf, IntoBuf};
 use futures::{Future, Poll, Async};
 use h2::{self, SendStream};
 use http::HeaderMap;
 
+use std::{error, fmt, mem};
+use std::time::{Duration, Instant};
+
+/// The send capacity reserved before polling the body for more data: the
+/// default maximum frame size.
+const RESERVE: usize = 16_384;
+
 /// Flush a body to the HTTP/2.0 send stream
 pub(crate) struct Flush<S>
 where S: Body,
 {
-    h2: SendStream<S::Data>,
+    h2: SendStream<BodyBuf<S>>,
     body: S,
     state: FlushState,
+
+    /// The most recent chunk, held back until it is known whether it ends the
+    /// stream.
+    held: Option<BodyBuf<S>>,
+
+    /// Small chunks copied together so that they are sent as a single frame.
+    coalesced: BytesMut,
+
+    /// Chunks smaller than this are coalesced. Zero disables coalescing.
+    coalesce_watermark: usize,
+
+    /// Up to this many ready chunks are queued and sent as a single frame.
+    max_queued: usize,
+
+    /// Provides the buffer chunks are coalesced into, if set.
+    pool: Option<SharedPool>,
+
+    /// The body is polled while fewer than this many bytes are buffered. If
+    /// unset, it is only polled while the stream has send capacity.
+    max_buffered: Option<usize>,
+
+    /// An upper bound on the bytes handed to h2 that it has not sent yet,
+    /// because they exceeded the stream's send capacity.
+    unsent: usize,
+
+    bytes_sent: usize,
+
+    /// The number of writes of data to the stream.
+    frames: u64,
+
+    /// The number of times the body was not polled because the stream had no
+    /// send capacity, and the total time spent waiting for capacity.
+    stalls: u64,
+    stalled: Duration,
+
+    /// When the current wait for send capacity started, if waiting.
+    stalled_since: Option<Instant>,
+
+    /// The length declared by the `content-length` header, if any.
+    content_length: Option<u64>,
+
+    /// Identifies the stream in log messages. Zero if unset.
+    stream_id: u64,
+}
+
+/// Statistics about sending a response or request body.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlushStats {
+    bytes_sent: u64,
+    frames: u64,
+    stalls: u64,
+    stalled: Duration,
+}
+
+/// Error produced when flushing a body.
+#[derive(Debug)]
+pub(crate) enum FlushError {
+    /// The stream failed.
+    H2(h2::Error),
+
+    /// The body did not match its `content-length` header. The stream has
+    /// been reset.
+    ContentLength { declared: u64, sent: u64 },
 }
 
 enum FlushState {
@@ -29,43 +102,333 @@ enum DataOrTrailers<B> {
 impl<S> Flush<S>
 where S: Body,
 {
-    pub fn new(src: S, dst: SendStream<S::Data>) -> Self {
+    pub fn new(src: S, dst: SendStream<BodyBuf<S>>) -> Self {
         Flush {
             h2: dst,
             body: src,
             state: FlushState::Data,
+            held: None,
+            coalesced: BytesMut::new(),
+            coalesce_watermark: 0,
+            max_queued: 1,
+            pool: None,
+            max_buffered: None,
+            unsent: 0,
+            bytes_sent: 0,
+            frames: 0,
+            stalls: 0,
+            stalled: Duration::from_secs(0),
+            stalled_since: None,
+            content_length: None,
+            stream_id: 0,
         }
     }
 
-    /// Try to flush the body.
-    fn poll_complete(&mut self) -> Poll<(), h2::Error> {
-        let mut first = try_ready!(self.poll_body());
+    /// Sets the ID included in log messages about the stream.
+    pub fn set_stream_id(&mut self, id: u64) {
+        self.stream_id = id;
+    }
+
+    /// Sets the result of polling the body for data before the `Flush` was
+    /// created: its first chunk, which is sent before the rest of the body,
+    /// or `None` if it had no data.
+    pub fn set_first(&mut self, first: Option<<S::Data as IntoBuf>::Buf>) {
+        match first {
+            Some(buf) => self.held = Some(SendBuf::buf(buf)),
+            None => self.state = FlushState::Trailers,
+        }
+    }
+
+    /// Fail and reset the stream if the body does not contain exactly
+    /// `len` bytes.
+    pub fn set_content_length(&mut self, len: u64) {
+        self.content_length = Some(len);
+    }
+
+    /// Coalesce chunks smaller than `watermark` bytes, sending them once
+    /// `watermark` bytes have accumulated or the body stops being ready.
+    pub fn set_coalesce_watermark(&mut self, watermark: usize) {
+        self.coalesce_watermark = watermark;
+    }
+
+    /// Queue up to `max` chunks that the body yields while it is ready, and
+    /// send them together as a single frame.
+    ///
+    /// The chunks are not copied; transports that support vectored writes
+    /// may write them with a single call.
+    pub fn set_max_queued_chunks(&mut self, max: usize) {
+        self.max_queued = max;
+    }
+
+    /// Obtain the buffer chunks are coalesced into from `pool`.
+    pub fn set_buffer_pool(&mut self, pool: SharedPool) {
+        self.pool = Some(pool);
+    }
+
+    /// Keep polling the body while fewer than `max` bytes are buffered,
+    /// even if the stream has no send capacity.
+    pub fn set_max_buffered(&mut self, max: usize) {
+        self.max_buffered = Some(max);
+    }
+
+    /// Returns the number of body bytes buffered but not sent yet, either
+    /// held by the `Flush` or queued by h2 until the peer grants more
+    /// capacity.
+    pub fn buffered(&self) -> usize {
+        let held = self.held.as_ref().map_or(0, |held| held.remaining());
+        self.unsent + held + self.coalesced.len()
+    }
+
+    /// Returns the number of body bytes sent so far.
+    pub fn bytes_sent(&self) -> usize {
+        self.bytes_sent
+    }
+
+    /// Returns statistics about the body sent so far.
+    pub fn stats(&self) -> FlushStats {
+        let stalled = match self.stalled_since {
+            Some(since) => self.stalled + since.elapsed(),
+            None => self.stalled,
+        };
+
+        FlushStats {
+            bytes_sent: self.bytes_sent as u64,
+            frames: self.frames,
+            stalls: self.stalls,
+            stalled,
+        }
+    }
 
+    /// Reset the stream with `reason`, abandoning the rest of the body.
+    pub fn send_reset(&mut self, reason: h2::Reason) {
+        self.h2.send_reset(reason);
+        self.state = FlushState::Done;
+    }
+
+    /// Polls for the peer resetting the stream.
+    pub fn poll_reset(&mut self) -> Poll<h2::Reason, h2::Error> {
+        self.h2.poll_reset()
+    }
+
+    /// Try to flush the body.
+    ///
+    /// The body is only polled for data while the stream has send capacity,
+    /// so that a body producing data faster than the peer accepts it is not
+    /// buffered without bound.
+    fn poll_complete(&mut self) -> Poll<(), FlushError> {
         loop {
-            if let Some(DataOrTrailers::Data(buf)) = first {
-                let second = self.poll_body()?;
-                let eos = if let Async::Ready(None) = second {
-                    true
-                } else {
-                    false
-                };
-                self.h2.send_data(buf, eos)?;
-                if eos {
-                    return Ok(Async::Ready(()));
-                } else if let Async::Ready(item) = second {
-                    first = item;
-                } else {
+            if let Async::NotReady = self.poll_send_capacity()? {
+                // Send everything buffered so far; h2 queues it until the
+                // peer grants more capacity.
+                self.send_buffered(false)?;
+                return Ok(Async::NotReady);
+            }
+
+            let item = match self.poll_body()? {
+                Async::Ready(item) => item,
+                Async::NotReady => {
+                    // Send everything buffered so far rather than waiting on
+                    // the body.
+                    self.send_buffered(false)?;
                     return Ok(Async::NotReady);
                 }
-            } else if let Some(DataOrTrailers::Trailers(trailers)) = first {
-                self.h2.send_trailers(trailers)?;
-                return Ok(Async::Ready(()));
-            } else {
+            };
+
+            match item {
+                Some(DataOrTrailers::Data(data)) => {
+                    let buf = data.into_buf();
+
+                    if buf.remaining() < self.coalesce_watermark {
+                        self.send_held(false)?;
+
+                        if self.coalesced.capacity() == 0 {
+                            self.coalesced = pool::get(self.pool.as_ref(), self.coalesce_watermark);
+                        }
+
+                        self.coalesced.reserve(buf.remaining());
+                        self.coalesced.put(buf);
+
+                        if self.coalesced.len() >= self.coalesce_watermark {
+                            let bytes = self.coalesced.take().freeze();
+                            self.held = Some(SendBuf::bytes(bytes));
+                        }
+                    } else if self.can_queue() {
+                        let held = self.held.take().expect("held chunk");
+                        self.held = Some(held.push(SendBuf::buf(buf)));
+                    } else {
+                        self.send_buffered(false)?;
+                        self.held = Some(SendBuf::buf(buf));
+                    }
+                }
+                Some(DataOrTrailers::Trailers(trailers)) => {
+                    self.send_buffered(false)?;
+                    self.check_content_length(0, true)?;
+                    trace!("sending trailers; stream={}", self.stream_id);
+                    self.h2.send_trailers(trailers)?;
+                    self.recycle();
+                    return Ok(Async::Ready(()));
+                }
+                None => {
+                    if self.held.is_none() && self.coalesced.is_empty() {
+                        // Everything was already sent, so end the stream with
+                        // an empty frame.
+                        self.send(SendBuf::empty(), true)?;
+                    } else {
+                        self.send_buffered(true)?;
+                    }
+
+                    self.recycle();
+                    return Ok(Async::Ready(()));
+                }
+            }
+        }
+    }
+
+    /// Ready once the stream has capacity to send more data, or fewer than
+    /// the maximum bytes are buffered if one is set, or if the body is no
+    /// longer being polled for data.
+    fn poll_send_capacity(&mut self) -> Poll<(), FlushError> {
+        match self.state {
+            FlushState::Data => {}
+            FlushState::Trailers | FlushState::Done => return Ok(Async::Ready(())),
+        }
+
+        loop {
+            let capacity = self.h2.capacity();
+
+            // h2 only reports capacity once everything it buffered was sent.
+            if capacity > 0 {
+                self.unsent = 0;
+            }
+
+            let ready = match self.max_buffered {
+                Some(max) => self.buffered() < max,
+                None => capacity > 0,
+            };
+
+            if ready {
+                self.end_stall();
                 return Ok(Async::Ready(()));
             }
+
+            if capacity > 0 {
+                // Only the chunks held back are over the maximum; hand them
+                // to h2, which may send them right away.
+                self.send_buffered(false)?;
+                continue;
+            }
+
+            self.h2.reserve_capacity(RESERVE);
+
+            // Capacity was assigned, so check it again.
+            match try_ready!(self.poll_capacity()) {
+                Some(_) => {}
+                None => {
+                    // The stream can no longer send data; let sending fail
+                    // rather than waiting forever.
+                    self.end_stall();
+                    return Ok(Async::Ready(()));
+                }
+            }
+        }
+    }
+
+    /// Polls h2 for send capacity, recording the time spent waiting for it.
+    fn poll_capacity(&mut self) -> Poll<Option<usize>, FlushError> {
+        match self.h2.poll_capacity()? {
+            Async::Ready(capacity) => Ok(Async::Ready(capacity)),
+            Async::NotReady => {
+                if self.stalled_since.is_none() {
+                    trace!("waiting for send capacity; stream={}", self.stream_id);
+                    self.stalls += 1;
+                    self.stalled_since = Some(Instant::now());
+                }
+
+                Ok(Async::NotReady)
+            }
+        }
+    }
+
+    fn end_stall(&mut self) {
+        if let Some(since) = self.stalled_since.take() {
+            self.stalled += since.elapsed();
+        }
+    }
+
+    /// Returns `true` if another chunk may be queued behind the held chunk.
+    fn can_queue(&self) -> bool {
+        if !self.coalesced.is_empty() {
+            return false;
+        }
+
+        match self.held {
+            Some(ref held) => held.chunks() < self.max_queued,
+            None => false,
+        }
+    }
+
+    /// Send the held chunk followed by any coalesced chunks.
+    ///
+    /// If `eos` is set, the last frame sent ends the stream.
+    fn send_buffered(&mut self, eos: bool) -> Result<(), FlushError> {
+        if self.coalesced.is_empty() {
+            return self.send_held(eos);
+        }
+
+        self.send_held(false)?;
+
+        let bytes = self.coalesced.take().freeze();
+        self.send(SendBuf::bytes(bytes), eos)
+    }
+
+    /// Return the coalescing buffer to the pool once the body is complete.
+    fn recycle(&mut self) {
+        if let Some(ref pool) = self.pool {
+            if self.coalesced.capacity() > 0 {
+                pool.recycle(mem::replace(&mut self.coalesced, BytesMut::new()));
+            }
         }
     }
 
+    fn send_held(&mut self, eos: bool) -> Result<(), FlushError> {
+        match self.held.take() {
+            Some(buf) => self.send(buf, eos),
+            None => Ok(()),
+        }
+    }
+
+    fn send(&mut self, buf: BodyBuf<S>, eos: bool) -> Result<(), FlushError> {
+        let len = buf.remaining();
+        self.check_content_length(len, eos)?;
+        trace!("sending data; stream={} len={} eos={}", self.stream_id, len, eos);
+        let capacity = self.h2.capacity();
+        self.h2.send_data(buf, eos)?;
+        self.unsent += len.saturating_sub(capacity);
+        self.bytes_sent += len;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Resets the stream if sending `len` more bytes would exceed the declared
+    /// `content-length`, or if the body ends with fewer bytes than declared.
+    fn check_content_length(&mut self, len: usize, eos: bool) -> Result<(), FlushError> {
+        let declared = match self.content_length {
+            Some(declared) => declared,
+            None => return Ok(()),
+        };
+
+        let sent = (self.bytes_sent + len) as u64;
+
+        if sent > declared || (eos && sent < declared) {
+            debug!("body does not match content-length; stream={} declared={} sent={}",
+                   self.stream_id, declared, sent);
+            self.send_reset(h2::Reason::INTERNAL_ERROR);
+            return Err(FlushError::ContentLength { declared, sent });
+        }
+
+        Ok(())
+    }
+
     /// Get the next message to write, either a data frame or trailers.
     fn poll_body(&mut self) -> Poll<Option<DataOrTrailers<S::Data>>, h2::Error> {
         loop {
@@ -94,10 +457,94 @@ impl<S> Future for Flush<S>
 where S: Body,
 {
     type Item = ();
-    type Error = ();
+    type Error = FlushError;
 
-    fn poll(&mut self) -> Poll<(), ()> {
-        // TODO: Do something with the error
-        self.poll_complete().map_err(|_| ())
+    fn poll(&mut self) -> Poll<(), FlushError> {
+        self.poll_complete()
+    }
+}
+
+// ===== impl FlushStats =====
+
+impl FlushStats {
+    /// Returns the statistics of a body sent as a single frame, without
+    /// stalling.
+    pub(crate) fn single(bytes_sent: usize) -> Self {
+        FlushStats {
+            bytes_sent: bytes_sent as u64,
+            frames: if bytes_sent > 0 { 1 } else { 0 },
+            ..FlushStats::default()
+        }
+    }
+
+    /// Returns the number of body bytes sent.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// Returns the number of writes of data to the stream.
+    ///
+    /// h2 splits a write into several DATA frames if it is larger than the
+    /// peer's maximum frame size.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns the number of times sending stalled because the peer had not
+    /// granted enough flow control capacity.
+    pub fn stalls(&self) -> u64 {
+        self.stalls
+    }
+
+    /// Returns the total time spent waiting for flow control capacity.
+    pub fn stalled(&self) -> Duration {
+        self.stalled
+    }
+}
+
+// ===== impl FlushError =====
+
+impl FlushError {
+    /// Returns the reason the stream was reset with.
+    pub fn reason(&self) -> h2::Reason {
+        match *self {
+            FlushError::H2(ref e) => e.reason().unwrap_or(h2::Reason::INTERNAL_ERROR),
+            FlushError::ContentLength { .. } => h2::Reason::INTERNAL_ERROR,
+        }
+    }
+}
+
+impl From<h2::Error> for FlushError {
+    fn from(src: h2::Error) -> Self {
+        FlushError::H2(src)
+    }
+}
+
+impl fmt::Display for FlushError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FlushError::H2(ref why) => fmt::Display::fmt(why, f),
+            FlushError::ContentLength { declared, sent } => write!(f,
+                "Error sending body: content-length is {} but {} bytes were sent",
+                declared,
+                sent,
+            ),
+        }
+    }
+}
+
+impl error::Error for FlushError {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            FlushError::H2(ref why) => Some(why),
+            FlushError::ContentLength { .. } => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            FlushError::H2(ref why) => why.description(),
+            FlushError::ContentLength { .. } => "body does not match content-length",
+        }
     }
 }
@@ -0,0 +1,351 @@
+//! Streams of messages over HTTP/2.0 bodies.
+//!
+//! RPC protocols usually send a sequence of messages as the data of a
+//! request or response body, each of them framed by the protocol, for
+//! example with a length prefix. `FramedRecv` decodes the data of a
+//! `RecvBody` into a `Stream` of messages with a `tokio_io::codec::Decoder`,
+//! and `FramedSend` encodes messages into a `BodySender` with an `Encoder`:
+//!
+//! ```rust,ignore
+//! let requests = FramedRecv::new(request.into_body(), LengthPrefixed::new(MAX));
+//! let (sender, body) = SendBody::channel(BUFFER);
+//! let responses = FramedSend::new(sender, LengthPrefixed::new(MAX));
+//! ```
+//!
+//! A message may span several DATA frames, and a frame may carry several
+//! messages; the framing of the messages is independent of that of the body.
+
+use {BodySender, RecvBody, SendError};
+
+use bytes::{Bytes, BytesMut};
+use futures::{Async, AsyncSink, Poll, Sink, StartSend, Stream};
+use tokio_io::codec::{Decoder, Encoder};
+
+use std::io;
+
+/// The length of the prefix of a `LengthPrefixed` message.
+const PREFIX_LEN: usize = 4;
+
+/// Decodes the data of a `RecvBody` into a stream of messages.
+///
+/// Flow control capacity is released as data is received, once it has been
+/// buffered for the decoder. Errors receiving the body are passed to the
+/// decoder's error type as an `io::Error` of kind `Other` wrapping the
+/// `RecvError`.
+#[derive(Debug)]
+pub struct FramedRecv<D> {
+    body: RecvBody,
+    decoder: D,
+    buffer: BytesMut,
+    eof: bool,
+}
+
+/// Encodes messages into the data of a `SendBody`.
+///
+/// Each message is encoded when it is sent, and the encoded bytes are handed
+/// to the `BodySender` once it accepts more data. If the `SendBody` was
+/// dropped, sending fails with an `io::Error` of kind `BrokenPipe`.
+///
+/// Closing the sink ends the body once every message has been sent. If it
+/// is dropped before being closed, the body fails and the stream is reset.
+#[derive(Debug)]
+pub struct FramedSend<E> {
+    sender: BodySender,
+    encoder: E,
+    buffer: BytesMut,
+}
+
+/// A codec of messages that are each prefixed with their length, as a 4-byte
+/// big-endian integer.
+///
+/// Messages longer than the codec's maximum length fail to decode and to
+/// encode with an `io::Error` of kind `InvalidData`.
+#[derive(Clone, Debug)]
+pub struct LengthPrefixed {
+    max: usize,
+
+    /// The length of the message being decoded, once its prefix was read.
+    len: Option<usize>,
+}
+
+// ===== impl FramedRecv =====
+
+impl<D> FramedRecv<D>
+where D: Decoder,
+{
+    /// Decode the data of `body` with `decoder`.
+    pub fn new(body: RecvBody, decoder: D) -> Self {
+        FramedRecv {
+            body,
+            decoder,
+            buffer: BytesMut::new(),
+            eof: false,
+        }
+    }
+
+    /// Returns a reference to the decoder.
+    pub fn decoder(&self) -> &D {
+        &self.decoder
+    }
+
+    /// Returns the body and decoder.
+    ///
+    /// Data that was received but not decoded yet is lost.
+    pub fn into_inner(self) -> (RecvBody, D) {
+        (self.body, self.decoder)
+    }
+}
+
+impl<D> Stream for FramedRecv<D>
+where D: Decoder,
+{
+    type Item = D::Item;
+    type Error = D::Error;
+
+    fn poll(&mut self) -> Poll<Option<D::Item>, D::Error> {
+        loop {
+            if self.eof {
+                let frame = self.decoder.decode_eof(&mut self.buffer)?;
+                return Ok(Async::Ready(frame));
+            }
+
+            if let Some(frame) = self.decoder.decode(&mut self.buffer)? {
+                return Ok(Async::Ready(Some(frame)));
+            }
+
+            let res = self.body.poll_recv()
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+
+            match try_ready!(res) {
+                Some(data) => self.buffer.extend_from_slice(&Bytes::from(data)),
+                None => {
+                    trace!("body ended; {} bytes left to decode", self.buffer.len());
+                    self.eof = true;
+                }
+            }
+        }
+    }
+}
+
+// ===== impl FramedSend =====
+
+impl<E> FramedSend<E>
+where E: Encoder,
+{
+    /// Send messages encoded with `encoder` to `sender`.
+    pub fn new(sender: BodySender, encoder: E) -> Self {
+        FramedSend {
+            sender,
+            encoder,
+            buffer: BytesMut::new(),
+        }
+    }
+
+    /// Returns a reference to the encoder.
+    pub fn encoder(&self) -> &E {
+        &self.encoder
+    }
+
+    /// Returns the sender and encoder, so that the body may be ended with
+    /// trailers.
+    ///
+    /// Messages that were encoded but not handed to the sender yet are lost;
+    /// they are not once `poll_complete` has returned `Ready`.
+    pub fn into_inner(self) -> (BodySender, E) {
+        (self.sender, self.encoder)
+    }
+
+    /// Hands the encoded messages to the sender, if it accepts them.
+    fn poll_buffer(&mut self) -> Poll<(), E::Error> {
+        if self.buffer.is_empty() {
+            return Ok(Async::Ready(()));
+        }
+
+        let bytes = self.buffer.take().freeze();
+
+        match self.sender.start_send(bytes).map_err(broken_pipe)? {
+            AsyncSink::Ready => Ok(Async::Ready(())),
+            AsyncSink::NotReady(bytes) => {
+                self.buffer = bytes.into();
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+impl<E> Sink for FramedSend<E>
+where E: Encoder,
+{
+    type SinkItem = E::Item;
+    type SinkError = E::Error;
+
+    fn start_send(&mut self, item: E::Item) -> StartSend<E::Item, E::Error> {
+        // Only encode more messages once the previous ones were accepted, so
+        // that the sender's buffer bounds the messages queued.
+        if self.poll_buffer()?.is_not_ready() {
+            return Ok(AsyncSink::NotReady(item));
+        }
+
+        self.encoder.encode(item, &mut self.buffer)?;
+        Ok(AsyncSink::Ready)
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), E::Error> {
+        try_ready!(self.poll_buffer());
+        self.sender.poll_complete().map_err(broken_pipe)
+    }
+
+    fn close(&mut self) -> Poll<(), E::Error> {
+        try_ready!(self.poll_buffer());
+        self.sender.close().map_err(broken_pipe)
+    }
+}
+
+fn broken_pipe<E: From<io::Error>>(err: SendError) -> E {
+    io::Error::new(io::ErrorKind::BrokenPipe, err).into()
+}
+
+// ===== impl LengthPrefixed =====
+
+impl LengthPrefixed {
+    /// Create a codec of messages of at most `max` bytes, not including
+    /// their prefix.
+    pub fn new(max: usize) -> Self {
+        LengthPrefixed {
+            max,
+            len: None,
+        }
+    }
+
+    fn check_len(&self, len: usize) -> Result<(), io::Error> {
+        if len > self.max || len as u64 > u32::max_value() as u64 {
+            debug!("message too long; len={} max={}", len, self.max);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "message too long"));
+        }
+
+        Ok(())
+    }
+}
+
+impl Decoder for LengthPrefixed {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Bytes>, io::Error> {
+        let len = match self.len {
+            Some(len) => len,
+            None => {
+                if src.len() < PREFIX_LEN {
+                    return Ok(None);
+                }
+
+                let prefix = src.split_to(PREFIX_LEN);
+                let len = prefix.iter().fold(0, |len, &b| len << 8 | b as usize);
+                self.check_len(len)?;
+                self.len = Some(len);
+                len
+            }
+        };
+
+        if src.len() < len {
+            src.reserve(len - src.len());
+            return Ok(None);
+        }
+
+        self.len = None;
+        Ok(Some(src.split_to(len).freeze()))
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Bytes>, io::Error> {
+        if let Some(message) = self.decode(src)? {
+            return Ok(Some(message));
+        }
+
+        if self.len.is_some() || !src.is_empty() {
+            debug!("body ended within a message");
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        Ok(None)
+    }
+}
+
+impl Encoder for LengthPrefixed {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), io::Error> {
+        let len = item.len();
+        self.check_len(len)?;
+
+        dst.reserve(PREFIX_LEN + len);
+        dst.extend_from_slice(&[
+            (len >> 24) as u8,
+            (len >> 16) as u8,
+            (len >> 8) as u8,
+            len as u8,
+        ]);
+        dst.extend_from_slice(&item);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LengthPrefixed;
+
+    use bytes::{Bytes, BytesMut};
+    use tokio_io::codec::{Decoder, Encoder};
+
+    use std::io;
+
+    #[test]
+    fn length_prefixed_round_trip() {
+        let mut codec = LengthPrefixed::new(16);
+        let mut buf = BytesMut::new();
+
+        codec.encode(Bytes::from_static(b"hello"), &mut buf).unwrap();
+        codec.encode(Bytes::new(), &mut buf).unwrap();
+        assert_eq!(&buf[..], &b"\0\0\0\x05hello\0\0\0\0"[..]);
+
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Bytes::from_static(b"hello")));
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Bytes::new()));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+    }
+
+    #[test]
+    fn length_prefixed_partial() {
+        let mut codec = LengthPrefixed::new(16);
+        let mut buf = BytesMut::from(&b"\0\0"[..]);
+
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+        buf.extend_from_slice(b"\0\x03ab");
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+        buf.extend_from_slice(b"c");
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(Bytes::from_static(b"abc")));
+    }
+
+    #[test]
+    fn length_prefixed_too_long() {
+        let mut codec = LengthPrefixed::new(4);
+
+        let err = codec.encode(Bytes::from_static(b"hello"), &mut BytesMut::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut buf = BytesMut::from(&b"\0\0\0\x05hello"[..]);
+        let err = codec.decode(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn length_prefixed_eof_within_message() {
+        let mut codec = LengthPrefixed::new(16);
+        let mut buf = BytesMut::from(&b"\0\0\0\x05he"[..]);
+
+        let err = codec.decode_eof(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,451 @@
+//! Helpers for serving gRPC over HTTP/2.0.
+//!
+//! gRPC reports the outcome of a call with `grpc-status` and `grpc-message`
+//! trailers, rather than with the HTTP status. `GrpcService` wraps a service so
+//! that:
+//!
+//! * requests without `te: trailers` are rejected, as required by gRPC;
+//! * response bodies end with a `grpc-status` trailer;
+//! * failed response futures produce a "trailers-only" response, whose
+//!   `grpc-status` is sent in the response head, rather than resetting the
+//!   stream.
+
+use {Body, RecvBody};
+
+use futures::{Async, Future, Poll};
+use h2::{self, Reason};
+use http::{HeaderMap, Request, Response};
+use http::header::{self, HeaderValue};
+use tower::{NewService, Service};
+
+use std::fmt;
+
+/// A gRPC status code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Code {
+    Ok = 0,
+    Cancelled = 1,
+    Unknown = 2,
+    InvalidArgument = 3,
+    DeadlineExceeded = 4,
+    NotFound = 5,
+    AlreadyExists = 6,
+    PermissionDenied = 7,
+    ResourceExhausted = 8,
+    FailedPrecondition = 9,
+    Aborted = 10,
+    OutOfRange = 11,
+    Unimplemented = 12,
+    Internal = 13,
+    Unavailable = 14,
+    DataLoss = 15,
+    Unauthenticated = 16,
+}
+
+/// The outcome of a gRPC call, sent as the `grpc-status` and `grpc-message`
+/// trailers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    code: Code,
+    message: Option<String>,
+}
+
+/// Converts a service error into the `Status` reported to the client.
+pub trait IntoStatus {
+    /// Returns the status to end the call with.
+    fn into_status(self) -> Status;
+}
+
+/// A response body that ends with a `grpc-status` trailer.
+pub struct GrpcBody<B> {
+    inner: Option<B>,
+    status: Option<Status>,
+}
+
+/// Wraps a service so that it follows the gRPC conventions for status
+/// reporting.
+#[derive(Clone, Debug)]
+pub struct GrpcService<S> {
+    inner: S,
+}
+
+/// Produces a `GrpcService` for each service produced by the inner
+/// `NewService`.
+#[derive(Clone, Debug)]
+pub struct NewGrpcService<N> {
+    inner: N,
+}
+
+/// Completes with the `GrpcService` produced by a `NewGrpcService`.
+pub struct NewServiceFuture<F> {
+    inner: F,
+}
+
+/// The response future of a `GrpcService`.
+pub struct ResponseFuture<F> {
+    inner: Option<F>,
+    status: Option<Status>,
+}
+
+const GRPC_STATUS: &str = "grpc-status";
+const GRPC_MESSAGE: &str = "grpc-message";
+
+// ===== impl Code =====
+
+impl Code {
+    /// Returns the code for the numeric value of a `grpc-status`.
+    ///
+    /// Unknown values are mapped to `Code::Unknown`.
+    pub fn from_i32(value: i32) -> Code {
+        use self::Code::*;
+
+        match value {
+            0 => Ok,
+            1 => Cancelled,
+            2 => Unknown,
+            3 => InvalidArgument,
+            4 => DeadlineExceeded,
+            5 => NotFound,
+            6 => AlreadyExists,
+            7 => PermissionDenied,
+            8 => ResourceExhausted,
+            9 => FailedPrecondition,
+            10 => Aborted,
+            11 => OutOfRange,
+            12 => Unimplemented,
+            13 => Internal,
+            14 => Unavailable,
+            15 => DataLoss,
+            16 => Unauthenticated,
+            _ => Unknown,
+        }
+    }
+
+    /// Returns the code a stream reset with `reason` is reported as, as
+    /// specified by the gRPC HTTP/2.0 protocol.
+    pub fn from_reason(reason: Reason) -> Code {
+        match reason {
+            Reason::REFUSED_STREAM => Code::Unavailable,
+            Reason::CANCEL => Code::Cancelled,
+            Reason::ENHANCE_YOUR_CALM => Code::ResourceExhausted,
+            Reason::INADEQUATE_SECURITY => Code::PermissionDenied,
+            _ => Code::Internal,
+        }
+    }
+
+    fn header_value(&self) -> HeaderValue {
+        let value = (*self as i32).to_string();
+        HeaderValue::from_str(&value).expect("status code is a valid header value")
+    }
+}
+
+// ===== impl Status =====
+
+impl Status {
+    /// Returns a new `Status` with `code` and no message.
+    pub fn new(code: Code) -> Self {
+        Status {
+            code,
+            message: None,
+        }
+    }
+
+    /// Returns a new `Status` with `code` and `message`.
+    pub fn with_message<M: Into<String>>(code: Code, message: M) -> Self {
+        Status {
+            code,
+            message: Some(message.into()),
+        }
+    }
+
+    /// Returns the status of a successful call.
+    pub fn ok() -> Self {
+        Status::new(Code::Ok)
+    }
+
+    /// Returns the status code.
+    pub fn code(&self) -> Code {
+        self.code
+    }
+
+    /// Returns the status message, if any.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_ref().map(|m| &m[..])
+    }
+
+    /// Returns a "trailers-only" response that ends the call with this
+    /// status.
+    ///
+    /// The status is sent in the response head, which ends the stream.
+    pub fn into_response<B>(self) -> Response<GrpcBody<B>> {
+        let mut response = Response::new(GrpcBody::empty());
+
+        {
+            let headers = response.headers_mut();
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/grpc"));
+            self.add_to(headers);
+        }
+
+        response
+    }
+
+    /// Inserts the `grpc-status` and `grpc-message` headers into `headers`.
+    pub fn add_to(&self, headers: &mut HeaderMap) {
+        headers.insert(GRPC_STATUS, self.code.header_value());
+
+        if let Some(ref message) = self.message {
+            let encoded = percent_encode(message);
+
+            match HeaderValue::from_str(&encoded) {
+                Ok(value) => {
+                    headers.insert(GRPC_MESSAGE, value);
+                }
+                Err(_) => debug!("invalid grpc-message; not sending it"),
+            }
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.message {
+            Some(ref message) => write!(f, "gRPC status {:?}: {}", self.code, message),
+            None => write!(f, "gRPC status {:?}", self.code),
+        }
+    }
+}
+
+/// Percent-encodes a `grpc-message`, as required by the gRPC HTTP/2.0
+/// protocol.
+fn percent_encode(message: &str) -> String {
+    let mut encoded = String::with_capacity(message.len());
+
+    for &byte in message.as_bytes() {
+        if byte >= 0x20 && byte <= 0x7E && byte != b'%' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    encoded
+}
+
+// ===== impl IntoStatus =====
+
+impl IntoStatus for Status {
+    fn into_status(self) -> Status {
+        self
+    }
+}
+
+impl IntoStatus for h2::Error {
+    fn into_status(self) -> Status {
+        let code = self.reason()
+            .map(Code::from_reason)
+            .unwrap_or(Code::Internal);
+
+        Status::with_message(code, self.to_string())
+    }
+}
+
+// ===== impl GrpcBody =====
+
+impl<B> GrpcBody<B> {
+    /// Wraps `body`, ending it with a `grpc-status` of `Code::Ok` unless its
+    /// own trailers include a `grpc-status`.
+    pub fn new(body: B) -> Self {
+        GrpcBody {
+            inner: Some(body),
+            status: Some(Status::ok()),
+        }
+    }
+
+    /// Wraps `body`, ending it with `status` unless its own trailers include
+    /// a `grpc-status`.
+    pub fn with_status(body: B, status: Status) -> Self {
+        GrpcBody {
+            inner: Some(body),
+            status: Some(status),
+        }
+    }
+
+    /// Returns an empty body, for a "trailers-only" response.
+    pub fn empty() -> Self {
+        GrpcBody {
+            inner: None,
+            status: None,
+        }
+    }
+}
+
+impl<B: Body> Body for GrpcBody<B> {
+    type Data = B::Data;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_none() && self.status.is_none()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        match self.inner {
+            Some(ref mut inner) => inner.poll_data(),
+            None => Ok(Async::Ready(None)),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, h2::Error> {
+        let trailers = match self.inner {
+            Some(ref mut inner) => try_ready!(inner.poll_trailers()),
+            None => None,
+        };
+
+        self.inner = None;
+
+        let status = match self.status.take() {
+            Some(status) => status,
+            None => return Ok(Async::Ready(trailers)),
+        };
+
+        let mut trailers = trailers.unwrap_or_else(HeaderMap::new);
+
+        if !trailers.contains_key(GRPC_STATUS) {
+            status.add_to(&mut trailers);
+        }
+
+        Ok(Async::Ready(Some(trailers)))
+    }
+}
+
+impl<B> fmt::Debug for GrpcBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GrpcBody")
+            .field("status", &self.status)
+            .finish()
+    }
+}
+
+// ===== impl GrpcService =====
+
+impl<S> GrpcService<S> {
+    /// Wraps `inner`.
+    pub fn new(inner: S) -> Self {
+        GrpcService { inner }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S, B> Service for GrpcService<S>
+where S: Service<Request = Request<RecvBody>, Response = Response<B>>,
+      S::Error: IntoStatus,
+      B: Body,
+{
+    type Request = Request<RecvBody>;
+    type Response = Response<GrpcBody<B>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, request: Self::Request) -> Self::Future {
+        if !has_te_trailers(request.headers()) {
+            debug!("gRPC request without `te: trailers`; rejecting");
+
+            let status = Status::with_message(Code::Internal, "missing te: trailers");
+            return ResponseFuture {
+                inner: None,
+                status: Some(status),
+            };
+        }
+
+        ResponseFuture {
+            inner: Some(self.inner.call(request)),
+            status: None,
+        }
+    }
+}
+
+/// Returns `true` if the `te` header includes `trailers`.
+fn has_te_trailers(headers: &HeaderMap) -> bool {
+    headers.get_all(header::TE).iter().any(|value| {
+        value.to_str()
+            .map(|value| value.split(',').any(|te| te.trim() == "trailers"))
+            .unwrap_or(false)
+    })
+}
+
+// ===== impl NewGrpcService =====
+
+impl<N> NewGrpcService<N> {
+    /// Wraps each service produced by `inner` in a `GrpcService`.
+    pub fn new(inner: N) -> Self {
+        NewGrpcService { inner }
+    }
+}
+
+impl<N, B> NewService for NewGrpcService<N>
+where N: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      N::Error: IntoStatus,
+      B: Body,
+{
+    type Request = Request<RecvBody>;
+    type Response = Response<GrpcBody<B>>;
+    type Error = N::Error;
+    type Service = GrpcService<N::Service>;
+    type InitError = N::InitError;
+    type Future = NewServiceFuture<N::Future>;
+
+    fn new_service(&self) -> Self::Future {
+        NewServiceFuture { inner: self.inner.new_service() }
+    }
+}
+
+// ===== impl NewServiceFuture =====
+
+impl<F> Future for NewServiceFuture<F>
+where F: Future,
+{
+    type Item = GrpcService<F::Item>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let inner = try_ready!(self.inner.poll());
+        Ok(Async::Ready(GrpcService::new(inner)))
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where F: Future<Item = Response<B>>,
+      F::Error: IntoStatus,
+{
+    type Item = Response<GrpcBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Some(status) = self.status.take() {
+            return Ok(Async::Ready(status.into_response()));
+        }
+
+        let res = self.inner.as_mut().expect("polled after complete").poll();
+
+        match res {
+            Ok(Async::Ready(response)) => {
+                let (parts, body) = response.into_parts();
+                Ok(Async::Ready(Response::from_parts(parts, GrpcBody::new(body))))
+            }
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => {
+                let status = e.into_status();
+                debug!("gRPC call failed; {}", status);
+                Ok(Async::Ready(status.into_response()))
+            }
+        }
+    }
+}
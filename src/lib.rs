@@ -1,27 +1,70 @@
 extern crate bytes;
+#[cfg(feature = "compress")]
+extern crate flate2;
 #[macro_use]
 extern crate futures;
 extern crate h2;
 extern crate http;
+extern crate iovec;
 #[macro_use]
 extern crate log;
+#[cfg(feature = "tls")]
+extern crate rustls;
 extern crate tokio_connect;
 extern crate tokio_core;
+#[cfg(feature = "fs")]
+extern crate tokio_fs;
 extern crate tokio_io;
+#[cfg(feature = "tls")]
+extern crate tokio_rustls;
+extern crate tokio_timer;
 extern crate tower;
 
 pub mod client;
+#[cfg(feature = "compress")]
+pub mod compress;
+#[cfg(feature = "compress")]
+pub mod decompress;
+pub mod framed;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod mock;
+pub mod route;
 pub mod server;
 
 mod body;
+mod buf;
+mod collect;
+mod content_length;
+mod duplex;
+#[cfg(feature = "fs")]
+mod file_body;
 mod flush;
+mod limit;
+mod pool;
+mod reader;
 mod recv_body;
+mod reset;
+mod rewind;
+mod send_body;
 mod service;
+mod tee;
 
 pub use body::{Body, BoxBody, UnsyncBoxBody};
-pub use recv_body::{RecvBody, Data};
+pub use buf::SendBuf;
+pub use collect::{Collect, CollectError};
+pub use duplex::{Duplex, Upgraded};
+#[cfg(feature = "fs")]
+pub use file_body::FileBody;
+pub use flush::FlushStats;
+pub use limit::{LimitBody, LimitRecvBody};
+pub use pool::BufferPool;
+pub use reader::BodyReader;
+pub use recv_body::{RecvBody, RecvError, Data, ForwardBody};
+pub use send_body::{SendBody, BodySender, SendError};
 pub use server::Server;
 pub use service::HttpService;
+pub use tee::Tee;
 
 mod sealed {
     /// Private trait to this crate to prevent traits from being implemented in
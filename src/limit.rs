@@ -0,0 +1,190 @@
+use {Body, Data, RecvBody, RecvError};
+
+use bytes::{Buf, Bytes, IntoBuf};
+use futures::{Async, Poll, Stream};
+use h2::{self, Reason};
+use http::HeaderMap;
+
+/// Limits the total size of an outbound body.
+///
+/// If the wrapped body yields more than the limit, the body fails, and the
+/// stream is reset with `INTERNAL_ERROR`.
+#[derive(Debug)]
+pub struct LimitBody<B> {
+    inner: B,
+    remaining: u64,
+}
+
+/// Limits the total size of a received body.
+///
+/// If the remote sends more than the limit, the body fails. When receiving a
+/// request, the stream is also reset with `CANCEL`, so the remote stops
+/// sending.
+#[derive(Debug)]
+pub struct LimitRecvBody {
+    inner: RecvBody,
+    limit: u64,
+    remaining: u64,
+    exceeded: bool,
+}
+
+// ===== impl LimitBody =====
+
+impl<B> LimitBody<B> {
+    /// Limit `inner` to `max` bytes.
+    pub fn new(inner: B, max: u64) -> Self {
+        LimitBody {
+            inner,
+            remaining: max,
+        }
+    }
+
+    /// Returns a reference to the wrapped body.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Consumes the `LimitBody`, returning the wrapped body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B> Body for LimitBody<B>
+where B: Body,
+      <B::Data as IntoBuf>::Buf: 'static,
+{
+    type Data = <B::Data as IntoBuf>::Buf;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        let buf = match try_ready!(self.inner.poll_data()) {
+            Some(data) => data.into_buf(),
+            None => return Ok(Async::Ready(None)),
+        };
+
+        let len = buf.remaining() as u64;
+
+        if len > self.remaining {
+            debug!("body exceeds length limit; resetting stream");
+            return Err(Reason::INTERNAL_ERROR.into());
+        }
+
+        self.remaining -= len;
+        Ok(Async::Ready(Some(buf)))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, h2::Error> {
+        self.inner.poll_trailers()
+    }
+}
+
+// ===== impl LimitRecvBody =====
+
+impl LimitRecvBody {
+    /// Limit `inner` to `max` bytes.
+    pub fn new(inner: RecvBody, max: u64) -> Self {
+        LimitRecvBody {
+            inner,
+            limit: max,
+            remaining: max,
+            exceeded: false,
+        }
+    }
+
+    /// Returns the limit the body is held to.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Returns a reference to the wrapped body.
+    pub fn get_ref(&self) -> &RecvBody {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped body.
+    pub fn get_mut(&mut self) -> &mut RecvBody {
+        &mut self.inner
+    }
+
+    /// Consumes the `LimitRecvBody`, returning the wrapped body.
+    pub fn into_inner(self) -> RecvBody {
+        self.inner
+    }
+
+    /// Returns `true`, and resets the stream, if `len` more bytes exceed the
+    /// limit.
+    fn exceeds(&mut self, len: usize) -> bool {
+        let len = len as u64;
+
+        if len > self.remaining {
+            debug!("received body exceeds length limit; limit={}", self.limit);
+            self.exceeded = true;
+            self.inner.reset(Reason::CANCEL);
+            return true;
+        }
+
+        self.remaining -= len;
+        false
+    }
+}
+
+impl Body for LimitRecvBody {
+    type Data = Data;
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        if self.exceeded {
+            return Err(Reason::CANCEL.into());
+        }
+
+        let data = match try_ready!(self.inner.poll_data()) {
+            Some(data) => data,
+            None => return Ok(Async::Ready(None)),
+        };
+
+        if self.exceeds(data.remaining()) {
+            return Err(Reason::CANCEL.into());
+        }
+
+        Ok(Async::Ready(Some(data)))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, h2::Error> {
+        if self.exceeded {
+            return Err(Reason::CANCEL.into());
+        }
+
+        self.inner.poll_trailers()
+    }
+}
+
+/// Yields the body's data chunks as `Bytes`, like the `Stream` implementation
+/// of `RecvBody`.
+impl Stream for LimitRecvBody {
+    type Item = Bytes;
+    type Error = RecvError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.exceeded {
+            return Err(RecvError::length_limit());
+        }
+
+        let data = match try_ready!(self.inner.poll()) {
+            Some(bytes) => bytes,
+            None => return Ok(Async::Ready(None)),
+        };
+
+        if self.exceeds(data.len()) {
+            return Err(RecvError::length_limit());
+        }
+
+        Ok(Async::Ready(Some(data)))
+    }
+}
@@ -0,0 +1,379 @@
+//! In-memory transports for testing services without sockets.
+//!
+//! `duplex` returns a pair of connected transports, and `TestClient` sends
+//! requests to a `Server` over one of them, so that services, and the
+//! behavior of the server itself, may be tested deterministically:
+//!
+//! ```rust,ignore
+//! let client = TestClient::connect(&server, executor.clone());
+//! let response = client.and_then(|mut client| client.request(request));
+//! ```
+
+use {Body, RecvBody};
+use client;
+use server::{self, Server};
+
+use bytes::{Buf, BufMut, BytesMut};
+use futures::{Async, Future, Poll};
+use futures::future::Executor;
+use futures::task::{self, Task};
+use http::{Request, Response};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tower::{NewService, Service};
+
+use std::{cmp, error, fmt, io};
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+/// One end of an in-memory, bidirectional transport.
+///
+/// Created by `duplex`. Bytes written to one end are read from the other.
+/// Dropping or shutting down one end ends the stream read by the other.
+pub struct Duplex {
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+/// A client that sends requests to a `Server` over a `Duplex`.
+pub struct TestClient<E, B>
+where B: Body,
+{
+    inner: client::Connection<Duplex, E, B>,
+}
+
+/// Completes with a `TestClient` once its connection is established.
+pub struct Connect<E, B>
+where B: Body,
+{
+    inner: Result<client::Handshake<Duplex, E, B>, Option<ConnectError>>,
+}
+
+/// Error produced when connecting a `TestClient`.
+#[derive(Debug)]
+pub enum ConnectError {
+    /// The server's connection task could not be spawned.
+    Execute,
+
+    /// The HTTP/2.0 handshake failed.
+    Handshake(client::HandshakeError),
+}
+
+/// Task that drives the server's end of a `TestClient`'s connection.
+pub struct ServerTask<S, E, B>
+where S: NewService,
+      B: Body,
+{
+    connection: server::Connection<Duplex, S, E, B, ()>,
+}
+
+/// A buffer of bytes written to one end of a `Duplex`.
+struct Pipe {
+    buf: BytesMut,
+    closed: bool,
+    reader: Option<Task>,
+}
+
+/// Returns a pair of connected in-memory transports.
+pub fn duplex() -> (Duplex, Duplex) {
+    let a = Arc::new(Mutex::new(Pipe::new()));
+    let b = Arc::new(Mutex::new(Pipe::new()));
+
+    let one = Duplex { read: a.clone(), write: b.clone() };
+    let two = Duplex { read: b, write: a };
+
+    (one, two)
+}
+
+// ===== impl Duplex =====
+
+impl Read for Duplex {
+    fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        let mut pipe = self.read.lock().unwrap();
+
+        if pipe.buf.is_empty() {
+            if pipe.closed {
+                return Ok(0);
+            }
+
+            pipe.reader = Some(task::current());
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let n = cmp::min(dst.len(), pipe.buf.len());
+        dst[..n].copy_from_slice(&pipe.buf.split_to(n));
+        Ok(n)
+    }
+}
+
+impl Write for Duplex {
+    fn write(&mut self, src: &[u8]) -> io::Result<usize> {
+        let mut pipe = self.write.lock().unwrap();
+
+        if pipe.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+
+        pipe.buf.reserve(src.len());
+        pipe.buf.put_slice(src);
+        pipe.notify();
+
+        Ok(src.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for Duplex {}
+
+impl AsyncWrite for Duplex {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.write.lock().unwrap().close();
+        Ok(Async::Ready(()))
+    }
+
+    fn write_buf<B: Buf>(&mut self, buf: &mut B) -> Poll<usize, io::Error> {
+        let n = self.write(buf.bytes())?;
+        buf.advance(n);
+        Ok(Async::Ready(n))
+    }
+}
+
+impl Drop for Duplex {
+    fn drop(&mut self) {
+        self.write.lock().unwrap().close();
+        self.read.lock().unwrap().close();
+    }
+}
+
+impl fmt::Debug for Duplex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Duplex")
+            .field("readable", &self.read.lock().unwrap().buf.len())
+            .finish()
+    }
+}
+
+// ===== impl Pipe =====
+
+impl Pipe {
+    fn new() -> Self {
+        Pipe {
+            buf: BytesMut::new(),
+            closed: false,
+            reader: None,
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        self.notify();
+    }
+
+    fn notify(&mut self) {
+        if let Some(task) = self.reader.take() {
+            task.notify();
+        }
+    }
+}
+
+// ===== impl TestClient =====
+
+impl<E, B> TestClient<E, B>
+where E: Executor<client::Background<Duplex, B>> + Clone,
+      B: Body + 'static,
+{
+    /// Connect a new client to `server`.
+    ///
+    /// The server's end of the connection is spawned onto `executor`, which
+    /// is also used to spawn the client's tasks.
+    pub fn connect<S, SB>(server: &Server<S, E, SB>, executor: E) -> Connect<E, B>
+    where S: NewService<Request = Request<RecvBody>, Response = Response<SB>>,
+          SB: Body,
+          E: Executor<ServerTask<S, E, SB>>,
+    {
+        let (client, io) = duplex();
+
+        let task = ServerTask { connection: server.serve(io) };
+
+        let inner = match executor.execute(task) {
+            Ok(()) => Ok(client::Connection::handshake(client, executor)),
+            Err(_) => Err(Some(ConnectError::Execute)),
+        };
+
+        Connect { inner }
+    }
+
+    /// Send `request` to the server, without waiting for the connection to be
+    /// ready.
+    pub fn request(&mut self, request: Request<B>) -> client::ResponseFuture {
+        self.inner.call(request)
+    }
+}
+
+impl<E, B> Service for TestClient<E, B>
+where E: Executor<client::Background<Duplex, B>>,
+      B: Body + 'static,
+{
+    type Request = Request<B>;
+    type Response = Response<RecvBody>;
+    type Error = client::Error;
+    type Future = client::ResponseFuture;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, request: Self::Request) -> Self::Future {
+        self.inner.call(request)
+    }
+}
+
+// ===== impl Connect =====
+
+impl<E, B> Future for Connect<E, B>
+where E: Executor<client::Background<Duplex, B>> + Clone,
+      B: Body,
+{
+    type Item = TestClient<E, B>;
+    type Error = ConnectError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.inner {
+            Ok(ref mut handshake) => {
+                let inner = try_ready!(handshake.poll().map_err(ConnectError::Handshake));
+                Ok(Async::Ready(TestClient { inner }))
+            }
+            Err(ref mut e) => Err(e.take().expect("polled after error")),
+        }
+    }
+}
+
+// ===== impl ConnectError =====
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConnectError::Execute =>
+                write!(f, "Error spawning the server's connection task"),
+            ConnectError::Handshake(ref why) =>
+                write!(f, "Error while performing HTTP/2.0 handshake: {}", why),
+        }
+    }
+}
+
+impl error::Error for ConnectError {
+    fn description(&self) -> &str {
+        match *self {
+            ConnectError::Execute => "error spawning the server's connection task",
+            ConnectError::Handshake(_) => "error performing HTTP/2.0 handshake",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            ConnectError::Execute => None,
+            ConnectError::Handshake(ref why) => Some(why),
+        }
+    }
+}
+
+// ===== impl ServerTask =====
+
+impl<S, E, B> Future for ServerTask<S, E, B>
+where S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      E: Executor<server::Background<<S::Service as Service>::Future, B>>,
+      B: Body + 'static,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        self.connection.poll().map_err(|_| {
+            debug!("test server connection failed");
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TestClient;
+    use RecvBody;
+    use server::{Builder, Server};
+
+    use futures::{future, Async, Future, Poll};
+    use h2;
+    use http::{Request, Response, StatusCode};
+    use tokio_core::reactor::Core;
+    use tower::{NewService, Service};
+
+    use std::io;
+
+    struct Hello;
+
+    impl Service for Hello {
+        type Request = Request<RecvBody>;
+        type Response = Response<&'static [u8]>;
+        type Error = h2::Error;
+        type Future = future::FutureResult<Self::Response, h2::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), h2::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, request: Self::Request) -> Self::Future {
+            assert_eq!(request.uri().path(), "/hello");
+            future::ok(Response::new(&b"hello"[..]))
+        }
+    }
+
+    impl NewService for Hello {
+        type Request = Request<RecvBody>;
+        type Response = Response<&'static [u8]>;
+        type Error = h2::Error;
+        type InitError = io::Error;
+        type Service = Hello;
+        type Future = future::FutureResult<Hello, io::Error>;
+
+        fn new_service(&self) -> Self::Future {
+            future::ok(Hello)
+        }
+    }
+
+    #[test]
+    fn test_client_round_trip() {
+        let mut core = Core::new().unwrap();
+        let server = Server::new(Hello, Builder::new(), core.handle());
+
+        let request = Request::builder()
+            .uri("http://test.example/hello")
+            .body(())
+            .unwrap();
+
+        let body = TestClient::connect(&server, core.handle())
+            .map_err(|e| format!("connect failed: {:?}", e))
+            .and_then(move |mut client| {
+                let response = client.request(request);
+
+                // Keep the client until the body is received.
+                response
+                    .map_err(|e| format!("request failed: {:?}", e))
+                    .map(move |response| (client, response))
+            })
+            .and_then(|(client, response)| {
+                assert_eq!(response.status(), StatusCode::OK);
+
+                response.into_body()
+                    .collect(1024)
+                    .map_err(|e| format!("body failed: {:?}", e))
+                    .map(move |body| {
+                        drop(client);
+                        body
+                    })
+            });
+
+        let body = core.run(body).unwrap();
+        assert_eq!(&body[..], b"hello");
+    }
+}
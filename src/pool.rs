@@ -0,0 +1,41 @@
+use bytes::BytesMut;
+
+use std::sync::Arc;
+
+/// Provides the buffers this crate allocates for body data.
+///
+/// A pool is used for the buffers that response chunks are coalesced into,
+/// and for the buffer that `RecvBody::collect` accumulates a request body in.
+/// This allows applications to hand out pooled, pre-sized buffers instead of
+/// allocating a new one for each body.
+///
+/// Buffers that are still owned by this crate once it is done with them,
+/// such as a partially used coalescing buffer, are returned with `recycle`.
+pub trait BufferPool: Send + Sync {
+    /// Returns an empty buffer with room for at least `capacity` bytes.
+    fn get(&self, capacity: usize) -> BytesMut;
+
+    /// Returns a buffer that is no longer needed to the pool.
+    ///
+    /// By default, the buffer is dropped.
+    fn recycle(&self, buf: BytesMut) {
+        drop(buf);
+    }
+}
+
+/// A `BufferPool` shared by the streams of a server.
+pub(crate) type SharedPool = Arc<BufferPool>;
+
+/// Returns a buffer with room for `capacity` bytes, from `pool` if there is
+/// one.
+pub(crate) fn get(pool: Option<&SharedPool>, capacity: usize) -> BytesMut {
+    match pool {
+        Some(pool) => {
+            let mut buf = pool.get(capacity);
+            buf.clear();
+            buf.reserve(capacity);
+            buf
+        }
+        None => BytesMut::with_capacity(capacity),
+    }
+}
@@ -0,0 +1,88 @@
+use {Data, RecvBody};
+
+use bytes::Buf;
+use futures::Async;
+use tokio_io::AsyncRead;
+
+use std::{cmp, fmt, io};
+
+/// Reads the data of a `RecvBody` as a byte stream.
+///
+/// Created by `RecvBody::into_reader`, so that a body may be fed to anything
+/// that reads from an `AsyncRead`, such as a `tokio_io::codec::FramedRead`.
+///
+/// Flow control capacity is released as the data is read, so the remote only
+/// sends as fast as the body is read. Reads fail with an error of kind
+/// `Other` wrapping the `RecvError` if receiving the body fails, and return
+/// `WouldBlock` while no data is available. Trailers are not read.
+pub struct BodyReader {
+    body: RecvBody,
+
+    /// The chunk currently being read, if it has data left.
+    chunk: Option<Data>,
+}
+
+// ===== impl RecvBody =====
+
+impl RecvBody {
+    /// Returns an `AsyncRead` that reads the data of the body.
+    pub fn into_reader(self) -> BodyReader {
+        BodyReader {
+            body: self,
+            chunk: None,
+        }
+    }
+}
+
+// ===== impl BodyReader =====
+
+impl BodyReader {
+    /// Returns the body being read.
+    ///
+    /// Data of the chunk currently being read that has not been read yet is
+    /// lost.
+    pub fn into_inner(self) -> RecvBody {
+        self.body
+    }
+}
+
+impl io::Read for BodyReader {
+    fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        if dst.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            if let Some(ref mut chunk) = self.chunk {
+                if chunk.has_remaining() {
+                    let n = cmp::min(dst.len(), chunk.remaining());
+                    chunk.copy_to_slice(&mut dst[..n]);
+                    return Ok(n);
+                }
+            }
+
+            // Drop the exhausted chunk before polling for the next one.
+            self.chunk = None;
+
+            let res = self.body.poll_recv()
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e));
+
+            match res? {
+                Async::Ready(Some(data)) => self.chunk = Some(data),
+                Async::Ready(None) => return Ok(0),
+                Async::NotReady => return Err(io::ErrorKind::WouldBlock.into()),
+            }
+        }
+    }
+}
+
+impl AsyncRead for BodyReader {}
+
+impl fmt::Debug for BodyReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BodyReader")
+            .field("body", &self.body)
+            .field("chunk", &self.chunk.as_ref().map(Buf::remaining))
+            .finish()
+    }
+}
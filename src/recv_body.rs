@@ -1,56 +1,410 @@
-use Body;
+use {Body, Collect, LimitRecvBody, Tee};
+use pool::SharedPool;
+use reset::Reset;
+use server::drain::Watch;
 use bytes::{Bytes, BytesMut, Buf};
-use futures::{Poll, Stream};
-use h2;
+use futures::{Async, Future, Poll, Stream};
+use h2::{self, Reason};
 use http;
+use tokio_timer::{Sleep, Timer};
+
+use std::{error, fmt};
+use std::time::Duration;
 
 /// Allows a stream to be read from the remote.
 #[derive(Debug, Default)]
 pub struct RecvBody {
     inner: Option<h2::RecvStream>,
+
+    auto_release: bool,
+    idle: Option<IdleTimeout>,
+    content_length: Option<ContentLength>,
+    declared_length: Option<u64>,
+    failed: Option<Failure>,
+    reset: Option<Reset>,
+    pool: Option<SharedPool>,
+    drain: Option<Drain>,
+}
+
+/// Resets the stream if no data is received for too long.
+struct IdleTimeout {
+    timer: Timer,
+    duration: Duration,
+    sleep: Sleep,
+    reset: Reset,
+}
+
+/// Resets the stream if the body does not match its `content-length` header.
+#[derive(Debug)]
+struct ContentLength {
+    remaining: u64,
+    reset: Reset,
+}
+
+/// Resets the stream if the server is drained before the body ends.
+#[derive(Debug)]
+struct Drain {
+    watch: Watch,
+    reset: Reset,
+}
+
+/// Why a `RecvBody` failed on its own, rather than because of the stream.
+#[derive(Clone, Copy, Debug)]
+enum Failure {
+    IdleTimeout,
+    ContentLength,
+    Drained,
 }
 
+/// A `RecvBody` that is forwarded as the body of an outbound request or
+/// response.
+///
+/// Created by `RecvBody::into_forward`.
+#[derive(Debug)]
+pub struct ForwardBody {
+    inner: RecvBody,
+}
+
+/// A chunk of data received from the remote.
+///
+/// Flow control capacity is released back to the remote as the chunk is
+/// consumed, or when it is dropped, unless the `RecvBody` that produced it
+/// released the capacity on receipt.
 #[derive(Debug)]
 pub struct Data {
-    release_capacity: h2::ReleaseCapacity,
+    release_capacity: Option<h2::ReleaseCapacity>,
     bytes: Bytes,
 }
 
+/// Error produced when receiving a body from the remote.
+#[derive(Debug)]
+pub struct RecvError {
+    kind: Kind,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Inner(h2::Error),
+    IdleTimeout,
+    ContentLength,
+    LengthLimit,
+    Drained,
+}
+
 // ===== impl RecvBody =====
 
 impl RecvBody {
     /// Return a new `RecvBody`.
     pub(crate) fn new(inner: h2::RecvStream) -> Self {
-        RecvBody { inner: Some(inner) }
+        RecvBody {
+            inner: Some(inner),
+            auto_release: false,
+            idle: None,
+            content_length: None,
+            declared_length: None,
+            failed: None,
+            reset: None,
+            pool: None,
+            drain: None,
+        }
     }
-}
 
-impl Body for RecvBody {
-    type Data = Data;
+    /// Allow the body to reset the stream via `reset`.
+    pub(crate) fn set_reset(&mut self, reset: Reset) {
+        self.reset = Some(reset);
+    }
 
-    #[inline]
-    fn is_end_stream(&self) -> bool {
+    /// Obtain the buffers used to collect the body from `pool`.
+    pub(crate) fn set_buffer_pool(&mut self, pool: SharedPool) {
+        self.pool = Some(pool);
+    }
+
+    pub(crate) fn buffer_pool(&self) -> Option<&SharedPool> {
+        self.pool.as_ref()
+    }
+
+    /// Request that the stream be reset with `reason`, if the body is able to
+    /// reset it.
+    ///
+    /// Returns `false` if it is not, as is the case for response bodies.
+    pub(crate) fn reset(&self, reason: Reason) -> bool {
+        match self.reset {
+            Some(ref reset) => {
+                reset.reset(reason);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reset the stream via `reset` if no data is received for `duration`.
+    pub(crate) fn set_idle_timeout(&mut self, timer: Timer, duration: Duration, reset: Reset) {
+        let sleep = timer.sleep(duration);
+
+        self.idle = Some(IdleTimeout {
+            timer,
+            duration,
+            sleep,
+            reset,
+        });
+    }
+
+    /// Fail and reset the stream via `reset` if a drain is signaled on
+    /// `watch` before the body ends.
+    pub(crate) fn set_drain(&mut self, watch: Watch, reset: Reset) {
+        self.drain = Some(Drain { watch, reset });
+    }
+
+    /// Fail and reset the stream via `reset` if more or fewer than `len` bytes
+    /// are received.
+    pub(crate) fn set_content_length(&mut self, len: u64, reset: Reset) {
+        self.declared_length = Some(len);
+        self.content_length = Some(ContentLength {
+            remaining: len,
+            reset,
+        });
+    }
+
+    /// Report `len` as the declared length of the body, without validating
+    /// it.
+    ///
+    /// This is used for responses, whose body may be empty regardless of
+    /// their `content-length`, such as responses to `HEAD` requests.
+    pub(crate) fn set_declared_length(&mut self, len: u64) {
+        self.declared_length = Some(len);
+    }
+
+    /// Returns the length of the body declared by its `content-length`
+    /// header, if it had a valid one.
+    ///
+    /// A request body fails if it does not match the declared length, so this
+    /// may be used to preallocate a buffer for the body.
+    pub fn content_length(&self) -> Option<u64> {
+        self.declared_length
+    }
+
+    /// Returns `true` if the remote has ended the stream, so that no more data
+    /// or trailers will be received.
+    ///
+    /// This may be used to skip reading an empty body.
+    pub fn is_end_stream(&self) -> bool {
         match self.inner {
             Some(ref inner) => inner.is_end_stream(),
             None => true,
         }
     }
 
-    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
-        match self.inner {
-            Some(ref mut inner) => {
-                let data = try_ready!(inner.poll())
-                    .map(|bytes| {
-                        Data {
-                            release_capacity: inner.release_capacity().clone(),
-                            bytes,
-                        }
-                    });
-
-                Ok(data.into())
+    /// Fails if the idle timeout has elapsed, requesting that the stream be
+    /// reset.
+    fn poll_idle_timeout(&mut self) -> Result<(), h2::Error> {
+        let res = match self.idle {
+            Some(ref mut idle) => idle.sleep.poll(),
+            None => return Ok(()),
+        };
+
+        match res {
+            Ok(Async::Ready(())) => {
+                let idle = self.idle.take().expect("idle timeout");
+                debug!("no data received for {:?}; resetting stream", idle.duration);
+                idle.reset.reset(Reason::CANCEL);
+                self.failed = Some(Failure::IdleTimeout);
+                Err(Reason::CANCEL.into())
             }
-            None => Ok(None.into()),
+            Ok(Async::NotReady) => Ok(()),
+            Err(e) => {
+                warn!("idle timer failed; ignoring timeout: {:?}", e);
+                self.idle = None;
+                Ok(())
+            }
+        }
+    }
+
+    /// Fails if a drain has been signaled, requesting that the stream be
+    /// reset.
+    fn poll_drain(&mut self) -> Result<(), h2::Error> {
+        let signaled = match self.drain {
+            Some(ref mut drain) => drain.watch.poll_signal(),
+            None => return Ok(()),
+        };
+
+        if signaled {
+            let drain = self.drain.take().expect("drain");
+            debug!("drain signaled before the body ended; resetting stream");
+            drain.reset.reset(Reason::CANCEL);
+            self.failed = Some(Failure::Drained);
+            return Err(Reason::CANCEL.into());
         }
+
+        Ok(())
+    }
+
+    /// Fails if `len` more bytes exceed the declared `content-length`, or, if
+    /// the body has ended, if fewer bytes than declared were received.
+    fn check_content_length(&mut self, len: usize, eos: bool) -> Result<(), h2::Error> {
+        let mismatch = match self.content_length {
+            Some(ref mut content_length) => {
+                let len = len as u64;
+
+                if len > content_length.remaining {
+                    true
+                } else {
+                    content_length.remaining -= len;
+                    eos && content_length.remaining > 0
+                }
+            }
+            None => return Ok(()),
+        };
+
+        if mismatch {
+            let content_length = self.content_length.take().expect("content length");
+            debug!("request body does not match content-length; resetting stream");
+            content_length.reset.reset(Reason::PROTOCOL_ERROR);
+            self.failed = Some(Failure::ContentLength);
+            return Err(Reason::PROTOCOL_ERROR.into());
+        }
+
+        Ok(())
+    }
+
+    /// Configures whether flow control capacity is released as soon as each
+    /// chunk is received.
+    ///
+    /// By default, capacity is held by each `Data` chunk until it is consumed
+    /// or dropped, so the remote stops sending when the service stops reading.
+    /// When enabled, the stream and connection windows are replenished
+    /// immediately, regardless of how long the service holds onto the chunks.
+    pub fn set_auto_release_capacity(&mut self, enabled: bool) {
+        self.auto_release = enabled;
+    }
+
+    /// Returns `true` if flow control capacity is released as soon as each
+    /// chunk is received.
+    pub fn is_auto_release_capacity(&self) -> bool {
+        self.auto_release
+    }
+
+    /// Returns a future that buffers the entire body into a single `Bytes`.
+    ///
+    /// The future fails with `CollectError::BodyTooLarge` if the body is
+    /// larger than `max` bytes.
+    pub fn collect(self, max: usize) -> Collect {
+        Collect::new(self, max)
+    }
+
+    /// Split the body into two bodies that each yield all of its data and
+    /// trailers.
+    ///
+    /// This allows a body to be inspected, for example hashed or logged, while
+    /// it is also handed to a service. Either body may read up to `max_buffer`
+    /// bytes ahead of the other before it waits for the other to catch up.
+    pub fn tee(self, max_buffer: usize) -> (Tee, Tee) {
+        Tee::new(self, max_buffer)
+    }
+
+    /// Returns a body that forwards this one onto another stream, for example
+    /// by a proxy forwarding a request to a `client::Connection`.
+    ///
+    /// Errors are mapped to the reason the outbound stream should be reset
+    /// with: the reason the remote reset the stream with is forwarded, unless
+    /// it describes an error of the inbound connection itself, such as a
+    /// `PROTOCOL_ERROR`, in which case `CANCEL` is used.
+    pub fn into_forward(self) -> ForwardBody {
+        ForwardBody { inner: self }
+    }
+
+    /// Limit the body to `max` bytes.
+    ///
+    /// See `LimitRecvBody` for details.
+    pub fn limit(self, max: u64) -> LimitRecvBody {
+        LimitRecvBody::new(self, max)
+    }
+
+    /// Polls for the next chunk, failing with the error of the stream
+    /// itself.
+    pub(crate) fn poll_chunk(&mut self) -> Poll<Option<Data>, h2::Error> {
+        if let Some(failure) = self.failed {
+            return Err(failure.reason().into());
+        }
+
+        self.poll_drain()?;
+
+        let res = match self.inner {
+            Some(ref mut inner) => inner.poll()?,
+            None => return Ok(None.into()),
+        };
+
+        let data = match res {
+            Async::Ready(Some(bytes)) => bytes,
+            Async::Ready(None) => {
+                self.idle = None;
+                self.drain = None;
+                self.check_content_length(0, true)?;
+                return Ok(None.into());
+            }
+            Async::NotReady => {
+                self.poll_idle_timeout()?;
+                return Ok(Async::NotReady);
+            }
+        };
+
+        self.check_content_length(data.len(), false)?;
+
+        if let Some(ref mut idle) = self.idle {
+            idle.sleep = idle.timer.sleep(idle.duration);
+        }
+
+        let auto_release = self.auto_release;
+        let inner = self.inner.as_mut().expect("polled above");
+
+        let release_capacity = if auto_release {
+            trace!("releasing capacity on receipt: {}", data.len());
+            inner.release_capacity()
+                .release_capacity(data.len())
+                .expect("flow control error");
+            None
+        } else {
+            Some(inner.release_capacity().clone())
+        };
+
+        Ok(Some(Data { release_capacity, bytes: data }).into())
+    }
+
+    /// Polls for the next chunk, failing with a `RecvError` that describes
+    /// why the body failed.
+    pub(crate) fn poll_recv(&mut self) -> Poll<Option<Data>, RecvError> {
+        match self.poll_chunk() {
+            Ok(ready) => Ok(ready),
+            Err(e) => {
+                let kind = match self.failed {
+                    Some(Failure::IdleTimeout) => Kind::IdleTimeout,
+                    Some(Failure::ContentLength) => Kind::ContentLength,
+                    Some(Failure::Drained) => Kind::Drained,
+                    None => Kind::Inner(e),
+                };
+
+                Err(RecvError { kind })
+            }
+        }
+    }
+}
+
+/// Each `Data` chunk holds its flow control capacity until it has been
+/// consumed, so when the body is sent as the body of another stream, the
+/// remote is only allowed to send as fast as the body is forwarded.
+///
+/// Errors are those of the stream itself. To forward a body onto another
+/// stream, use `RecvBody::into_forward`, which maps them to the reason the
+/// outbound stream should be reset with.
+impl Body for RecvBody {
+    type Data = Data;
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        RecvBody::is_end_stream(self)
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        self.poll_chunk()
     }
 
     fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, h2::Error> {
@@ -61,8 +415,102 @@ impl Body for RecvBody {
     }
 }
 
+// ===== impl ForwardBody =====
+
+impl ForwardBody {
+    /// Returns the body being forwarded.
+    pub fn into_inner(self) -> RecvBody {
+        self.inner
+    }
+}
+
+/// Trailers are forwarded as well as data.
+impl Body for ForwardBody {
+    type Data = Data;
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, h2::Error> {
+        self.inner.poll_data().map_err(forward_error)
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, h2::Error> {
+        self.inner.poll_trailers().map_err(forward_error)
+    }
+}
+
+/// Maps an error of a body being forwarded to the error that the stream it is
+/// forwarded onto fails with.
+fn forward_error(err: h2::Error) -> h2::Error {
+    // Reasons that only concern the connection the body was received on.
+    const INBOUND: &[Reason] = &[
+        Reason::PROTOCOL_ERROR,
+        Reason::FLOW_CONTROL_ERROR,
+        Reason::SETTINGS_TIMEOUT,
+        Reason::STREAM_CLOSED,
+        Reason::FRAME_SIZE_ERROR,
+        Reason::COMPRESSION_ERROR,
+        Reason::CONNECT_ERROR,
+        Reason::HTTP_1_1_REQUIRED,
+    ];
+
+    match err.reason() {
+        Some(reason) if !INBOUND.contains(&reason) => err,
+        _ => Reason::CANCEL.into(),
+    }
+}
+
+/// Yields the body's data chunks as `Bytes`, releasing flow control capacity
+/// as each chunk is yielded.
+///
+/// Trailers are not yielded by the stream; use `Body::poll_trailers` once the
+/// stream has completed to obtain them.
+impl Stream for RecvBody {
+    type Item = Bytes;
+    type Error = RecvError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let data = try_ready!(self.poll_recv());
+        Ok(data.map(Bytes::from).into())
+    }
+}
+
+// ===== impl IdleTimeout =====
+
+impl fmt::Debug for IdleTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IdleTimeout")
+            .field("duration", &self.duration)
+            .finish()
+    }
+}
+
+// ===== impl Failure =====
+
+impl Failure {
+    fn reason(&self) -> Reason {
+        match *self {
+            Failure::IdleTimeout => Reason::CANCEL,
+            Failure::ContentLength => Reason::PROTOCOL_ERROR,
+            Failure::Drained => Reason::CANCEL,
+        }
+    }
+}
+
 // ===== impl Data =====
 
+impl Data {
+    fn release(&mut self, sz: usize) {
+        if let Some(ref mut release_capacity) = self.release_capacity {
+            release_capacity.release_capacity(sz)
+                .expect("flow control error");
+        }
+    }
+}
+
 impl Buf for Data {
     fn remaining(&self) -> usize {
         self.bytes.len()
@@ -80,8 +528,7 @@ impl Buf for Data {
         trace!("releasing capacity: {} of {}", cnt, self.remaining());
         let _ = self.bytes.split_to(cnt);
 
-        self.release_capacity.release_capacity(cnt)
-            .expect("flow control error")
+        self.release(cnt);
     }
 }
 
@@ -89,9 +536,7 @@ impl Drop for Data {
     fn drop(&mut self) {
         let sz = self.remaining();
         trace!("Data::drop: releasing capacity: {}", sz);
-        self.release_capacity
-            .release_capacity(sz)
-            .expect("flow control error");
+        self.release(sz);
     }
 }
 
@@ -99,8 +544,7 @@ impl From<Data> for Bytes {
     fn from(mut src: Data) -> Self {
         let bytes = ::std::mem::replace(&mut src.bytes, Bytes::new());
 
-        src.release_capacity.release_capacity(bytes.len())
-            .expect("flow control error");
+        src.release(bytes.len());
 
         bytes
     }
@@ -110,9 +554,108 @@ impl From<Data> for BytesMut {
     fn from(mut src: Data) -> Self {
         let bytes = ::std::mem::replace(&mut src.bytes, Bytes::new());
 
-        src.release_capacity.release_capacity(bytes.len())
-            .expect("flow control error");
+        src.release(bytes.len());
 
         bytes.into()
     }
 }
+
+// ===== impl RecvError =====
+
+impl RecvError {
+    /// Returns the reason the stream was reset, if it was.
+    pub fn reason(&self) -> Option<h2::Reason> {
+        match self.kind {
+            Kind::Inner(ref h2) => h2.reason(),
+            Kind::IdleTimeout => Some(Reason::CANCEL),
+            Kind::ContentLength => Some(Reason::PROTOCOL_ERROR),
+            Kind::LengthLimit => Some(Reason::CANCEL),
+            Kind::Drained => Some(Reason::CANCEL),
+        }
+    }
+
+    /// Returns `true` if the stream was reset because no data was received
+    /// within the stream idle timeout.
+    pub fn is_idle_timeout(&self) -> bool {
+        match self.kind {
+            Kind::IdleTimeout => true,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the stream was reset because the body did not match
+    /// its `content-length` header.
+    pub fn is_content_length(&self) -> bool {
+        match self.kind {
+            Kind::ContentLength => true,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the body was larger than the limit of a
+    /// `LimitRecvBody`.
+    pub fn is_length_limit(&self) -> bool {
+        match self.kind {
+            Kind::LengthLimit => true,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the stream was reset because the server was drained
+    /// before the body ended.
+    pub fn is_drained(&self) -> bool {
+        match self.kind {
+            Kind::Drained => true,
+            _ => false,
+        }
+    }
+
+    pub(crate) fn length_limit() -> Self {
+        RecvError { kind: Kind::LengthLimit }
+    }
+}
+
+impl From<h2::Error> for RecvError {
+    fn from(src: h2::Error) -> Self {
+        RecvError { kind: Kind::Inner(src) }
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            Kind::Inner(ref h2) =>
+                write!(f, "Error receiving body: {}", h2),
+            Kind::IdleTimeout =>
+                write!(f, "Timed out waiting for body data"),
+            Kind::ContentLength =>
+                write!(f, "Error receiving body: body does not match content-length"),
+            Kind::LengthLimit =>
+                write!(f, "Error receiving body: body exceeds length limit"),
+            Kind::Drained =>
+                write!(f, "Error receiving body: server drained before body ended"),
+        }
+    }
+}
+
+impl error::Error for RecvError {
+    fn cause(&self) -> Option<&error::Error> {
+        match self.kind {
+            Kind::Inner(ref h2) => Some(h2),
+            Kind::IdleTimeout => None,
+            Kind::ContentLength => None,
+            Kind::LengthLimit => None,
+            Kind::Drained => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match self.kind {
+            Kind::Inner(_) => "error receiving body",
+            Kind::IdleTimeout => "timed out waiting for body data",
+            Kind::ContentLength => "body does not match content-length",
+            Kind::LengthLimit => "body exceeds length limit",
+            Kind::Drained => "server drained before body ended",
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use futures::task::AtomicTask;
+use h2::Reason;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Allows a stream to be reset by something other than the task that owns its
+/// sending half.
+///
+/// The owning task polls `poll_reset` and resets the stream once a reset has
+/// been requested.
+#[derive(Clone)]
+pub(crate) struct Reset {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    reason: Mutex<Option<Reason>>,
+    task: AtomicTask,
+}
+
+// ===== impl Reset =====
+
+impl Reset {
+    pub fn new() -> Self {
+        Reset {
+            inner: Arc::new(Inner {
+                reason: Mutex::new(None),
+                task: AtomicTask::new(),
+            }),
+        }
+    }
+
+    /// Request that the stream be reset with `reason`.
+    ///
+    /// Only the first requested reason is used.
+    pub fn reset(&self, reason: Reason) {
+        {
+            let mut current = self.inner.reason.lock().unwrap();
+
+            if current.is_some() {
+                return;
+            }
+
+            *current = Some(reason);
+        }
+
+        self.inner.task.notify();
+    }
+
+    /// Returns the reason the stream should be reset with, if a reset has been
+    /// requested.
+    ///
+    /// Otherwise, the current task is notified once a reset is requested.
+    pub fn poll_reset(&self) -> Option<Reason> {
+        self.inner.task.register();
+        *self.inner.reason.lock().unwrap()
+    }
+
+    /// Returns `true` if a reset has been requested, without registering the
+    /// current task.
+    pub fn is_reset(&self) -> bool {
+        self.inner.reason.lock().unwrap().is_some()
+    }
+}
+
+impl fmt::Debug for Reset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Reset")
+            .field("reason", &*self.inner.reason.lock().unwrap())
+            .finish()
+    }
+}
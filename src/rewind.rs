@@ -0,0 +1,71 @@
+use bytes::{Buf, Bytes};
+use futures::Poll;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use std::io::{self, Read, Write};
+
+/// Wraps a transport, replaying bytes that were already read from it before
+/// reading from the transport itself.
+///
+/// Writes go directly to the transport.
+#[derive(Debug)]
+pub struct Rewind<T> {
+    pre: Option<Bytes>,
+    inner: T,
+}
+
+// ===== impl Rewind =====
+
+impl<T> Rewind<T> {
+    /// Create a new `Rewind` that yields `pre` before reading from `inner`.
+    pub fn new(inner: T, pre: Bytes) -> Self {
+        let pre = if pre.is_empty() { None } else { Some(pre) };
+        Rewind { pre, inner }
+    }
+
+    /// Returns a reference to the transport.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the transport, and the bytes that have not been replayed yet.
+    pub fn into_inner(self) -> (T, Bytes) {
+        (self.inner, self.pre.unwrap_or_else(Bytes::new))
+    }
+}
+
+impl<T: Read> Read for Rewind<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(mut pre) = self.pre.take() {
+            let n = ::std::cmp::min(pre.len(), buf.len());
+            buf[..n].copy_from_slice(&pre[..n]);
+            pre.advance(n);
+
+            if !pre.is_empty() {
+                self.pre = Some(pre);
+            }
+
+            return Ok(n);
+        }
+
+        self.inner.read(buf)
+    }
+}
+
+impl<T: Write> Write for Rewind<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for Rewind<T> {}
+
+impl<T: AsyncWrite> AsyncWrite for Rewind<T> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
@@ -0,0 +1,343 @@
+//! Dispatching requests to services by path and method.
+//!
+//! `RouteService` is a minimal router for servers with a handful of
+//! endpoints, which do not need a full framework:
+//!
+//! ```rust,ignore
+//! let mut routes = RouteService::new();
+//! routes
+//!     .route("/health", health)
+//!     .route_method(Method::POST, "/api/users", create_user)
+//!     .route("/api", api);
+//! ```
+//!
+//! A path prefix matches a request's path only at a segment boundary: the
+//! prefix `/api` matches `/api` and `/api/users`, but not `/apis`. Routes
+//! are tried in the order they were added, so more specific routes should be
+//! added first.
+
+use {Body, RecvBody};
+
+use futures::{Async, Future, Poll};
+use futures::future;
+use http::{Method, Request, Response, StatusCode};
+use http::header::{self, HeaderValue};
+use tower::Service;
+
+use std::fmt;
+use std::marker::PhantomData;
+
+/// The response future of a `RouteService`.
+pub type RouteFuture<B, E> = Box<Future<Item = Response<B>, Error = E> + Send>;
+
+/// A service that a `RouteService` dispatches requests to.
+type BoxService<B, E> = Box<Service<
+    Request = Request<RecvBody>,
+    Response = Response<B>,
+    Error = E,
+    Future = RouteFuture<B, E>,
+> + Send>;
+
+/// Dispatches each request to the first registered service whose path prefix
+/// and method match the request.
+///
+/// Requests whose path matches no route are handled by the fallback service,
+/// which responds with `404 Not Found` unless one was set with `fallback`.
+/// Requests whose path matches a route, but whose method does not match any
+/// route with a matching path, are answered with `405 Method Not Allowed`.
+///
+/// Every sub-service is polled for readiness, so the `RouteService` is only
+/// ready once all of its sub-services are.
+pub struct RouteService<B, E> {
+    routes: Vec<Route<B, E>>,
+    fallback: Option<BoxService<B, E>>,
+}
+
+struct Route<B, E> {
+    prefix: String,
+
+    /// The method the route is restricted to, if any.
+    method: Option<Method>,
+
+    service: BoxService<B, E>,
+}
+
+/// Adapts a sub-service to the response future and error of the router.
+struct Boxed<S, E> {
+    inner: S,
+    _p: PhantomData<fn() -> E>,
+}
+
+// ===== impl RouteService =====
+
+impl<B, E> RouteService<B, E>
+where B: Body + Default + Send + 'static,
+      E: Send + 'static,
+{
+    /// Create a new `RouteService` without any routes.
+    pub fn new() -> Self {
+        RouteService {
+            routes: Vec::new(),
+            fallback: None,
+        }
+    }
+
+    /// Dispatch requests of any method whose path starts with `prefix` to
+    /// `service`.
+    pub fn route<S>(&mut self, prefix: &str, service: S) -> &mut Self
+    where S: Service<Request = Request<RecvBody>, Response = Response<B>> + Send + 'static,
+          S::Error: Into<E>,
+          S::Future: Send + 'static,
+    {
+        self.add(None, prefix, service)
+    }
+
+    /// Dispatch requests of `method` whose path starts with `prefix` to
+    /// `service`.
+    pub fn route_method<S>(&mut self, method: Method, prefix: &str, service: S) -> &mut Self
+    where S: Service<Request = Request<RecvBody>, Response = Response<B>> + Send + 'static,
+          S::Error: Into<E>,
+          S::Future: Send + 'static,
+    {
+        self.add(Some(method), prefix, service)
+    }
+
+    /// Dispatch requests whose path matches no route to `service`, rather
+    /// than responding with `404 Not Found`.
+    pub fn fallback<S>(&mut self, service: S) -> &mut Self
+    where S: Service<Request = Request<RecvBody>, Response = Response<B>> + Send + 'static,
+          S::Error: Into<E>,
+          S::Future: Send + 'static,
+    {
+        self.fallback = Some(Box::new(Boxed::new(service)));
+        self
+    }
+
+    fn add<S>(&mut self, method: Option<Method>, prefix: &str, service: S) -> &mut Self
+    where S: Service<Request = Request<RecvBody>, Response = Response<B>> + Send + 'static,
+          S::Error: Into<E>,
+          S::Future: Send + 'static,
+    {
+        // A trailing slash is not needed to match at a segment boundary.
+        let prefix = match prefix.trim_right_matches('/') {
+            "" => "/",
+            prefix => prefix,
+        };
+
+        self.routes.push(Route {
+            prefix: prefix.to_owned(),
+            method,
+            service: Box::new(Boxed::new(service)),
+        });
+
+        self
+    }
+}
+
+impl<B, E> Service for RouteService<B, E>
+where B: Body + Default + Send + 'static,
+      E: Send + 'static,
+{
+    type Request = Request<RecvBody>;
+    type Response = Response<B>;
+    type Error = E;
+    type Future = RouteFuture<B, E>;
+
+    fn poll_ready(&mut self) -> Poll<(), E> {
+        let mut ready = true;
+
+        // Poll every service, so that each of them is driven towards being
+        // ready.
+        for route in &mut self.routes {
+            ready &= route.service.poll_ready()?.is_ready();
+        }
+
+        if let Some(ref mut fallback) = self.fallback {
+            ready &= fallback.poll_ready()?.is_ready();
+        }
+
+        if ready {
+            Ok(Async::Ready(()))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+
+    fn call(&mut self, request: Request<RecvBody>) -> Self::Future {
+        let mut path_matched = false;
+
+        let index = self.routes.iter().position(|route| {
+            if !route.matches_path(request.uri().path()) {
+                return false;
+            }
+
+            path_matched = true;
+            route.method.as_ref().map_or(true, |method| method == request.method())
+        });
+
+        if let Some(index) = index {
+            trace!("routing request; path={} prefix={}", request.uri().path(), self.routes[index].prefix);
+            return self.routes[index].service.call(request);
+        }
+
+        if path_matched {
+            debug!("no route for method; method={} path={}", request.method(), request.uri().path());
+            return Box::new(future::ok(self.method_not_allowed(request.uri().path())));
+        }
+
+        match self.fallback {
+            Some(ref mut fallback) => fallback.call(request),
+            None => {
+                debug!("no route for path; path={}", request.uri().path());
+                Box::new(future::ok(status(StatusCode::NOT_FOUND)))
+            }
+        }
+    }
+}
+
+impl<B, E> RouteService<B, E>
+where B: Default,
+{
+    /// Returns a `405 Method Not Allowed` response, listing the methods of
+    /// the routes that match `path`.
+    fn method_not_allowed(&self, path: &str) -> Response<B> {
+        let mut response = status(StatusCode::METHOD_NOT_ALLOWED);
+
+        let methods = self.routes.iter()
+            .filter(|route| route.matches_path(path))
+            .filter_map(|route| route.method.as_ref())
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if let Ok(allow) = HeaderValue::from_str(&methods) {
+            response.headers_mut().insert(header::ALLOW, allow);
+        }
+
+        response
+    }
+}
+
+impl<B, E> fmt::Debug for RouteService<B, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RouteService")
+            .field("routes", &self.routes)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+/// Returns an empty response with `status`.
+fn status<B: Default>(status: StatusCode) -> Response<B> {
+    let mut response = Response::new(B::default());
+    *response.status_mut() = status;
+    response
+}
+
+// ===== impl Route =====
+
+impl<B, E> Route<B, E> {
+    fn matches_path(&self, path: &str) -> bool {
+        if self.prefix == "/" {
+            return true;
+        }
+
+        path.starts_with(&self.prefix[..]) &&
+            path[self.prefix.len()..].chars().next().map_or(true, |c| c == '/')
+    }
+}
+
+impl<B, E> fmt::Debug for Route<B, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Route")
+            .field("prefix", &self.prefix)
+            .field("method", &self.method)
+            .finish()
+    }
+}
+
+// ===== impl Boxed =====
+
+impl<S, E> Boxed<S, E> {
+    fn new(inner: S) -> Self {
+        Boxed { inner, _p: PhantomData }
+    }
+}
+
+impl<S, B, E> Service for Boxed<S, E>
+where S: Service<Request = Request<RecvBody>, Response = Response<B>>,
+      S::Error: Into<E>,
+      S::Future: Send + 'static,
+      B: 'static,
+      E: 'static,
+{
+    type Request = Request<RecvBody>;
+    type Response = Response<B>;
+    type Error = E;
+    type Future = RouteFuture<B, E>;
+
+    fn poll_ready(&mut self) -> Poll<(), E> {
+        self.inner.poll_ready().map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Request<RecvBody>) -> Self::Future {
+        Box::new(self.inner.call(request).map_err(Into::into))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RouteService;
+    use RecvBody;
+
+    use futures::{future, Async, Poll};
+    use http::{Request, Response};
+    use tower::Service;
+
+    struct Empty;
+
+    impl Service for Empty {
+        type Request = Request<RecvBody>;
+        type Response = Response<()>;
+        type Error = ();
+        type Future = future::FutureResult<Response<()>, ()>;
+
+        fn poll_ready(&mut self) -> Poll<(), ()> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Self::Request) -> Self::Future {
+            future::ok(Response::new(()))
+        }
+    }
+
+    fn matches(prefix: &str, path: &str) -> bool {
+        let mut routes = RouteService::<(), ()>::new();
+        routes.route(prefix, Empty);
+        routes.routes[0].matches_path(path)
+    }
+
+    #[test]
+    fn matches_path_at_segment_boundary() {
+        assert!(matches("/api", "/api"));
+        assert!(matches("/api", "/api/"));
+        assert!(matches("/api", "/api/users"));
+        assert!(!matches("/api", "/apis"));
+        assert!(!matches("/api", "/"));
+        assert!(!matches("/api", "/health"));
+    }
+
+    #[test]
+    fn matches_path_ignores_trailing_slash() {
+        assert!(matches("/api/", "/api"));
+        assert!(matches("/api/", "/api/users"));
+        assert!(!matches("/api/", "/apis"));
+    }
+
+    #[test]
+    fn matches_path_root() {
+        assert!(matches("/", "/"));
+        assert!(matches("/", "/anything"));
+        assert!(matches("", "/anything"));
+    }
+}
@@ -0,0 +1,210 @@
+use Body;
+
+use bytes::Bytes;
+use futures::{Async, Poll, Sink, StartSend, Stream, Future};
+use futures::sync::{mpsc, oneshot};
+use h2::{self, Reason};
+use http::HeaderMap;
+
+use std::{error, fmt};
+
+/// A `Body` whose data is streamed from a `BodySender`.
+///
+/// This allows a service to return a response immediately and produce its
+/// body later, possibly from another task.
+///
+/// A connection only polls a body for data while its stream has send
+/// capacity, as reported by h2's `poll_capacity`. Until the peer grants more
+/// capacity, chunks queue up in the channel, and once `buffer` chunks are
+/// queued the `BodySender` is not ready, so a sender producing data faster
+/// than the peer accepts it waits rather than buffering it.
+#[derive(Debug)]
+pub struct SendBody {
+    data: mpsc::Receiver<Bytes>,
+    end: Option<oneshot::Receiver<End>>,
+    data_done: bool,
+}
+
+/// Sends data and trailers to a `SendBody`.
+///
+/// The `BodySender` is a `Sink` of data chunks. At most `buffer` chunks may be
+/// queued before the sender must wait for the body to be flushed.
+///
+/// The body ends once the sender is closed with `Sink::close` or `finish`, or
+/// ends with `send_trailers`. If the sender is dropped without ending the
+/// body, for example because the task producing it failed, the body fails
+/// and the stream is reset with `INTERNAL_ERROR`, so that the peer does not
+/// mistake a truncated body for a complete one.
+#[derive(Debug)]
+pub struct BodySender {
+    /// `None` once the sender has been closed.
+    data: Option<mpsc::Sender<Bytes>>,
+
+    /// `None` once the end of the body has been sent.
+    end: Option<oneshot::Sender<End>>,
+}
+
+/// Error produced when the `SendBody` has been dropped, or the `BodySender`
+/// has been closed.
+#[derive(Debug)]
+pub struct SendError {
+    _p: (),
+}
+
+/// How the body ends once all of its data has been sent.
+#[derive(Debug)]
+enum End {
+    Eos,
+    Trailers(HeaderMap),
+    Reset(Reason),
+}
+
+// ===== impl SendBody =====
+
+impl SendBody {
+    /// Create a new `SendBody` and the `BodySender` that streams into it.
+    ///
+    /// `buffer` is the number of data chunks that may be queued in addition to
+    /// the one chunk that is always guaranteed to be accepted.
+    pub fn channel(buffer: usize) -> (BodySender, SendBody) {
+        let (data_tx, data_rx) = mpsc::channel(buffer);
+        let (end_tx, end_rx) = oneshot::channel();
+
+        let tx = BodySender {
+            data: Some(data_tx),
+            end: Some(end_tx),
+        };
+
+        let rx = SendBody {
+            data: data_rx,
+            end: Some(end_rx),
+            data_done: false,
+        };
+
+        (tx, rx)
+    }
+}
+
+impl Body for SendBody {
+    type Data = Bytes;
+
+    fn is_end_stream(&self) -> bool {
+        self.data_done && self.end.is_none()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Bytes>, h2::Error> {
+        if self.data_done {
+            return Ok(Async::Ready(None));
+        }
+
+        // The receiver never fails.
+        let data = try_ready!(self.data.poll().map_err(|_| Reason::INTERNAL_ERROR));
+
+        if data.is_none() {
+            self.data_done = true;
+        }
+
+        Ok(Async::Ready(data))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, h2::Error> {
+        let end = match self.end {
+            Some(ref mut end) => match end.poll() {
+                Ok(Async::Ready(end)) => Some(end),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(_) => {
+                    debug!("body sender dropped without ending the body");
+                    self.end = None;
+                    return Err(Reason::INTERNAL_ERROR.into());
+                }
+            },
+            None => return Ok(Async::Ready(None)),
+        };
+
+        self.end = None;
+
+        match end {
+            Some(End::Eos) | None => Ok(Async::Ready(None)),
+            Some(End::Trailers(trailers)) => Ok(Async::Ready(Some(trailers))),
+            Some(End::Reset(reason)) => Err(reason.into()),
+        }
+    }
+}
+
+// ===== impl BodySender =====
+
+impl BodySender {
+    /// Ends the body without trailers, once all queued data has been sent.
+    pub fn finish(mut self) {
+        self.send_end(End::Eos);
+    }
+
+    /// Ends the body with `trailers`, once all queued data has been sent.
+    pub fn send_trailers(mut self, trailers: HeaderMap) {
+        self.send_end(End::Trailers(trailers));
+    }
+
+    /// Fails the body so that the stream is reset with `reason`, once all
+    /// queued data has been sent.
+    pub fn send_reset(mut self, reason: Reason) {
+        self.send_end(End::Reset(reason));
+    }
+
+    /// Returns `true` if the `SendBody` has been dropped.
+    ///
+    /// This is not known once the sender has been closed.
+    pub fn is_canceled(&self) -> bool {
+        self.end.as_ref().map_or(false, |end| end.is_canceled())
+    }
+
+    fn send_end(&mut self, end: End) {
+        // Dropping the data sender ends the data once it has been received.
+        self.data = None;
+
+        if let Some(tx) = self.end.take() {
+            let _ = tx.send(end);
+        }
+    }
+}
+
+impl Sink for BodySender {
+    type SinkItem = Bytes;
+    type SinkError = SendError;
+
+    fn start_send(&mut self, item: Bytes) -> StartSend<Bytes, SendError> {
+        match self.data {
+            Some(ref mut data) => data.start_send(item)
+                .map_err(|_| SendError { _p: () }),
+            None => Err(SendError { _p: () }),
+        }
+    }
+
+    fn poll_complete(&mut self) -> Poll<(), SendError> {
+        match self.data {
+            Some(ref mut data) => data.poll_complete()
+                .map_err(|_| SendError { _p: () }),
+            None => Ok(Async::Ready(())),
+        }
+    }
+
+    /// Ends the body without trailers, once all queued data has been sent.
+    fn close(&mut self) -> Poll<(), SendError> {
+        try_ready!(self.poll_complete());
+        self.send_end(End::Eos);
+        Ok(Async::Ready(()))
+    }
+}
+
+// ===== impl SendError =====
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Error sending body data: the body was dropped or closed")
+    }
+}
+
+impl error::Error for SendError {
+    fn description(&self) -> &str {
+        "body was dropped or closed"
+    }
+}
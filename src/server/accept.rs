@@ -0,0 +1,39 @@
+use h2::Reason;
+use http::{Request, StatusCode};
+
+use std::sync::Arc;
+
+/// Decides whether a received request is dispatched to the service.
+///
+/// An `Accept` is consulted for every request before the service is called,
+/// after `Modify` has been applied. Rejected requests are answered directly by
+/// the connection, so no `RecvBody` is created and no `Background` task is
+/// spawned for them.
+pub trait Accept: Send + Sync + 'static {
+    /// Returns `Ok` to dispatch `request` to the service, or the way it should
+    /// be rejected.
+    fn accept(&self, request: &Request<()>) -> Result<(), Reject>;
+}
+
+/// How a request refused by an `Accept` is rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reject {
+    /// Respond with an empty response with the given status.
+    Status(StatusCode),
+
+    /// Reset the stream with the given reason.
+    Reset(Reason),
+}
+
+/// An `Accept` shared by a server's connections.
+pub(super) type Shared = Arc<Accept>;
+
+// ===== impl Accept =====
+
+impl<F> Accept for F
+where F: Fn(&Request<()>) -> Result<(), Reject> + Send + Sync + 'static,
+{
+    fn accept(&self, request: &Request<()>) -> Result<(), Reject> {
+        (*self)(request)
+    }
+}
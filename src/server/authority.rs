@@ -0,0 +1,188 @@
+use http::Request;
+use http::header::HOST;
+
+/// The authorities a server accepts requests for.
+///
+/// Requests whose `:authority`, or `host` header in its absence, is not
+/// allowed are answered with `421 Misdirected Request`.
+#[derive(Debug)]
+pub(super) struct Authorities {
+    allowed: Vec<Allowed>,
+}
+
+#[derive(Debug)]
+struct Allowed {
+    /// The lowercase host, without the `*` of a wildcard.
+    host: String,
+    wildcard: bool,
+    port: Option<u16>,
+}
+
+// ===== impl Authorities =====
+
+impl Authorities {
+    pub fn new<I, A>(allowed: I) -> Self
+    where I: IntoIterator<Item = A>,
+          A: AsRef<str>,
+    {
+        let allowed = allowed.into_iter()
+            .map(|authority| Allowed::parse(authority.as_ref()))
+            .collect();
+
+        Authorities { allowed }
+    }
+
+    /// Returns `true` if `request` was sent to an allowed authority.
+    pub fn allows(&self, request: &Request<()>) -> bool {
+        let authority = match request.uri().authority_part() {
+            Some(authority) => authority.as_str(),
+            None => match request.headers().get(HOST).and_then(|h| h.to_str().ok()) {
+                Some(host) => host,
+                None => return false,
+            },
+        };
+
+        let (host, port) = split_port(authority);
+        let host = host.to_lowercase();
+
+        self.allowed.iter().any(|allowed| allowed.matches(&host, port))
+    }
+}
+
+// ===== impl Allowed =====
+
+impl Allowed {
+    /// Parses an authority such as `example.com`, `example.com:8443` or
+    /// `*.example.com`.
+    fn parse(authority: &str) -> Self {
+        let (host, port) = split_port(authority);
+        let host = host.to_lowercase();
+
+        if host.starts_with("*.") {
+            Allowed { host: host[1..].to_owned(), wildcard: true, port }
+        } else {
+            Allowed { host, wildcard: false, port }
+        }
+    }
+
+    fn matches(&self, host: &str, port: Option<u16>) -> bool {
+        if self.port.is_some() && self.port != port {
+            return false;
+        }
+
+        if self.wildcard {
+            // `self.host` starts with the `.`, so some label must precede it.
+            host.len() > self.host.len() && host.ends_with(&self.host)
+        } else {
+            host == self.host
+        }
+    }
+}
+
+/// Splits the port from an authority, if it has a valid one.
+fn split_port(authority: &str) -> (&str, Option<u16>) {
+    // The user info, if any, is not part of the host.
+    let authority = match authority.rfind('@') {
+        Some(at) => &authority[at + 1..],
+        None => authority,
+    };
+
+    if let Some(colon) = authority.rfind(':') {
+        // A colon inside an IPv6 literal is not a port separator.
+        if !authority[colon..].contains(']') {
+            let port = &authority[colon + 1..];
+
+            // An empty port is the same as no port.
+            if port.is_empty() {
+                return (&authority[..colon], None);
+            }
+
+            if let Ok(port) = port.parse() {
+                return (&authority[..colon], Some(port));
+            }
+        }
+    }
+
+    (authority, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_port, Authorities};
+
+    use http::Request;
+    use http::header::HOST;
+
+    fn uri(authorities: &Authorities, uri: &str) -> bool {
+        let request = Request::builder().uri(uri).body(()).unwrap();
+        authorities.allows(&request)
+    }
+
+    fn host(authorities: &Authorities, host: &str) -> bool {
+        let request = Request::builder().uri("/").header(HOST, host).body(()).unwrap();
+        authorities.allows(&request)
+    }
+
+    #[test]
+    fn split_port_host() {
+        assert_eq!(split_port("example.com"), ("example.com", None));
+        assert_eq!(split_port("example.com:8443"), ("example.com", Some(8443)));
+        assert_eq!(split_port("user@example.com:8443"), ("example.com", Some(8443)));
+    }
+
+    #[test]
+    fn split_port_empty_or_bad_port() {
+        assert_eq!(split_port("example.com:"), ("example.com", None));
+        assert_eq!(split_port("example.com:http"), ("example.com:http", None));
+        assert_eq!(split_port("example.com:65536"), ("example.com:65536", None));
+    }
+
+    #[test]
+    fn split_port_ipv6() {
+        assert_eq!(split_port("[::1]"), ("[::1]", None));
+        assert_eq!(split_port("[::1]:8080"), ("[::1]", Some(8080)));
+        assert_eq!(split_port("[2001:db8::1]:"), ("[2001:db8::1]", None));
+    }
+
+    #[test]
+    fn allows_exact_hosts_ignoring_case() {
+        let authorities = Authorities::new(&["Example.COM"]);
+
+        assert!(uri(&authorities, "https://example.com/"));
+        assert!(uri(&authorities, "https://EXAMPLE.com:8443/"));
+        assert!(host(&authorities, "eXample.Com"));
+        assert!(host(&authorities, "example.com:"));
+        assert!(!host(&authorities, "www.example.com"));
+        assert!(!host(&authorities, "example.com:http"));
+    }
+
+    #[test]
+    fn allows_ports() {
+        let authorities = Authorities::new(&["example.com:8443", "[::1]:8080"]);
+
+        assert!(uri(&authorities, "https://example.com:8443/"));
+        assert!(!uri(&authorities, "https://example.com/"));
+        assert!(!uri(&authorities, "https://example.com:443/"));
+        assert!(uri(&authorities, "http://[::1]:8080/"));
+        assert!(!uri(&authorities, "http://[::1]/"));
+    }
+
+    #[test]
+    fn allows_wildcards() {
+        let authorities = Authorities::new(&["*.example.com"]);
+
+        assert!(host(&authorities, "www.example.com"));
+        assert!(host(&authorities, "a.b.Example.com"));
+        assert!(!host(&authorities, "example.com"));
+        assert!(!host(&authorities, ".example.com"));
+        assert!(!host(&authorities, "wwwexample.com"));
+    }
+
+    #[test]
+    fn rejects_requests_without_authority() {
+        let authorities = Authorities::new(&["example.com"]);
+        let request = Request::builder().uri("/").body(()).unwrap();
+
+        assert!(!authorities.allows(&request));
+    }
+}
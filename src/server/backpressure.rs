@@ -0,0 +1,24 @@
+use futures::Async;
+
+use std::sync::Arc;
+
+/// Reports whether an executor has fallen behind on spawning tasks.
+///
+/// A `Backpressure` is configured with `Builder::max_executor_queue`. While the
+/// executor has more tasks queued than allowed, connections keep driving the
+/// streams they have already accepted, but accept no new streams. The peer's
+/// new streams then wait in h2, and flow control pushes back on the peer,
+/// rather than the queue growing and the latency of every stream with it.
+///
+/// This is implemented by `PriorityExecutor`.
+pub trait Backpressure: Send + Sync + 'static {
+    /// Returns `Async::Ready` if at most `max` tasks are queued.
+    ///
+    /// Otherwise, returns `Async::NotReady` and notifies the current task once
+    /// the queue may have drained.
+    fn poll_queue(&self, max: usize) -> Async<()>;
+}
+
+/// A `Backpressure` shared by a server's connections, with the queue depth
+/// above which they stop accepting streams.
+pub(super) type Shared = (Arc<Backpressure>, usize);
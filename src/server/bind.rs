@@ -0,0 +1,112 @@
+use futures::{Async, Future, Poll, Stream};
+use tokio_timer::{Sleep, Timer};
+
+use std::{fmt, io};
+use std::time::Duration;
+
+/// Accepts transports from a listener, backing off when accepting fails.
+///
+/// Created by `server::bind`. The stream may be passed to
+/// `Server::serve_incoming`.
+pub struct Bind<I> {
+    incoming: I,
+    timer: Timer,
+    backoff: Duration,
+    sleep: Option<Sleep>,
+}
+
+/// The default time to wait after failing to accept a connection, in seconds.
+const DEFAULT_BACKOFF_SECS: u64 = 1;
+
+/// Wrap a stream of transports, such as accepted TCP or Unix sockets, so that
+/// errors accepting them do not end the stream.
+///
+/// Errors that only affect the connection being accepted, such as the peer
+/// resetting it, are skipped. Other errors, such as running out of file
+/// descriptors (`EMFILE`), are logged and accepting is paused for a backoff
+/// period, one second by default, so that the listener does not spin while
+/// the condition persists.
+pub fn bind<I>(incoming: I) -> Bind<I>
+where I: Stream<Error = io::Error>,
+{
+    Bind {
+        incoming,
+        timer: Timer::default(),
+        backoff: Duration::from_secs(DEFAULT_BACKOFF_SECS),
+        sleep: None,
+    }
+}
+
+// ===== impl Bind =====
+
+impl<I> Bind<I>
+where I: Stream<Error = io::Error>,
+{
+    /// Sets the time to wait after failing to accept a connection.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the timer used to wait after failing to accept a connection.
+    pub fn timer(mut self, timer: Timer) -> Self {
+        self.timer = timer;
+        self
+    }
+
+    /// Returns a reference to the wrapped stream.
+    pub fn get_ref(&self) -> &I {
+        &self.incoming
+    }
+}
+
+impl<I> Stream for Bind<I>
+where I: Stream<Error = io::Error>,
+{
+    type Item = I::Item;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<I::Item>, io::Error> {
+        loop {
+            if let Some(ref mut sleep) = self.sleep {
+                try_ready!(sleep.poll().map_err(|e| {
+                    io::Error::new(io::ErrorKind::Other, e)
+                }));
+            }
+
+            self.sleep = None;
+
+            match self.incoming.poll() {
+                Ok(ready) => return Ok(ready),
+                Err(ref e) if is_connection_error(e) => {
+                    debug!("accepted connection already failed: {}", e);
+                }
+                Err(e) => {
+                    warn!("error accepting connection; backing off for {:?}: {}",
+                          self.backoff, e);
+                    self.sleep = Some(self.timer.sleep(self.backoff));
+                }
+            }
+        }
+    }
+}
+
+impl<I: fmt::Debug> fmt::Debug for Bind<I> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Bind")
+            .field("incoming", &self.incoming)
+            .field("backoff", &self.backoff)
+            .field("sleeping", &self.sleep.is_some())
+            .finish()
+    }
+}
+
+/// Returns `true` if `e` only affects the connection being accepted.
+fn is_connection_error(e: &io::Error) -> bool {
+    match e.kind() {
+        io::ErrorKind::ConnectionRefused |
+        io::ErrorKind::ConnectionAborted |
+        io::ErrorKind::ConnectionReset => true,
+        _ => false,
+    }
+}
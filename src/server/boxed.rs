@@ -0,0 +1,167 @@
+use {Body, RecvBody};
+use super::{Background, Closed, Connection, Error, Server};
+
+use futures::{Future, Poll};
+use futures::future::Executor;
+use h2;
+use http::{Request, Response};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tower::{NewService, Service};
+
+use std::{error, fmt};
+
+/// A type-erased error.
+type Boxed = Box<error::Error + Send + Sync>;
+
+/// Drives a connection, producing a `BoxError` on failure.
+///
+/// Created by `Server::serve_boxed`.
+pub struct ServeBoxed<T, S, E, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService,
+      B: Body,
+{
+    inner: Connection<T, S, E, B, ()>,
+}
+
+/// Error produced by a `Connection`, without the `NewService` type parameter.
+///
+/// The errors produced by the service and while obtaining it are boxed, so a
+/// `BoxError` may be stored or passed around regardless of the service used.
+#[derive(Debug)]
+pub enum BoxError {
+    /// Error produced during the HTTP/2.0 handshake.
+    Handshake(h2::Error),
+
+    /// Error produced by the HTTP/2.0 connection.
+    ///
+    /// This is always a connection-level error. h2 handles errors on
+    /// individual streams itself by resetting them, and the connection keeps
+    /// serving its other streams.
+    Protocol(h2::Error),
+
+    /// Error produced when obtaining the service
+    NewService(Boxed),
+
+    /// Error produced by the service
+    Service(Boxed),
+
+    /// Error produced when attempting to spawn a task
+    Execute,
+
+    /// The peer did not complete the HTTP/2.0 handshake in time.
+    HandshakeTimeout,
+}
+
+// ===== impl Server =====
+
+impl<S, E, B> Server<S, E, B>
+where S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      S::InitError: Into<Boxed>,
+      S::Error: Into<Boxed>,
+      B: Body,
+      E: Clone,
+{
+    /// Like `serve`, but the returned future fails with a `BoxError` rather
+    /// than an `Error<S>`.
+    pub fn serve_boxed<T>(&self, io: T) -> ServeBoxed<T, S, E, B>
+    where T: AsyncRead + AsyncWrite,
+    {
+        ServeBoxed {
+            inner: self.serve(io),
+        }
+    }
+}
+
+// ===== impl ServeBoxed =====
+
+impl<T, S, E, B> ServeBoxed<T, S, E, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      B: Body,
+{
+    /// Start a graceful shutdown of the connection.
+    ///
+    /// See `Connection::graceful_shutdown`.
+    pub fn graceful_shutdown(&mut self) {
+        self.inner.graceful_shutdown();
+    }
+}
+
+impl<T, S, E, B> Future for ServeBoxed<T, S, E, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      S::InitError: Into<Boxed>,
+      S::Error: Into<Boxed>,
+      E: Executor<Background<<S::Service as Service>::Future, B>>,
+      B: Body + 'static,
+{
+    type Item = Closed;
+    type Error = BoxError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.inner.poll().map_err(BoxError::from)
+    }
+}
+
+// ===== impl BoxError =====
+
+impl<S> From<Error<S>> for BoxError
+where S: NewService,
+      S::InitError: Into<Boxed>,
+      S::Error: Into<Boxed>,
+{
+    fn from(err: Error<S>) -> Self {
+        match err {
+            Error::Handshake(e) => BoxError::Handshake(e),
+            Error::Protocol(e) => BoxError::Protocol(e),
+            Error::NewService(e) => BoxError::NewService(e.into()),
+            Error::Service(e) => BoxError::Service(e.into()),
+            Error::Execute => BoxError::Execute,
+            Error::HandshakeTimeout => BoxError::HandshakeTimeout,
+        }
+    }
+}
+
+impl fmt::Display for BoxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BoxError::Handshake(ref why) =>
+                write!(f, "Error occurred during HTTP/2.0 handshake: {}", why),
+            BoxError::Protocol(ref why) =>
+                write!(f, "Error produced by HTTP/2.0 connection: {}", why),
+            BoxError::NewService(ref why) =>
+                write!(f, "Error occurred while obtaining service: {}", why),
+            BoxError::Service(ref why) =>
+                write!(f, "Error returned by service: {}", why),
+            BoxError::Execute =>
+                write!(f, "Error occurred while attempting to spawn a task"),
+            BoxError::HandshakeTimeout =>
+                write!(f, "Timed out waiting for the HTTP/2.0 handshake"),
+        }
+    }
+}
+
+impl error::Error for BoxError {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            BoxError::Handshake(ref why) => Some(why),
+            BoxError::Protocol(ref why) => Some(why),
+            BoxError::NewService(ref why) => Some(&**why),
+            BoxError::Service(ref why) => Some(&**why),
+            BoxError::Execute => None,
+            BoxError::HandshakeTimeout => None,
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            BoxError::Handshake(_) =>  "error occurred during HTTP/2.0 handshake",
+            BoxError::Protocol(_) => "error produced by HTTP/2.0 connection",
+            BoxError::NewService(_) => "error occured while obtaining service",
+            BoxError::Service(_) => "error returned by service",
+            BoxError::Execute => "error occurred while attempting to spawn a task",
+            BoxError::HandshakeTimeout => "timed out waiting for the HTTP/2.0 handshake",
+        }
+    }
+}
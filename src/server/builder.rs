@@ -0,0 +1,1017 @@
+use {Body, BufferPool, RecvBody};
+#[cfg(feature = "compress")]
+use compress::{CompressBody, NewCompress};
+#[cfg(feature = "compress")]
+use decompress::{self, DecompressRecvBody, NewDecompress};
+use super::{BackgroundError, Server};
+use super::accept::{self, Accept, Reject};
+use super::authority::Authorities;
+use super::backpressure::{self, Backpressure};
+use super::concurrency::ConcurrencyLimit;
+use super::date::Date;
+use super::drain::Watch;
+use super::instrument::{self, Instrument};
+use super::log::{Log, LogHandler};
+use super::rate::RateLimit;
+use super::state::Settings;
+
+use h2;
+use http::{Request, Response};
+use http::header::{HeaderName, HeaderValue};
+use tokio_timer::{self, Sleep, Timer};
+use tower::NewService;
+
+use std::{cmp, fmt};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The longest timeout, in seconds, of a timer created by a `Builder`,
+/// unless a longer timeout is configured.
+///
+/// Request deadlines longer than this are shortened to it.
+const DEFAULT_MAX_TIMEOUT_SECS: u64 = 24 * 60 * 60;
+
+/// Configures and builds a `Server`.
+///
+/// This wraps an `h2::server::Builder` so that the HTTP/2.0 settings used for
+/// each connection can be configured without depending on `h2` directly.
+///
+/// The settings must be configured through the `Builder` rather than on an
+/// `h2::server::Builder`, as they are also tracked by the `Builder` itself,
+/// for example to report them in `ConnectionState`.
+#[derive(Clone, Default)]
+pub struct Builder {
+    h2: h2::server::Builder,
+    settings: Settings,
+    max_in_flight: Option<usize>,
+    concurrency: Option<ConcurrencyLimit>,
+    backpressure: Option<backpressure::Shared>,
+    on_background_error: Option<ErrorHandler>,
+    timer: Option<Timer>,
+
+    /// The longest timeout `timer` accepts.
+    max_timeout: Duration,
+
+    /// Whether `timer` was set with `Builder::timer`, rather than created
+    /// by the `Builder`.
+    custom_timer: bool,
+
+    handshake_timeout: Option<Duration>,
+    stream_idle_timeout: Option<Duration>,
+    response_timeout: Option<Duration>,
+    response_timeout_reject: Option<Reject>,
+    idle_timeout: Option<Duration>,
+    deadline_header: Option<HeaderName>,
+    drain: Option<Watch>,
+    max_connection_age: Option<Duration>,
+    max_requests_per_connection: Option<u64>,
+    stream_rate: Option<RateLimit>,
+    instrument: Option<instrument::Shared>,
+    access_log: Option<LogHandler>,
+    coalesce_watermark: usize,
+    max_queued_chunks: usize,
+    max_send_buffer: Option<usize>,
+    buffer_pool: Option<Arc<BufferPool>>,
+    not_ready: NotReadyPolicy,
+    accept: Option<accept::Shared>,
+    authorities: Option<Arc<Authorities>>,
+    connect: bool,
+    priority: Option<PriorityFn>,
+    strip_connection_headers: bool,
+    execute_error: ExecuteErrorPolicy,
+    date: Option<Date>,
+    server_header: Option<HeaderValue>,
+    #[cfg(feature = "compress")]
+    decompress: decompress::Limits,
+}
+
+/// Determines what a connection does when the executor fails to spawn the
+/// `Background` task of a stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecuteErrorPolicy {
+    /// Fail the connection with `Error::Execute`.
+    Fail,
+
+    /// Refuse the stream with `REFUSED_STREAM` and keep the connection open.
+    Refuse,
+
+    /// If the executor is at capacity, yield and retry spawning the task
+    /// before accepting any more streams. If the executor has shut down, the
+    /// stream is refused as with `Refuse`.
+    Retry,
+}
+
+/// Determines how a connection treats new streams while its service is not
+/// ready.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotReadyPolicy {
+    /// Stop accepting new streams until the service is ready.
+    ///
+    /// The connection continues to be driven, so streams that were already
+    /// accepted make progress, but new streams wait in h2 until the service
+    /// becomes ready.
+    Wait,
+
+    /// Refuse new streams with `REFUSED_STREAM` until the service is ready.
+    ///
+    /// Refused streams were not processed, so the peer may safely retry them,
+    /// possibly on another connection.
+    Refuse,
+}
+
+/// Assigns the priority a `PriorityExecutor` spawns a stream's task with.
+pub(super) type PriorityFn = Arc<Fn(&Request<()>) -> u8 + Send + Sync>;
+
+/// Callback invoked with the errors encountered by `Background` tasks.
+pub(super) type ErrorHandler = Arc<Fn(BackgroundError) + Send + Sync>;
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Returns a new `Builder` with the default HTTP/2.0 settings.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Sets the initial stream-level flow control window size, in octets.
+    ///
+    /// This is sent to the peer as `SETTINGS_INITIAL_WINDOW_SIZE`.
+    pub fn initial_window_size(&mut self, size: u32) -> &mut Self {
+        self.h2.initial_window_size(size);
+        self.settings.initial_window_size = Some(size);
+        self
+    }
+
+    /// Sets the initial connection-level flow control window size, in
+    /// octets.
+    ///
+    /// HTTP/2.0 starts every connection with a 65,535 octet window, which
+    /// limits the throughput of all of the connection's streams together on
+    /// links with a large bandwidth-delay product. The window is enlarged
+    /// with a `WINDOW_UPDATE` frame once the connection is established.
+    ///
+    /// The window is static. h2 0.1 has no API to send PING frames or to
+    /// observe their ACKs, so the bandwidth-delay product cannot be
+    /// measured and the window cannot be tuned automatically from it; it
+    /// should be sized for the expected links instead.
+    pub fn initial_connection_window_size(&mut self, size: u32) -> &mut Self {
+        self.h2.initial_connection_window_size(size);
+        self.settings.initial_connection_window_size = Some(size);
+        self
+    }
+
+    /// Sets the maximum number of concurrent streams the peer may open.
+    ///
+    /// This is sent to the peer as `SETTINGS_MAX_CONCURRENT_STREAMS`.
+    pub fn max_concurrent_streams(&mut self, max: u32) -> &mut Self {
+        self.h2.max_concurrent_streams(max);
+        self.settings.max_concurrent_streams = Some(max);
+        self
+    }
+
+    /// Sets the largest frame payload the peer may send, in octets.
+    ///
+    /// This is sent to the peer as `SETTINGS_MAX_FRAME_SIZE`.
+    pub fn max_frame_size(&mut self, max: u32) -> &mut Self {
+        self.h2.max_frame_size(max);
+        self.settings.max_frame_size = Some(max);
+        self
+    }
+
+    /// Sets the maximum size of the header list the peer may send, in octets.
+    ///
+    /// This is sent to the peer as `SETTINGS_MAX_HEADER_LIST_SIZE`. Requests
+    /// whose header list is larger are answered with
+    /// `431 Request Header Fields Too Large`, and reported to the
+    /// `on_background_error` callback as `BackgroundError::HeaderListSize`,
+    /// without failing the connection.
+    pub fn max_header_list_size(&mut self, max: u32) -> &mut Self {
+        self.h2.max_header_list_size(max);
+        self.settings.max_header_list_size = Some(max);
+        self
+    }
+
+    /// Returns the maximum size of the header list the peer may send, if one
+    /// has been configured.
+    pub fn header_list_size_limit(&self) -> Option<u32> {
+        self.settings.max_header_list_size
+    }
+
+    /// Sets the size below which response body chunks are coalesced.
+    ///
+    /// Chunks smaller than `watermark` bytes are copied into a buffer that is
+    /// sent as a single DATA frame once it reaches `watermark` bytes, or once
+    /// the body is not immediately ready to yield another chunk. This reduces
+    /// the per-frame overhead of bodies that yield many small chunks.
+    ///
+    /// By default, chunks are not coalesced.
+    pub fn coalesce_watermark(&mut self, watermark: usize) -> &mut Self {
+        self.coalesce_watermark = watermark;
+        self
+    }
+
+    /// Sets how new streams are treated while the service is not ready.
+    ///
+    /// Defaults to `NotReadyPolicy::Wait`.
+    pub fn not_ready_policy(&mut self, policy: NotReadyPolicy) -> &mut Self {
+        self.not_ready = policy;
+        self
+    }
+
+    /// Sets an `Accept` that decides whether each request is dispatched to
+    /// the service.
+    pub fn accept<A>(&mut self, accept: A) -> &mut Self
+    where A: Accept,
+    {
+        self.accept = Some(Arc::new(accept));
+        self
+    }
+
+    /// Sets the authorities that requests may be sent to.
+    ///
+    /// Requests whose `:authority`, or `host` header in its absence, does not
+    /// match one of `authorities` are answered with `421 Misdirected Request`
+    /// without being dispatched to the service. This lets the peer retry them
+    /// on a connection to the right server, for example when a TLS
+    /// certificate covers more names than the server handles.
+    ///
+    /// Authorities are matched case-insensitively. An authority without a
+    /// port matches any port, and one starting with `*.` matches any
+    /// subdomain of the rest of it.
+    pub fn allowed_authorities<I, A>(&mut self, authorities: I) -> &mut Self
+    where I: IntoIterator<Item = A>,
+          A: AsRef<str>,
+    {
+        self.authorities = Some(Arc::new(Authorities::new(authorities)));
+        self
+    }
+
+    /// Sets whether `CONNECT` requests are dispatched to the service.
+    ///
+    /// By default, `CONNECT` requests are answered with `405 Method Not
+    /// Allowed`. Once enabled, the service may accept a `CONNECT` request by
+    /// answering it with a `2xx` response whose body is the `SendBody` of an
+    /// `Upgraded` created from the request's body, and then use the
+    /// `Upgraded` as a tunnel to the peer.
+    ///
+    /// h2 does not support the extended `CONNECT` of RFC 8441, so the
+    /// `:protocol` pseudo-header used by WebSockets over HTTP/2.0 cannot be
+    /// received.
+    pub fn enable_connect(&mut self, enabled: bool) -> &mut Self {
+        self.connect = enabled;
+        self
+    }
+
+    /// Sets a function that assigns a priority to each stream.
+    ///
+    /// The priority is used by a `PriorityExecutor` to decide which of the
+    /// queued `Background` tasks to spawn first; higher priorities are
+    /// spawned first. Other executors ignore it. h2 does not expose the
+    /// priority the peer sent for a stream, so it must be derived from the
+    /// request, for example from its path or a header.
+    ///
+    /// By default, every stream has priority 0.
+    pub fn stream_priority<F>(&mut self, f: F) -> &mut Self
+    where F: Fn(&Request<()>) -> u8 + Send + Sync + 'static,
+    {
+        self.priority = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets whether connection-specific headers are removed from responses.
+    ///
+    /// HTTP/2.0 forbids the `connection`, `keep-alive`, `proxy-connection`,
+    /// `transfer-encoding` and `upgrade` headers, and a `te` header other
+    /// than `te: trailers`. When enabled, these headers, and any named by the
+    /// `connection` header, are removed before the response is sent, which
+    /// suits services that proxy HTTP/1.1 responses.
+    ///
+    /// By default, a response with such a header is not sent: the stream is
+    /// reset with `INTERNAL_ERROR` and `BackgroundError::ConnectionHeader` is
+    /// reported.
+    pub fn strip_connection_headers(&mut self, enabled: bool) -> &mut Self {
+        self.strip_connection_headers = enabled;
+        self
+    }
+
+    /// Sets what a connection does when the executor fails to spawn the task
+    /// that processes a stream.
+    ///
+    /// Defaults to `ExecuteErrorPolicy::Fail`.
+    pub fn execute_error_policy(&mut self, policy: ExecuteErrorPolicy) -> &mut Self {
+        self.execute_error = policy;
+        self
+    }
+
+    /// Sets whether a `date` header is added to every response that does not
+    /// already have one.
+    ///
+    /// The header value is formatted at most once per second and shared by
+    /// every connection.
+    pub fn date_header(&mut self, enabled: bool) -> &mut Self {
+        self.date = if enabled { Some(Date::new()) } else { None };
+        self
+    }
+
+    /// Sets a `server` header that is added to every response that does not
+    /// already have one.
+    pub fn server_header(&mut self, value: HeaderValue) -> &mut Self {
+        self.server_header = Some(value);
+        self
+    }
+
+    /// Sets the maximum number of streams per connection that may be processed
+    /// concurrently.
+    ///
+    /// Once this many response tasks are in flight, the connection stops
+    /// accepting new streams until one of them completes. Unlike
+    /// `max_concurrent_streams`, this is enforced locally and is not
+    /// advertised to the peer.
+    pub fn max_in_flight(&mut self, max: usize) -> &mut Self {
+        self.max_in_flight = Some(max);
+        self
+    }
+
+    /// Sets a limit on the number of streams processed concurrently across
+    /// every connection sharing `limit`.
+    ///
+    /// Unlike `max_in_flight`, which applies to each connection separately,
+    /// this bounds the total number of requests in flight, for example across
+    /// all of the connections of a process.
+    pub fn concurrency_limit(&mut self, limit: ConcurrencyLimit) -> &mut Self {
+        self.concurrency = Some(limit);
+        self
+    }
+
+    /// Stops accepting new streams while more than `max` tasks are queued on
+    /// the executor, as reported by `backpressure`.
+    ///
+    /// This is typically the `PriorityExecutor` the server spawns onto.
+    pub fn max_executor_queue<P>(&mut self, backpressure: P, max: usize) -> &mut Self
+    where P: Backpressure,
+    {
+        self.backpressure = Some((Arc::new(backpressure), max));
+        self
+    }
+
+    /// Sets a callback that is invoked whenever a `Background` task fails to
+    /// send a response to the peer.
+    ///
+    /// By default, these errors are only logged.
+    pub fn on_background_error<F>(&mut self, f: F) -> &mut Self
+    where F: Fn(BackgroundError) + Send + Sync + 'static,
+    {
+        self.on_background_error = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets the maximum amount of time a peer may take to complete the
+    /// HTTP/2.0 handshake.
+    ///
+    /// Connections whose peer has not sent the connection preface and initial
+    /// SETTINGS frame by then fail with `Error::HandshakeTimeout`.
+    pub fn handshake_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.handshake_timeout = Some(timeout);
+        self.ensure_timeout(timeout);
+        self
+    }
+
+    /// Sets the maximum amount of time a request body may go without receiving
+    /// any data.
+    ///
+    /// When this elapses, the stream is reset with `CANCEL` and the service's
+    /// `RecvBody` fails with an error for which `RecvError::is_idle_timeout`
+    /// returns `true`.
+    pub fn stream_idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.stream_idle_timeout = Some(timeout);
+        self.ensure_timeout(timeout);
+        self
+    }
+
+    /// Sets the maximum amount of time the service's response future may take
+    /// to produce a response.
+    ///
+    /// When this elapses, the response future is dropped, the request's
+    /// `Cancellation` completes, and the stream is rejected as configured by
+    /// `response_timeout_reject`. The timeout does not apply to sending the
+    /// response body.
+    pub fn response_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.response_timeout = Some(timeout);
+        self.ensure_timeout(timeout);
+        self
+    }
+
+    /// Sets how streams whose response times out are rejected.
+    ///
+    /// By default, the stream is reset with `CANCEL`. A status such as
+    /// `503 Service Unavailable` or `504 Gateway Timeout` may be sent instead,
+    /// as an empty response.
+    ///
+    /// This has no effect unless `response_timeout` is set.
+    pub fn response_timeout_reject(&mut self, reject: Reject) -> &mut Self {
+        self.response_timeout_reject = Some(reject);
+        self
+    }
+
+    /// Sets the name of a header that carries the time a request's response
+    /// must be sent within, such as gRPC's `grpc-timeout`.
+    ///
+    /// Values are parsed in the format of `grpc-timeout`: up to eight digits
+    /// followed by a unit of `H` (hours), `M` (minutes), `S` (seconds), `m`
+    /// (milliseconds), `u` (microseconds) or `n` (nanoseconds). Values without
+    /// a unit are milliseconds.
+    ///
+    /// A `Deadline` is inserted into the extensions of each request that has a
+    /// valid value. If the response has not been sent once the deadline
+    /// elapses, the stream is reset with `CANCEL` and the request's
+    /// `Cancellation` completes.
+    ///
+    /// Deadlines longer than the timer accepts, a day unless a longer
+    /// timeout is configured or set with `Builder::timer`, are shortened to
+    /// that maximum.
+    pub fn deadline_header(&mut self, name: HeaderName) -> &mut Self {
+        self.deadline_header = Some(name);
+        self.ensure_timer();
+        self
+    }
+
+    /// Sets the maximum amount of time a connection may go without any
+    /// streams in flight.
+    ///
+    /// When this elapses, a graceful shutdown of the connection is started
+    /// and the `Connection` completes with `Closed::Idle`. The timeout is
+    /// restarted whenever a stream is accepted or the last stream in flight
+    /// completes. This reclaims the resources held by idle keep-alive peers.
+    pub fn idle_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.idle_timeout = Some(timeout);
+        self.ensure_timeout(timeout);
+        self
+    }
+
+    /// Sets a `Watch` that causes every connection to shut down gracefully
+    /// once its drain is signaled.
+    ///
+    /// `Server::serve_incoming` also stops accepting new connections once the
+    /// drain is signaled, and request bodies still being received are ended
+    /// by resetting their streams with `CANCEL`. See the `drain` module.
+    pub fn drain(&mut self, watch: Watch) -> &mut Self {
+        self.drain = Some(watch);
+        self
+    }
+
+    /// Sets the maximum amount of time a connection may stay open.
+    ///
+    /// Once this elapses, a graceful shutdown of the connection is started,
+    /// as when a drain is signaled. This allows connections to be recycled,
+    /// for example so that the peer reconnects through a load balancer.
+    pub fn max_connection_age(&mut self, age: Duration) -> &mut Self {
+        self.max_connection_age = Some(age);
+        self.ensure_timeout(age);
+        self
+    }
+
+    /// Sets the maximum number of requests a connection may accept.
+    ///
+    /// Once this many streams have been accepted, a graceful shutdown of the
+    /// connection is started. The accepted streams are still processed.
+    pub fn max_requests_per_connection(&mut self, max: u64) -> &mut Self {
+        self.max_requests_per_connection = Some(max);
+        self
+    }
+
+    /// Limits the rate at which each connection accepts new streams.
+    ///
+    /// Every connection may accept up to `burst` streams at once, and then
+    /// `per_second` streams per second on average. Streams opened faster than
+    /// this are reset, by default with `REFUSED_STREAM`, without being
+    /// dispatched to the service. This defends against peers that flood the
+    /// server with requests.
+    pub fn max_stream_rate(&mut self, per_second: u32, burst: u32) -> &mut Self {
+        let reason = self.stream_rate.map(|limit| limit.reason());
+        let mut limit = RateLimit::new(per_second, burst);
+
+        if let Some(reason) = reason {
+            limit.set_reason(reason);
+        }
+
+        self.stream_rate = Some(limit);
+        self
+    }
+
+    /// Sets the reason that streams exceeding `max_stream_rate` are reset
+    /// with.
+    ///
+    /// `REFUSED_STREAM`, the default, tells the peer that the stream was not
+    /// processed and may be retried. `ENHANCE_YOUR_CALM` tells the peer that
+    /// it is opening streams too quickly.
+    ///
+    /// This has no effect unless `max_stream_rate` is set.
+    pub fn stream_rate_reset_reason(&mut self, reason: h2::Reason) -> &mut Self {
+        if let Some(ref mut limit) = self.stream_rate {
+            limit.set_reason(reason);
+        }
+        self
+    }
+
+    /// Sets an `Instrument` that observes the activity of every connection.
+    pub fn instrument<I>(&mut self, instrument: I) -> &mut Self
+    where I: Instrument,
+    {
+        self.instrument = Some(Arc::new(instrument));
+        self
+    }
+
+    /// Sets a callback that is invoked with a `Log` of every stream once the
+    /// server is done with it.
+    ///
+    /// This is invoked when the response body has been flushed, or when the
+    /// stream is reset or fails.
+    pub fn access_log<F>(&mut self, f: F) -> &mut Self
+    where F: Fn(&Log) + Send + Sync + 'static,
+    {
+        self.access_log = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets the timer used to enforce timeouts, along with the
+    /// `max_timeout` it was built with.
+    ///
+    /// If no timer is set, one is created when the first timeout is
+    /// configured, accepting timeouts of up to a day or of the longest
+    /// timeout configured.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a timeout longer than `max_timeout` is configured, either
+    /// already or once the timer is set, rather than letting the timer
+    /// refuse it when a connection starts it.
+    pub fn timer(&mut self, timer: Timer, max_timeout: Duration) -> &mut Self {
+        self.timer = Some(timer);
+        self.max_timeout = max_timeout;
+        self.custom_timer = true;
+
+        let timeouts = [
+            self.handshake_timeout,
+            self.stream_idle_timeout,
+            self.response_timeout,
+            self.idle_timeout,
+            self.max_connection_age,
+        ];
+
+        for timeout in timeouts.iter().filter_map(|t| *t) {
+            self.ensure_timeout(timeout);
+        }
+
+        self
+    }
+
+    /// Build a `Server` using the current configuration.
+    pub fn build<S, E, B>(&self, new_service: S, executor: E) -> Server<S, E, B>
+    where S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+          B: Body,
+    {
+        Server::new(new_service, self.clone(), executor)
+    }
+
+    /// Build a `Server` whose services' response bodies are compressed with
+    /// an encoding negotiated from each request's `accept-encoding` header.
+    ///
+    /// Each service produced by `new_service` is wrapped in a `Compress`.
+    #[cfg(feature = "compress")]
+    pub fn build_compressed<S, E, B>(&self, new_service: S, executor: E)
+        -> Server<NewCompress<S>, E, CompressBody<B>>
+    where S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+          B: Body,
+    {
+        Server::new(NewCompress::new(new_service), self.clone(), executor)
+    }
+
+    /// Sets the limits on the inflated size of request bodies decompressed
+    /// by a server built with `build_decompressed`.
+    #[cfg(feature = "compress")]
+    pub fn decompress_limits(&mut self, limits: decompress::Limits) -> &mut Self {
+        self.decompress = limits;
+        self
+    }
+
+    /// Build a `Server` whose services receive request bodies that are
+    /// inflated according to their `content-encoding`.
+    ///
+    /// Each service produced by `new_service` is wrapped in a `Decompress`,
+    /// which inflates bodies within the limits set by `decompress_limits`.
+    #[cfg(feature = "compress")]
+    pub fn build_decompressed<S, E, B>(&self, new_service: S, executor: E)
+        -> Server<NewDecompress<S>, E, B>
+    where S: NewService<Request = Request<DecompressRecvBody>, Response = Response<B>>,
+          B: Body,
+    {
+        let new_service = NewDecompress::with_limits(new_service, self.decompress);
+        Server::new(new_service, self.clone(), executor)
+    }
+
+    /// Sets the maximum number of response body chunks sent in a single DATA
+    /// frame.
+    ///
+    /// Chunks that the body yields while it is immediately ready are queued,
+    /// without being copied, and handed to h2 together once `max` chunks are
+    /// queued or the body is not ready. Transports that support vectored
+    /// writes may write the queued chunks with a single call.
+    ///
+    /// By default, each chunk is sent as it is yielded.
+    pub fn max_queued_chunks(&mut self, max: usize) -> &mut Self {
+        self.max_queued_chunks = max;
+        self
+    }
+
+    /// Sets the maximum number of response body bytes buffered for a stream
+    /// but not yet sent.
+    ///
+    /// A response body is polled for more data while fewer than `max` bytes
+    /// are buffered, whether they are held by the server or queued by h2
+    /// until the peer's flow control window allows sending them. Once `max`
+    /// bytes are buffered, the body is not polled again until the peer reads
+    /// some of them, which bounds the memory used by a stream whose peer
+    /// stops reading. The bytes currently buffered by a connection are
+    /// reported by `Stats::send_buffered`.
+    ///
+    /// By default, the body is only polled while the stream has send
+    /// capacity.
+    pub fn max_send_buffer(&mut self, max: usize) -> &mut Self {
+        self.max_send_buffer = Some(max);
+        self
+    }
+
+    /// Sets the pool that body buffers are obtained from.
+    ///
+    /// The pool provides the buffers response chunks are coalesced into, and
+    /// the buffers request bodies are collected into by `RecvBody::collect`.
+    ///
+    /// By default, buffers are allocated as needed.
+    pub fn buffer_pool(&mut self, pool: Arc<BufferPool>) -> &mut Self {
+        self.buffer_pool = Some(pool);
+        self
+    }
+
+    /// Returns the underlying `h2` builder.
+    pub(super) fn h2(&self) -> &h2::server::Builder {
+        &self.h2
+    }
+
+    pub(super) fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    pub(super) fn coalesce_watermark_size(&self) -> usize {
+        self.coalesce_watermark
+    }
+
+    pub(super) fn max_queued(&self) -> usize {
+        self.max_queued_chunks
+    }
+
+    pub(super) fn max_send_buffer_size(&self) -> Option<usize> {
+        self.max_send_buffer
+    }
+
+    pub(super) fn buffer_pool_ref(&self) -> Option<Arc<BufferPool>> {
+        self.buffer_pool.clone()
+    }
+
+    pub(super) fn not_ready(&self) -> NotReadyPolicy {
+        self.not_ready
+    }
+
+    pub(super) fn accept_filter(&self) -> Option<accept::Shared> {
+        self.accept.clone()
+    }
+
+    pub(super) fn authorities(&self) -> Option<Arc<Authorities>> {
+        self.authorities.clone()
+    }
+
+    pub(super) fn connect_enabled(&self) -> bool {
+        self.connect
+    }
+
+    pub(super) fn priority_fn(&self) -> Option<PriorityFn> {
+        self.priority.clone()
+    }
+
+    pub(super) fn strip_connection_headers_enabled(&self) -> bool {
+        self.strip_connection_headers
+    }
+
+    pub(super) fn execute_error(&self) -> ExecuteErrorPolicy {
+        self.execute_error
+    }
+
+    pub(super) fn date(&self) -> Option<Date> {
+        self.date.clone()
+    }
+
+    pub(super) fn server_header_value(&self) -> Option<HeaderValue> {
+        self.server_header.clone()
+    }
+
+    pub(super) fn max_in_flight_limit(&self) -> Option<usize> {
+        self.max_in_flight
+    }
+
+    pub(super) fn concurrency(&self) -> Option<ConcurrencyLimit> {
+        self.concurrency.clone()
+    }
+
+    pub(super) fn backpressure(&self) -> Option<backpressure::Shared> {
+        self.backpressure.clone()
+    }
+
+    pub(super) fn background_error_handler(&self) -> Option<ErrorHandler> {
+        self.on_background_error.clone()
+    }
+
+    /// Returns a timeout for a connection's handshake, starting now.
+    pub(super) fn handshake_timer(&self) -> Option<Sleep> {
+        self.sleep(self.handshake_timeout)
+    }
+
+    /// Returns the timer and duration used to enforce stream idle timeouts.
+    pub(super) fn stream_idle_timer(&self) -> Option<(Timer, Duration)> {
+        match (self.timer.as_ref(), self.stream_idle_timeout) {
+            (Some(timer), Some(duration)) => Some((timer.clone(), duration)),
+            _ => None,
+        }
+    }
+
+    /// Returns the timer and duration used to enforce response timeouts, and
+    /// how timed out streams are rejected.
+    pub(super) fn response_timer(&self) -> Option<(Timer, Duration, Reject)> {
+        let reject = self.response_timeout_reject
+            .unwrap_or(Reject::Reset(h2::Reason::CANCEL));
+
+        match (self.timer.as_ref(), self.response_timeout) {
+            (Some(timer), Some(duration)) => Some((timer.clone(), duration, reject)),
+            _ => None,
+        }
+    }
+
+    /// Returns the timer and duration used to close idle connections.
+    pub(super) fn idle_timer(&self) -> Option<(Timer, Duration)> {
+        match (self.timer.as_ref(), self.idle_timeout) {
+            (Some(timer), Some(duration)) => Some((timer.clone(), duration)),
+            _ => None,
+        }
+    }
+
+    /// Returns a timeout for a connection's maximum age, starting now.
+    pub(super) fn max_age_timer(&self) -> Option<Sleep> {
+        self.sleep(self.max_connection_age)
+    }
+
+    pub(super) fn max_requests(&self) -> Option<u64> {
+        self.max_requests_per_connection
+    }
+
+    pub(super) fn stream_rate(&self) -> Option<RateLimit> {
+        self.stream_rate
+    }
+
+    /// Returns the timer and header name used to enforce request deadlines,
+    /// and the longest deadline the timer accepts.
+    pub(super) fn deadline_timer(&self) -> Option<(Timer, HeaderName, Duration)> {
+        match (self.timer.as_ref(), self.deadline_header.as_ref()) {
+            (Some(timer), Some(name)) => Some((timer.clone(), name.clone(), self.max_timeout)),
+            _ => None,
+        }
+    }
+
+    pub(super) fn drain_watch(&self) -> Option<Watch> {
+        self.drain.clone()
+    }
+
+    pub(super) fn instrument_handle(&self) -> Option<instrument::Shared> {
+        self.instrument.clone()
+    }
+
+    pub(super) fn access_log_handler(&self) -> Option<LogHandler> {
+        self.access_log.clone()
+    }
+
+    fn sleep(&self, duration: Option<Duration>) -> Option<Sleep> {
+        match (self.timer.as_ref(), duration) {
+            (Some(timer), Some(duration)) => Some(timer.sleep(duration)),
+            _ => None,
+        }
+    }
+
+    fn ensure_timer(&mut self) {
+        if self.timer.is_none() {
+            let max = Duration::from_secs(DEFAULT_MAX_TIMEOUT_SECS);
+            self.timer = Some(tokio_timer::wheel().max_timeout(max).build());
+            self.max_timeout = max;
+        }
+    }
+
+    /// Ensures that the timer accepts `timeout`.
+    ///
+    /// A timer created by the `Builder` is replaced with one that accepts
+    /// longer timeouts if needed. `tokio_timer`'s default timer accepts
+    /// timeouts of only about 7 minutes, and refuses longer ones when they
+    /// are started.
+    fn ensure_timeout(&mut self, timeout: Duration) {
+        self.ensure_timer();
+
+        if timeout <= self.max_timeout {
+            return;
+        }
+
+        assert!(
+            !self.custom_timer,
+            "timeout of {:?} exceeds the timer's max_timeout of {:?}",
+            timeout,
+            self.max_timeout,
+        );
+
+        let max = cmp::max(timeout, self.max_timeout);
+        self.timer = Some(tokio_timer::wheel().max_timeout(max).build());
+        self.max_timeout = max;
+    }
+}
+
+impl fmt::Debug for Builder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Builder")
+            .field("h2", &self.h2)
+            .field("coalesce_watermark", &self.coalesce_watermark)
+            .field("max_queued_chunks", &self.max_queued_chunks)
+            .field("max_send_buffer", &self.max_send_buffer)
+            .field("buffer_pool", &self.buffer_pool.is_some())
+            .field("not_ready", &self.not_ready)
+            .field("accept", &self.accept.is_some())
+            .field("authorities", &self.authorities)
+            .field("connect", &self.connect)
+            .field("priority", &self.priority.is_some())
+            .field("strip_connection_headers", &self.strip_connection_headers)
+            .field("execute_error", &self.execute_error)
+            .field("date", &self.date.is_some())
+            .field("server_header", &self.server_header)
+            .field("max_in_flight", &self.max_in_flight)
+            .field("concurrency", &self.concurrency)
+            .field("max_executor_queue", &self.backpressure.as_ref().map(|&(_, max)| max))
+            .field("on_background_error", &self.on_background_error.is_some())
+            .field("max_timeout", &self.max_timeout)
+            .field("handshake_timeout", &self.handshake_timeout)
+            .field("stream_idle_timeout", &self.stream_idle_timeout)
+            .field("response_timeout", &self.response_timeout)
+            .field("response_timeout_reject", &self.response_timeout_reject)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("deadline_header", &self.deadline_header)
+            .field("drain", &self.drain)
+            .field("max_connection_age", &self.max_connection_age)
+            .field("max_requests_per_connection", &self.max_requests_per_connection)
+            .field("stream_rate", &self.stream_rate)
+            .field("instrument", &self.instrument.is_some())
+            .field("access_log", &self.access_log.is_some())
+            .finish()
+    }
+}
+
+impl Default for NotReadyPolicy {
+    fn default() -> Self {
+        NotReadyPolicy::Wait
+    }
+}
+
+impl Default for ExecuteErrorPolicy {
+    fn default() -> Self {
+        ExecuteErrorPolicy::Fail
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Builder;
+
+    use futures::{future, Future};
+    use http::header::HeaderName;
+    use tokio_timer;
+
+    use std::time::Duration;
+
+    const HOUR: u64 = 60 * 60;
+
+    #[test]
+    fn long_max_connection_age_is_started() {
+        let mut builder = Builder::new();
+        builder.max_connection_age(Duration::from_secs(48 * HOUR));
+
+        let mut sleep = builder.max_age_timer().expect("max age timer");
+
+        future::lazy(move || {
+            assert!(!sleep.poll().expect("timer refused the max age").is_ready());
+            Ok::<(), ()>(())
+        }).wait().unwrap();
+    }
+
+    #[test]
+    fn long_idle_timeouts_are_started() {
+        let mut builder = Builder::new();
+        builder
+            .idle_timeout(Duration::from_secs(2 * HOUR))
+            .stream_idle_timeout(Duration::from_secs(30 * HOUR));
+
+        let (timer, duration) = builder.idle_timer().expect("idle timer");
+        let mut idle = timer.sleep(duration);
+
+        let (timer, duration) = builder.stream_idle_timer().expect("stream idle timer");
+        let mut stream_idle = timer.sleep(duration);
+
+        future::lazy(move || {
+            assert!(!idle.poll().expect("timer refused the idle timeout").is_ready());
+            assert!(!stream_idle.poll().expect("timer refused the stream idle timeout").is_ready());
+            Ok::<(), ()>(())
+        }).wait().unwrap();
+    }
+
+    #[test]
+    fn long_response_timeout_is_started() {
+        let mut builder = Builder::new();
+        builder.response_timeout(Duration::from_secs(30 * HOUR));
+
+        let (timer, duration, _) = builder.response_timer().expect("response timer");
+        let mut timeout = timer.sleep(duration);
+
+        future::lazy(move || {
+            assert!(!timeout.poll().expect("timer refused the response timeout").is_ready());
+            Ok::<(), ()>(())
+        }).wait().unwrap();
+    }
+
+    #[test]
+    fn deadlines_are_limited_to_max_timeout() {
+        let mut builder = Builder::new();
+        builder.deadline_header(HeaderName::from_static("grpc-timeout"));
+
+        let (_, _, max) = builder.deadline_timer().expect("deadline timer");
+        assert_eq!(max, Duration::from_secs(24 * HOUR));
+
+        builder.max_connection_age(Duration::from_secs(48 * HOUR));
+
+        let (timer, _, max) = builder.deadline_timer().expect("deadline timer");
+        assert_eq!(max, Duration::from_secs(48 * HOUR));
+
+        let mut deadline = timer.sleep(max);
+
+        future::lazy(move || {
+            assert!(!deadline.poll().expect("timer refused the deadline").is_ready());
+            Ok::<(), ()>(())
+        }).wait().unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn response_timeout_longer_than_custom_timer_is_rejected() {
+        let timer = tokio_timer::wheel()
+            .max_timeout(Duration::from_secs(60))
+            .build();
+
+        Builder::new()
+            .timer(timer, Duration::from_secs(60))
+            .response_timeout(Duration::from_secs(HOUR));
+    }
+
+    #[test]
+    #[should_panic]
+    fn idle_timeout_longer_than_custom_timer_is_rejected() {
+        let timer = tokio_timer::wheel()
+            .max_timeout(Duration::from_secs(60))
+            .build();
+
+        Builder::new()
+            .timer(timer, Duration::from_secs(60))
+            .idle_timeout(Duration::from_secs(HOUR));
+    }
+
+    #[test]
+    #[should_panic]
+    fn max_connection_age_longer_than_custom_timer_is_rejected() {
+        let timer = tokio_timer::wheel()
+            .max_timeout(Duration::from_secs(60))
+            .build();
+
+        Builder::new()
+            .timer(timer, Duration::from_secs(60))
+            .max_connection_age(Duration::from_secs(HOUR));
+    }
+
+    #[test]
+    #[should_panic]
+    fn custom_timer_shorter_than_max_connection_age_is_rejected() {
+        let timer = tokio_timer::wheel()
+            .max_timeout(Duration::from_secs(60))
+            .build();
+
+        Builder::new()
+            .max_connection_age(Duration::from_secs(HOUR))
+            .timer(timer, Duration::from_secs(60));
+    }
+}
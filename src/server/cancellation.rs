@@ -0,0 +1,42 @@
+use reset::Reset;
+
+use futures::{Async, Future, Poll};
+use h2::Reason;
+
+/// A future that completes when the peer resets a request's stream.
+///
+/// A `Cancellation` is inserted into the extensions of every request received
+/// by a `Server`. Services may remove it and poll it to stop expensive work
+/// once the peer is no longer interested in the response. When the stream is
+/// reset, the response future is dropped regardless.
+///
+/// The future yields the reason the stream was reset with.
+#[derive(Debug)]
+pub struct Cancellation {
+    inner: Reset,
+}
+
+// ===== impl Cancellation =====
+
+impl Cancellation {
+    pub(super) fn new(inner: Reset) -> Self {
+        Cancellation { inner }
+    }
+
+    /// Returns `true` if the stream has been reset.
+    pub fn is_canceled(&self) -> bool {
+        self.inner.is_reset()
+    }
+}
+
+impl Future for Cancellation {
+    type Item = Reason;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Reason, ()> {
+        match self.inner.poll_reset() {
+            Some(reason) => Ok(Async::Ready(reason)),
+            None => Ok(Async::NotReady),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use futures::{Async, Stream};
+use futures::sync::mpsc;
+use h2::Reason;
+
+/// The number of streams a connection's `Background` tasks have completed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamCounts {
+    succeeded: u64,
+    reset: u64,
+}
+
+/// Reports the completion of a `Background` task to its connection.
+#[derive(Clone)]
+pub(super) struct Sender {
+    tx: mpsc::UnboundedSender<Option<Reason>>,
+}
+
+/// Receives the completions of a connection's `Background` tasks.
+pub(super) struct Receiver {
+    rx: mpsc::UnboundedReceiver<Option<Reason>>,
+    counts: StreamCounts,
+}
+
+/// Creates a completion channel for a connection.
+pub(super) fn channel() -> (Sender, Receiver) {
+    let (tx, rx) = mpsc::unbounded();
+
+    let rx = Receiver {
+        rx,
+        counts: StreamCounts::default(),
+    };
+
+    (Sender { tx }, rx)
+}
+
+// ===== impl StreamCounts =====
+
+impl StreamCounts {
+    /// Returns the number of streams whose response was sent successfully.
+    pub fn succeeded(&self) -> u64 {
+        self.succeeded
+    }
+
+    /// Returns the number of streams that were reset or failed, by either
+    /// side.
+    pub fn reset(&self) -> u64 {
+        self.reset
+    }
+}
+
+// ===== impl Sender =====
+
+impl Sender {
+    /// Reports that a task completed, or was reset with `reason`.
+    pub fn complete(self, reason: Option<Reason>) {
+        // The connection may have closed while the task was in flight.
+        let _ = self.tx.unbounded_send(reason);
+    }
+}
+
+// ===== impl Receiver =====
+
+impl Receiver {
+    /// Counts the completions reported since the last call.
+    ///
+    /// The current task is notified when further completions are reported.
+    pub fn poll(&mut self) {
+        // The connection holds a `Sender`, so the channel never ends.
+        while let Ok(Async::Ready(Some(reason))) = self.rx.poll() {
+            match reason {
+                Some(_) => self.counts.reset += 1,
+                None => self.counts.succeeded += 1,
+            }
+        }
+    }
+
+    pub fn counts(&self) -> StreamCounts {
+        self.counts
+    }
+}
@@ -0,0 +1,120 @@
+use futures::Async;
+use futures::task::{self, Task};
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Limits the number of streams in flight across many connections.
+///
+/// A `ConcurrencyLimit` is configured on a server with
+/// `Builder::concurrency_limit`. Clones share the same limit, so one limit may
+/// be used by several servers. While the limit is reached, connections keep
+/// driving the streams they have already accepted, but accept no new streams
+/// until one of the `Background` tasks holding the limit completes.
+///
+/// Connections check the limit independently, so when several of them accept
+/// a stream at the same moment the limit may briefly be exceeded by a few
+/// streams.
+#[derive(Clone)]
+pub struct ConcurrencyLimit {
+    shared: Arc<Shared>,
+}
+
+/// Held by a `Background` task for as long as it is in flight.
+pub(super) struct Permit {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    max: usize,
+    state: Mutex<State>,
+}
+
+struct State {
+    active: usize,
+
+    /// The connection tasks waiting for the number of active streams to drop
+    /// below `max`.
+    waiters: Vec<Task>,
+}
+
+// ===== impl ConcurrencyLimit =====
+
+impl ConcurrencyLimit {
+    /// Create a new `ConcurrencyLimit` that allows up to `max` streams in
+    /// flight.
+    pub fn new(max: usize) -> Self {
+        ConcurrencyLimit {
+            shared: Arc::new(Shared {
+                max,
+                state: Mutex::new(State {
+                    active: 0,
+                    waiters: Vec::new(),
+                }),
+            }),
+        }
+    }
+
+    /// Returns the maximum number of streams in flight.
+    pub fn max(&self) -> usize {
+        self.shared.max
+    }
+
+    /// Returns the number of streams currently in flight.
+    pub fn active(&self) -> usize {
+        self.shared.state.lock().unwrap().active
+    }
+
+    /// Returns `Ready` when another stream may be accepted.
+    ///
+    /// If `NotReady` is returned, the current task is notified once a stream
+    /// completes.
+    pub(super) fn poll_acquire(&self) -> Async<()> {
+        let mut state = self.shared.state.lock().unwrap();
+
+        if state.active < self.shared.max {
+            return Async::Ready(());
+        }
+
+        trace!("concurrency limit reached; max={}", self.shared.max);
+
+        if !state.waiters.iter().any(|waiter| waiter.will_notify_current()) {
+            state.waiters.push(task::current());
+        }
+
+        Async::NotReady
+    }
+
+    /// Claim a slot for a new stream.
+    pub(super) fn acquire(&self) -> Permit {
+        self.shared.state.lock().unwrap().active += 1;
+        Permit { shared: self.shared.clone() }
+    }
+}
+
+impl fmt::Debug for ConcurrencyLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConcurrencyLimit")
+            .field("max", &self.max())
+            .field("active", &self.active())
+            .finish()
+    }
+}
+
+// ===== impl Permit =====
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let waiters = {
+            let mut state = self.shared.state.lock().unwrap();
+            state.active -= 1;
+            ::std::mem::replace(&mut state.waiters, Vec::new())
+        };
+
+        // Every waiting connection is woken, since a connection that is
+        // notified may no longer be accepting streams.
+        for waiter in waiters {
+            waiter.notify();
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use http::header::HeaderValue;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Produces the value of the `date` header, formatting it at most once per
+/// second.
+///
+/// Clones share the same cached value.
+#[derive(Clone)]
+pub(super) struct Date {
+    cached: Arc<Mutex<Cached>>,
+}
+
+struct Cached {
+    /// The second, since the Unix epoch, that `value` was formatted for.
+    secs: u64,
+    value: HeaderValue,
+}
+
+/// Formats a time as an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+struct HttpDate(u64);
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun",
+    "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// ===== impl Date =====
+
+impl Date {
+    pub fn new() -> Self {
+        let secs = now();
+
+        Date {
+            cached: Arc::new(Mutex::new(Cached {
+                secs,
+                value: format(secs),
+            })),
+        }
+    }
+
+    /// Returns the `date` header value for the current second.
+    pub fn value(&self) -> HeaderValue {
+        let secs = now();
+        let mut cached = self.cached.lock().unwrap();
+
+        if cached.secs != secs {
+            cached.secs = secs;
+            cached.value = format(secs);
+        }
+
+        cached.value.clone()
+    }
+}
+
+impl fmt::Debug for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Date")
+            .field("value", &self.cached.lock().unwrap().value)
+            .finish()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn format(secs: u64) -> HeaderValue {
+    let value = HttpDate(secs).to_string();
+    HeaderValue::from_str(&value).expect("formatted date is a valid header value")
+}
+
+// ===== impl HttpDate =====
+
+impl fmt::Display for HttpDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let days = self.0 / 86_400;
+        let secs_of_day = self.0 % 86_400;
+
+        // Convert the number of days since the epoch to a civil date, using
+        // years that start in March so that leap days come last.
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        write!(f, "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+               DAYS[(days % 7) as usize],
+               day,
+               MONTHS[(month - 1) as usize],
+               year,
+               secs_of_day / 3_600,
+               secs_of_day % 3_600 / 60,
+               secs_of_day % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpDate;
+
+    fn date(secs: u64) -> String {
+        HttpDate(secs).to_string()
+    }
+
+    #[test]
+    fn formats_the_epoch() {
+        assert_eq!(date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn formats_the_rfc_example() {
+        assert_eq!(date(784_111_777), "Sun, 06 Nov 1994 08:49:37 GMT");
+    }
+
+    #[test]
+    fn formats_leap_days() {
+        assert_eq!(date(951_782_400), "Tue, 29 Feb 2000 00:00:00 GMT");
+        assert_eq!(date(951_868_800), "Wed, 01 Mar 2000 00:00:00 GMT");
+        assert_eq!(date(1_709_164_800), "Thu, 29 Feb 2024 00:00:00 GMT");
+    }
+
+    #[test]
+    fn formats_the_end_of_a_year() {
+        assert_eq!(date(946_684_799), "Fri, 31 Dec 1999 23:59:59 GMT");
+    }
+}
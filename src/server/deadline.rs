@@ -0,0 +1,139 @@
+use http::HeaderMap;
+use http::header::HeaderName;
+
+use std::time::{Duration, Instant};
+
+/// The time by which the client expects a request's response to complete.
+///
+/// When a `Server` is configured with `Builder::deadline_header`, a
+/// `Deadline` is inserted into the extensions of every request that carries
+/// the header. If the deadline elapses before the response has been sent, the
+/// stream is reset with `CANCEL` and the request's `Cancellation` completes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deadline {
+    instant: Instant,
+}
+
+// ===== impl Deadline =====
+
+impl Deadline {
+    /// Returns the instant at which the deadline elapses.
+    pub fn instant(&self) -> Instant {
+        self.instant
+    }
+
+    /// Returns the time left until the deadline elapses, or `None` if it
+    /// already has.
+    pub fn remaining(&self) -> Option<Duration> {
+        let now = Instant::now();
+
+        if self.instant > now {
+            Some(self.instant - now)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the deadline has elapsed.
+    pub fn is_elapsed(&self) -> bool {
+        self.remaining().is_none()
+    }
+}
+
+/// Returns the timeout carried by the `name` header of `headers`, if any.
+///
+/// Values are parsed in the format of gRPC's `grpc-timeout` header: up to
+/// eight digits followed by a unit of `H`, `M`, `S`, `m`, `u`, or `n`. A value
+/// without a unit is a number of milliseconds.
+pub(super) fn parse(headers: &HeaderMap, name: &HeaderName) -> Option<Duration> {
+    let value = headers.get(name)?.to_str().ok()?.trim();
+
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+
+    if digits.is_empty() || digits.len() > 8 {
+        return None;
+    }
+
+    let n: u64 = digits.parse().ok()?;
+
+    let timeout = match unit {
+        "H" => Duration::from_secs(n * 60 * 60),
+        "M" => Duration::from_secs(n * 60),
+        "S" => Duration::from_secs(n),
+        "m" | "" => Duration::from_millis(n),
+        "u" => Duration::from_nanos(n * 1_000),
+        "n" => Duration::from_nanos(n),
+        _ => return None,
+    };
+
+    Some(timeout)
+}
+
+/// Returns the `Deadline` of a request received now with `timeout`.
+pub(super) fn after(timeout: Duration) -> Deadline {
+    Deadline {
+        instant: Instant::now() + timeout,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    use http::HeaderMap;
+    use http::header::{HeaderName, HeaderValue};
+
+    use std::time::Duration;
+
+    fn timeout(value: &[u8]) -> Option<Duration> {
+        let name = HeaderName::from_static("grpc-timeout");
+        let mut headers = HeaderMap::new();
+        headers.insert(name.clone(), HeaderValue::from_bytes(value).unwrap());
+        parse(&headers, &name)
+    }
+
+    #[test]
+    fn parse_units() {
+        assert_eq!(timeout(b"2H"), Some(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(timeout(b"3M"), Some(Duration::from_secs(3 * 60)));
+        assert_eq!(timeout(b"4S"), Some(Duration::from_secs(4)));
+        assert_eq!(timeout(b"5m"), Some(Duration::from_millis(5)));
+        assert_eq!(timeout(b"6u"), Some(Duration::from_nanos(6_000)));
+        assert_eq!(timeout(b"7n"), Some(Duration::from_nanos(7)));
+        assert_eq!(timeout(b"8"), Some(Duration::from_millis(8)));
+    }
+
+    #[test]
+    fn parse_eight_digits() {
+        assert_eq!(timeout(b"99999999H"), Some(Duration::from_secs(99999999 * 60 * 60)));
+        assert_eq!(timeout(b"000000001S"), None);
+        assert_eq!(timeout(b"123456789m"), None);
+    }
+
+    #[test]
+    fn parse_missing_digits() {
+        assert_eq!(timeout(b""), None);
+        assert_eq!(timeout(b"S"), None);
+    }
+
+    #[test]
+    fn parse_bad_unit() {
+        assert_eq!(timeout(b"1s"), None);
+        assert_eq!(timeout(b"1SS"), None);
+        assert_eq!(timeout(b"1 S"), None);
+        assert_eq!(timeout(b"-1S"), None);
+    }
+
+    #[test]
+    fn parse_non_ascii() {
+        assert_eq!(timeout(b"1\xc2\xb5"), None);
+        assert_eq!(timeout("\u{661}S".as_bytes()), None);
+    }
+
+    #[test]
+    fn parse_missing_header() {
+        let name = HeaderName::from_static("grpc-timeout");
+        assert_eq!(parse(&HeaderMap::new(), &name), None);
+    }
+}
@@ -0,0 +1,182 @@
+//! Coordinates the graceful shutdown of many connections.
+//!
+//! A `Watch` is configured on a server with `Builder::drain`. When the
+//! corresponding `Signal` is drained, every connection served with that
+//! `Watch` initiates an HTTP/2.0 graceful shutdown, and the request bodies
+//! still being received on those connections end, resetting their streams
+//! with `CANCEL`, so that streams waiting on them can complete.
+//!
+//! The `Drained` future completes once every connection served with the
+//! `Watch`, and every `Background` task spawned by those connections, has
+//! completed or been dropped. The `Watch` handles themselves, such as the
+//! one held by the `Builder`, do not hold up the drain.
+
+use futures::{Async, Future, Poll};
+use futures::future::Shared;
+use futures::sync::oneshot;
+use futures::task::AtomicTask;
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Creates a drain channel.
+pub fn channel() -> (Signal, Watch) {
+    let (signal_tx, signal_rx) = oneshot::channel();
+
+    let tracker = Arc::new(Tracker {
+        active: AtomicUsize::new(0),
+        task: AtomicTask::new(),
+    });
+
+    let signal = Signal {
+        signal_tx,
+        tracker: tracker.clone(),
+    };
+
+    let watch = Watch {
+        signal_rx: signal_rx.shared(),
+        tracker,
+    };
+
+    (signal, watch)
+}
+
+/// Initiates the graceful shutdown of all connections watching this channel.
+pub struct Signal {
+    signal_tx: oneshot::Sender<()>,
+    tracker: Arc<Tracker>,
+}
+
+/// Watches for a drain to be signaled.
+#[derive(Clone)]
+pub struct Watch {
+    signal_rx: Shared<oneshot::Receiver<()>>,
+    tracker: Arc<Tracker>,
+}
+
+/// Completes once every connection and task watching the channel has
+/// completed.
+pub struct Drained {
+    tracker: Arc<Tracker>,
+}
+
+/// Held by a connection or a `Background` task for as long as it is live.
+///
+/// Dropping the guard notifies the `Drained` future once no guards remain.
+pub(crate) struct Guard {
+    tracker: Arc<Tracker>,
+}
+
+/// Counts the live connections and tasks watching a channel.
+struct Tracker {
+    active: AtomicUsize,
+    task: AtomicTask,
+}
+
+// ===== impl Signal =====
+
+impl Signal {
+    /// Signal all watchers to drain, returning a future that completes once
+    /// their connections and tasks have all completed.
+    pub fn drain(self) -> Drained {
+        let _ = self.signal_tx.send(());
+
+        Drained {
+            tracker: self.tracker,
+        }
+    }
+}
+
+impl fmt::Debug for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Signal")
+            .field("active", &self.tracker.active())
+            .finish()
+    }
+}
+
+// ===== impl Watch =====
+
+impl Watch {
+    /// Returns `true` if a drain has been signaled.
+    ///
+    /// Otherwise, the current task is notified once a drain is signaled.
+    pub(crate) fn poll_signal(&mut self) -> bool {
+        match self.signal_rx.poll() {
+            Ok(Async::Ready(_)) => true,
+            Ok(Async::NotReady) => false,
+            // The `Signal` was dropped without being drained.
+            Err(_) => false,
+        }
+    }
+
+    /// Counts a connection or task as live until the returned guard is
+    /// dropped.
+    pub(crate) fn guard(&self) -> Guard {
+        self.tracker.active.fetch_add(1, Ordering::AcqRel);
+        Guard { tracker: self.tracker.clone() }
+    }
+}
+
+impl fmt::Debug for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Watch")
+            .field("active", &self.tracker.active())
+            .finish()
+    }
+}
+
+// ===== impl Drained =====
+
+impl Future for Drained {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        // Register before checking the count so that a guard dropped
+        // concurrently is not missed.
+        self.tracker.task.register();
+
+        let active = self.tracker.active();
+
+        if active == 0 {
+            Ok(Async::Ready(()))
+        } else {
+            trace!("waiting for drain; active={}", active);
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+impl fmt::Debug for Drained {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Drained")
+            .field("active", &self.tracker.active())
+            .finish()
+    }
+}
+
+// ===== impl Guard =====
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if self.tracker.active.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.tracker.task.notify();
+        }
+    }
+}
+
+impl fmt::Debug for Guard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Guard").finish()
+    }
+}
+
+// ===== impl Tracker =====
+
+impl Tracker {
+    fn active(&self) -> usize {
+        self.active.load(Ordering::Acquire)
+    }
+}
@@ -0,0 +1,124 @@
+use futures::{Async, Future, Poll};
+use futures::future::{Executor, ExecuteError};
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// A boxed `Background` task, or any other task spawned by a `Server`.
+pub type BoxBackground = Box<Future<Item = (), Error = ()> + Send>;
+
+/// Adapts an executor that only accepts `BoxBackground` futures so that it
+/// can spawn the tasks of a `Server`.
+///
+/// This allows executors such as one spawning onto a `tokio` runtime through
+/// boxed futures to be used without implementing
+/// `Executor<Background<...>>` for them.
+///
+/// The executor's type remains in `E`. `E` defaults to a trait object, so
+/// `BoxedExecutor::erase` returns a `BoxedExecutor` whose type is the same
+/// whatever it spawns onto. A `Server<S, BoxedExecutor, B>`, also named
+/// `BoxServer<S, B>`, can then be stored in structs without carrying the
+/// executor's type along.
+pub struct BoxedExecutor<E: ?Sized = Executor<BoxBackground> + Send + Sync> {
+    inner: Arc<E>,
+}
+
+/// A task passed to the inner executor.
+///
+/// The task is shared with `BoxedExecutor::execute` until it is first polled,
+/// so that it can be recovered if the inner executor fails to spawn it.
+enum Slot<F> {
+    Shared(Arc<Mutex<Option<F>>>),
+    Owned(F),
+}
+
+// ===== impl BoxedExecutor =====
+
+impl<E> BoxedExecutor<E>
+where E: Executor<BoxBackground>,
+{
+    /// Create a new `BoxedExecutor` that spawns tasks onto `inner`.
+    pub fn new(inner: E) -> Self {
+        BoxedExecutor { inner: Arc::new(inner) }
+    }
+
+    /// Erase the type of the inner executor.
+    pub fn erase(self) -> BoxedExecutor
+    where E: Send + Sync + 'static,
+    {
+        BoxedExecutor { inner: self.inner }
+    }
+}
+
+impl<E: ?Sized> BoxedExecutor<E> {
+    /// Returns a reference to the inner executor.
+    pub fn get_ref(&self) -> &E {
+        &self.inner
+    }
+}
+
+impl<E, F> Executor<F> for BoxedExecutor<E>
+where E: Executor<BoxBackground> + ?Sized,
+      F: Future<Item = (), Error = ()> + Send + 'static,
+{
+    fn execute(&self, future: F) -> Result<(), ExecuteError<F>> {
+        execute_boxed(&*self.inner, future)
+    }
+}
+
+impl<E: ?Sized> Clone for BoxedExecutor<E> {
+    fn clone(&self) -> Self {
+        BoxedExecutor { inner: self.inner.clone() }
+    }
+}
+
+impl<E: ?Sized> fmt::Debug for BoxedExecutor<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BoxedExecutor").finish()
+    }
+}
+
+/// Spawn `future` onto `inner` as a `BoxBackground`, handing it back if the
+/// executor fails to spawn it.
+pub(super) fn execute_boxed<E, F>(inner: &E, future: F) -> Result<(), ExecuteError<F>>
+where E: Executor<BoxBackground> + ?Sized,
+      F: Future<Item = (), Error = ()> + Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Some(future)));
+    let task: BoxBackground = Box::new(Slot::Shared(shared.clone()));
+
+    inner.execute(task).map_err(|err| {
+        let kind = err.kind();
+
+        // Drop the boxed task so that the future can be taken back.
+        drop(err.into_future());
+
+        let future = shared.lock().unwrap().take()
+            .expect("task polled by an executor that failed to spawn it");
+
+        ExecuteError::new(kind, future)
+    })
+}
+
+// ===== impl Slot =====
+
+impl<F> Future for Slot<F>
+where F: Future<Item = (), Error = ()>,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        loop {
+            let future = match *self {
+                Slot::Owned(ref mut future) => return future.poll(),
+                Slot::Shared(ref shared) => match shared.lock().unwrap().take() {
+                    Some(future) => future,
+                    None => return Ok(Async::Ready(())),
+                },
+            };
+
+            *self = Slot::Owned(future);
+        }
+    }
+}
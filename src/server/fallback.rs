@@ -0,0 +1,242 @@
+use {Body, RecvBody};
+use rewind::Rewind;
+use super::{Background, Closed, Connection, Error, Server};
+use super::h2c::PREFACE;
+
+use bytes::BytesMut;
+use futures::{Async, Future, IntoFuture, Poll};
+use futures::future::Executor;
+use http::{Request, Response};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tower::{NewService, Service};
+
+use std::{error, fmt, io, mem};
+
+/// Serves the connections that do not start with the HTTP/2.0 connection
+/// preface, such as HTTP/1.1 connections.
+///
+/// This is implemented for functions returning an `IntoFuture`, so a closure
+/// that hands the transport to an HTTP/1.1 server may be used.
+pub trait Fallback<T> {
+    /// Completes once the connection has been served.
+    type Future: Future<Item = (), Error = io::Error>;
+
+    /// Serve `io`, which replays the bytes read while looking for the
+    /// preface before reading from the transport.
+    fn serve(&self, io: Rewind<T>) -> Self::Future;
+}
+
+/// Serves a connection over HTTP/2.0 if it starts with the connection preface,
+/// or with a `Fallback` otherwise.
+///
+/// Created by `Server::serve_with_fallback`.
+pub struct ServeWithFallback<T, S, E, B, F>
+where T: AsyncRead + AsyncWrite,
+      S: NewService,
+      B: Body,
+      F: Fallback<T>,
+{
+    state: State<T, S, E, B, F>,
+}
+
+/// How a connection served by `ServeWithFallback` was served.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Served {
+    /// The connection was served over HTTP/2.0, and ended as described.
+    H2(Closed),
+
+    /// The connection was handed to the `Fallback`, which completed.
+    Fallback,
+}
+
+/// Error produced by a `ServeWithFallback` future.
+pub enum FallbackError<S>
+where S: NewService,
+{
+    /// Reading the start of the connection, or the `Fallback`, failed.
+    Io(io::Error),
+
+    /// Error produced by the HTTP/2.0 connection.
+    Connection(Error<S>),
+}
+
+enum State<T, S, E, B, F>
+where T: AsyncRead + AsyncWrite,
+      S: NewService,
+      B: Body,
+      F: Fallback<T>,
+{
+    /// Reading the start of the connection.
+    Detect {
+        io: T,
+        buf: BytesMut,
+        server: Server<S, E, B>,
+        fallback: F,
+    },
+
+    /// Serving the HTTP/2.0 connection.
+    Serve(Connection<Rewind<T>, S, E, B, ()>),
+
+    /// Serving the connection with the `Fallback`.
+    Fallback(F::Future),
+
+    /// Transient state used while transitioning between states.
+    Empty,
+}
+
+// ===== impl Server =====
+
+impl<S, E, B> Server<S, E, B>
+where S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      B: Body,
+      E: Clone,
+{
+    /// Serve `io` over HTTP/2.0 if it starts with the connection preface, or
+    /// hand it to `fallback` otherwise.
+    ///
+    /// This allows a single plaintext port to serve both HTTP/2.0 with prior
+    /// knowledge and HTTP/1.1, without TLS and ALPN. HTTP/1.1 requests with
+    /// `Upgrade: h2c` are handed to `fallback` too, which should answer them
+    /// over HTTP/1.1, ignoring the upgrade.
+    pub fn serve_with_fallback<T, F>(&self, io: T, fallback: F)
+        -> ServeWithFallback<T, S, E, B, F>
+    where T: AsyncRead + AsyncWrite,
+          S: Clone,
+          F: Fallback<T>,
+    {
+        ServeWithFallback {
+            state: State::Detect {
+                io,
+                buf: BytesMut::with_capacity(PREFACE.len()),
+                server: self.clone(),
+                fallback,
+            },
+        }
+    }
+}
+
+// ===== impl Fallback =====
+
+impl<T, F, R> Fallback<T> for F
+where F: Fn(Rewind<T>) -> R,
+      R: IntoFuture<Item = (), Error = io::Error>,
+{
+    type Future = R::Future;
+
+    fn serve(&self, io: Rewind<T>) -> Self::Future {
+        (*self)(io).into_future()
+    }
+}
+
+// ===== impl ServeWithFallback =====
+
+impl<T, S, E, B, F> Future for ServeWithFallback<T, S, E, B, F>
+where T: AsyncRead + AsyncWrite,
+      S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      E: Executor<Background<<S::Service as Service>::Future, B>> + Clone,
+      B: Body + 'static,
+      F: Fallback<T>,
+{
+    type Item = Served;
+    type Error = FallbackError<S>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let preface = match self.state {
+                State::Detect { ref mut io, ref mut buf, .. } => {
+                    let len = buf.len();
+
+                    if len < PREFACE.len() && PREFACE.starts_with(&buf[..]) {
+                        buf.reserve(PREFACE.len() - len);
+                        let n = try_ready!(io.read_buf(buf).map_err(FallbackError::Io));
+
+                        if n == 0 {
+                            debug!("connection closed before its protocol was detected");
+                            return Err(FallbackError::Io(io::ErrorKind::UnexpectedEof.into()));
+                        }
+
+                        continue;
+                    }
+
+                    buf.starts_with(PREFACE)
+                }
+                State::Serve(ref mut connection) => {
+                    let closed = try_ready!(connection.poll().map_err(FallbackError::Connection));
+                    return Ok(Async::Ready(Served::H2(closed)));
+                }
+                State::Fallback(ref mut future) => {
+                    try_ready!(future.poll().map_err(FallbackError::Io));
+                    return Ok(Async::Ready(Served::Fallback));
+                }
+                State::Empty => unreachable!(),
+            };
+
+            self.state = match mem::replace(&mut self.state, State::Empty) {
+                State::Detect { io, buf, server, fallback } => {
+                    let io = Rewind::new(io, buf.freeze());
+
+                    if preface {
+                        trace!("received HTTP/2.0 connection preface");
+                        State::Serve(server.serve(io))
+                    } else {
+                        debug!("no HTTP/2.0 connection preface; using fallback");
+                        State::Fallback(fallback.serve(io))
+                    }
+                }
+                _ => unreachable!(),
+            };
+        }
+    }
+}
+
+// ===== impl FallbackError =====
+
+impl<S> fmt::Debug for FallbackError<S>
+where
+    Error<S>: fmt::Debug,
+    S: NewService,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FallbackError::Io(ref why) =>
+                f.debug_tuple("Io").field(why).finish(),
+            FallbackError::Connection(ref why) =>
+                f.debug_tuple("Connection").field(why).finish(),
+        }
+    }
+}
+
+impl<S> fmt::Display for FallbackError<S>
+where
+    Error<S>: fmt::Display,
+    S: NewService,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FallbackError::Io(ref why) =>
+                write!(f, "Error occurred while serving connection: {}", why),
+            FallbackError::Connection(ref why) =>
+                write!(f, "{}", why),
+        }
+    }
+}
+
+impl<S> error::Error for FallbackError<S>
+where
+    Error<S>: error::Error,
+    S: NewService,
+{
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            FallbackError::Io(ref why) => Some(why),
+            FallbackError::Connection(ref why) => Some(why),
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            FallbackError::Io(_) => "error occurred while serving connection",
+            FallbackError::Connection(_) => "error produced by HTTP/2.0 connection",
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! Serving HTTP/2.0 over plaintext connections (h2c).
+//!
+//! A connection is served over HTTP/2.0 if it starts with the HTTP/2.0
+//! connection preface (prior knowledge).
+//!
+//! Upgrading from HTTP/1.1 with `Upgrade: h2c` is not supported: the server
+//! must answer the upgrade request on stream 1, and h2 does not support
+//! adopting a request as stream 1. Rather than switching protocols and
+//! leaving the request unanswered, HTTP/1.x requests, including upgrade
+//! requests, are answered with `505 HTTP Version Not Supported` and the
+//! connection is closed, so that clients may retry with prior knowledge. To
+//! serve such requests over HTTP/1.1 instead, ignoring the upgrade, use
+//! `Server::serve_with_fallback`.
+
+use {Body, RecvBody};
+use rewind::Rewind;
+use super::{Background, Closed, Connection, Error, Server};
+
+use bytes::BytesMut;
+use futures::{Future, Poll};
+use futures::future::Executor;
+use http::{Request, Response};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_io::io::{write_all, WriteAll};
+use tower::{NewService, Service};
+
+use std::{error, fmt, io, mem};
+
+/// The HTTP/2.0 connection preface sent by clients.
+pub(super) const PREFACE: &'static [u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// The response sent to HTTP/1.x requests, including `Upgrade: h2c` requests.
+const VERSION_NOT_SUPPORTED: &'static [u8] =
+    b"HTTP/1.1 505 HTTP Version Not Supported\r\nConnection: close\r\nContent-Length: 0\r\n\r\n";
+
+/// The maximum size of an HTTP/1.x request line.
+const MAX_LINE_SIZE: usize = 8 * 1024;
+
+/// Detects how the peer wishes to start HTTP/2.0 and then drives the
+/// connection.
+///
+/// Created by `Server::serve_h2c`.
+pub struct ServeH2c<T, S, E, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService,
+      B: Body,
+{
+    state: State<T, S, E, B>,
+}
+
+enum State<T, S, E, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService,
+      B: Body,
+{
+    /// Reading the start of the connection.
+    Detect {
+        io: T,
+        buf: BytesMut,
+        server: Server<S, E, B>,
+    },
+
+    /// Answering an HTTP/1.x request with `505 HTTP Version Not Supported`.
+    Reject(WriteAll<T, &'static [u8]>),
+
+    /// Serving the HTTP/2.0 connection.
+    Serve(Connection<Rewind<T>, S, E, B, ()>),
+
+    /// Transient state used while transitioning between states.
+    Empty,
+}
+
+/// Error produced by a `ServeH2c` future.
+pub enum H2cError<S>
+where S: NewService,
+{
+    /// Reading the start of the connection, or answering an HTTP/1.x
+    /// request, failed.
+    Io(io::Error),
+
+    /// The peer did not send the HTTP/2.0 connection preface.
+    ///
+    /// If the peer sent an HTTP/1.x request, it was answered with `505 HTTP
+    /// Version Not Supported` first.
+    NotH2c,
+
+    /// Error produced by the HTTP/2.0 connection.
+    Connection(Error<S>),
+}
+
+/// The outcome of inspecting the start of a connection.
+#[derive(Debug, PartialEq)]
+enum Detected {
+    NeedMore,
+    Preface,
+    Http1,
+    NotH2c,
+}
+
+/// Transition to perform after polling a `ServeH2c`.
+enum Step {
+    Preface,
+    Reject,
+}
+
+// ===== impl Server =====
+
+impl<S, E, B> Server<S, E, B>
+where S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      B: Body,
+      E: Clone,
+{
+    /// Serve HTTP/2.0 with prior knowledge on a plaintext `io`, answering
+    /// HTTP/1.x requests with `505 HTTP Version Not Supported`.
+    pub fn serve_h2c<T>(&self, io: T) -> ServeH2c<T, S, E, B>
+    where T: AsyncRead + AsyncWrite,
+          S: Clone,
+    {
+        ServeH2c {
+            state: State::Detect {
+                io,
+                buf: BytesMut::with_capacity(PREFACE.len()),
+                server: self.clone(),
+            },
+        }
+    }
+}
+
+// ===== impl ServeH2c =====
+
+impl<T, S, E, B> Future for ServeH2c<T, S, E, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      E: Executor<Background<<S::Service as Service>::Future, B>> + Clone,
+      B: Body + 'static,
+{
+    type Item = Closed;
+    type Error = H2cError<S>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let step = match self.state {
+                State::Detect { ref mut io, ref mut buf, .. } => {
+                    match detect(buf) {
+                        Detected::NeedMore => {
+                            buf.reserve(MAX_LINE_SIZE);
+                            let n = try_ready!(io.read_buf(buf).map_err(H2cError::Io));
+
+                            if n == 0 {
+                                debug!("connection closed before h2c was detected");
+                                return Err(H2cError::Io(io::ErrorKind::UnexpectedEof.into()));
+                            }
+
+                            continue;
+                        }
+                        Detected::Preface => Step::Preface,
+                        Detected::Http1 => Step::Reject,
+                        Detected::NotH2c => {
+                            debug!("connection does not use h2c");
+                            return Err(H2cError::NotH2c);
+                        }
+                    }
+                }
+                State::Reject(ref mut write) => {
+                    try_ready!(write.poll().map_err(H2cError::Io));
+                    debug!("answered HTTP/1.x request; h2c upgrades are not supported");
+                    return Err(H2cError::NotH2c);
+                }
+                State::Serve(ref mut connection) => {
+                    return connection.poll().map_err(H2cError::Connection);
+                }
+                State::Empty => unreachable!(),
+            };
+
+            self.state = match (mem::replace(&mut self.state, State::Empty), step) {
+                (State::Detect { io, buf, server }, Step::Preface) => {
+                    trace!("received HTTP/2.0 connection preface");
+                    State::Serve(server.serve(Rewind::new(io, buf.freeze())))
+                }
+                (State::Detect { io, .. }, Step::Reject) => {
+                    trace!("rejecting HTTP/1.x request");
+                    State::Reject(write_all(io, VERSION_NOT_SUPPORTED))
+                }
+                _ => unreachable!(),
+            };
+        }
+    }
+}
+
+/// Inspects the bytes read so far from the start of a connection.
+fn detect(buf: &[u8]) -> Detected {
+    if buf.len() < PREFACE.len() && PREFACE.starts_with(buf) {
+        return Detected::NeedMore;
+    }
+
+    if buf.starts_with(PREFACE) {
+        return Detected::Preface;
+    }
+
+    let line = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(pos) => &buf[..pos],
+        None if buf.len() < MAX_LINE_SIZE => return Detected::NeedMore,
+        None => return Detected::NotH2c,
+    };
+
+    // Only the request line is needed to answer the request, whatever its
+    // headers and body.
+    if line.ends_with(b" HTTP/1.1") || line.ends_with(b" HTTP/1.0") {
+        Detected::Http1
+    } else {
+        Detected::NotH2c
+    }
+}
+
+// ===== impl H2cError =====
+
+impl<S> fmt::Debug for H2cError<S>
+where
+    Error<S>: fmt::Debug,
+    S: NewService,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            H2cError::Io(ref why) =>
+                f.debug_tuple("Io").field(why).finish(),
+            H2cError::NotH2c =>
+                f.debug_tuple("NotH2c").finish(),
+            H2cError::Connection(ref why) =>
+                f.debug_tuple("Connection").field(why).finish(),
+        }
+    }
+}
+
+impl<S> fmt::Display for H2cError<S>
+where
+    Error<S>: fmt::Display,
+    S: NewService,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            H2cError::Io(ref why) =>
+                write!(f, "Error occurred while detecting h2c: {}", why),
+            H2cError::NotH2c =>
+                write!(f, "Peer did not use h2c"),
+            H2cError::Connection(ref why) =>
+                write!(f, "{}", why),
+        }
+    }
+}
+
+impl<S> error::Error for H2cError<S>
+where
+    Error<S>: error::Error,
+    S: NewService,
+{
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            H2cError::Io(ref why) => Some(why),
+            H2cError::NotH2c => None,
+            H2cError::Connection(ref why) => Some(why),
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            H2cError::Io(_) => "error occurred while detecting h2c",
+            H2cError::NotH2c => "peer did not use h2c",
+            H2cError::Connection(_) => "error produced by HTTP/2.0 connection",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect, Detected, MAX_LINE_SIZE, PREFACE};
+
+    #[test]
+    fn detect_preface() {
+        assert_eq!(detect(PREFACE), Detected::Preface);
+
+        let mut buf = PREFACE.to_vec();
+        buf.extend_from_slice(b"\0\0\0\x04");
+        assert_eq!(detect(&buf), Detected::Preface);
+    }
+
+    #[test]
+    fn detect_partial_preface() {
+        assert_eq!(detect(b""), Detected::NeedMore);
+        assert_eq!(detect(&PREFACE[..10]), Detected::NeedMore);
+    }
+
+    #[test]
+    fn detect_http1() {
+        assert_eq!(detect(b"GET / HTTP/1.1\r\nHost: a\r\n"), Detected::Http1);
+        assert_eq!(detect(b"POST /x HTTP/1.0\r\n"), Detected::Http1);
+        assert_eq!(detect(b"GET / HTTP/1.1"), Detected::NeedMore);
+    }
+
+    #[test]
+    fn detect_not_h2c() {
+        assert_eq!(detect(b"\x16\x03\x01\x00\xa5\r\n"), Detected::NotH2c);
+        assert_eq!(detect(b"GET / HTTP/2.0\r\n"), Detected::NotH2c);
+        assert_eq!(detect(&vec![b'a'; MAX_LINE_SIZE]), Detected::NotH2c);
+    }
+}
@@ -0,0 +1,87 @@
+use futures::Async;
+use futures::task::AtomicTask;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts, and optionally limits, the number of `Background` tasks a
+/// connection has in flight.
+#[derive(Clone)]
+pub(super) struct InFlight {
+    max: Option<usize>,
+    shared: Arc<Shared>,
+}
+
+/// Held by a `Background` task for as long as it is in flight.
+///
+/// Dropping the guard releases its slot and notifies the connection task.
+pub(super) struct Guard {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    active: AtomicUsize,
+    task: AtomicTask,
+}
+
+// ===== impl InFlight =====
+
+impl InFlight {
+    pub fn new(max: Option<usize>) -> Self {
+        InFlight {
+            max,
+            shared: Arc::new(Shared {
+                active: AtomicUsize::new(0),
+                task: AtomicTask::new(),
+            }),
+        }
+    }
+
+    /// Returns `Ready` when another task may be spawned.
+    ///
+    /// If `NotReady` is returned, the current task is notified once a slot is
+    /// released.
+    pub fn poll_acquire(&self) -> Async<()> {
+        // Register before checking the count so that a release racing with
+        // this check is not missed.
+        let max = match self.max {
+            Some(max) => max,
+            None => return Async::Ready(()),
+        };
+
+        self.shared.task.register();
+
+        if self.active() < max {
+            Async::Ready(())
+        } else {
+            trace!("max in-flight streams reached; max={}", max);
+            Async::NotReady
+        }
+    }
+
+    /// Registers the current task to be notified whenever a slot is
+    /// released.
+    pub fn register(&self) {
+        self.shared.task.register();
+    }
+
+    /// Returns the number of tasks currently in flight.
+    pub fn active(&self) -> usize {
+        self.shared.active.load(Ordering::Acquire)
+    }
+
+    /// Claim a slot for a new task.
+    pub fn acquire(&self) -> Guard {
+        self.shared.active.fetch_add(1, Ordering::AcqRel);
+        Guard { shared: self.shared.clone() }
+    }
+}
+
+// ===== impl Guard =====
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.shared.active.fetch_sub(1, Ordering::AcqRel);
+        self.shared.task.notify();
+    }
+}
@@ -0,0 +1,146 @@
+use {Body, RecvBody};
+use super::{Background, Connection, Error, ModifyRequest, NewModify, Server};
+use super::drain::Watch;
+
+use futures::{Async, Future, Poll, Stream};
+use futures::future::Executor;
+use http::{Request, Response};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tower::{NewService, Service};
+
+use std::sync::Arc;
+
+/// Accepts connections from a stream of transports, spawning a task to serve
+/// each of them.
+///
+/// Created by `Server::serve_incoming` and `Server::serve_incoming_modified`.
+pub struct ServeIncoming<I, S, E, B, H, N = ()>
+where S: NewService,
+      B: Body,
+{
+    incoming: I,
+    server: Server<S, E, B>,
+    new_modify: N,
+    on_error: Arc<H>,
+    drain: Option<Watch>,
+}
+
+/// Task that drives a single connection accepted by `ServeIncoming`.
+pub struct ConnectionTask<T, S, E, B, H, F = ()>
+where T: AsyncRead + AsyncWrite,
+      S: NewService,
+      B: Body,
+{
+    connection: Connection<T, S, E, B, F>,
+    on_error: Arc<H>,
+}
+
+// ===== impl Server =====
+
+impl<S, E, B> Server<S, E, B>
+where S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      B: Body,
+{
+    /// Serve every transport yielded by `incoming`.
+    ///
+    /// Each connection is spawned onto the server's executor. Errors produced
+    /// by a connection, or while spawning it, are passed to `on_error`.
+    ///
+    /// The returned future completes once `incoming` ends or the server's
+    /// drain is signaled, or fails with the error produced by `incoming`.
+    /// Connections that were already spawned continue to run.
+    pub fn serve_incoming<I, H>(self, incoming: I, on_error: H)
+        -> ServeIncoming<I, S, E, B, H>
+    where I: Stream,
+          I::Item: AsyncRead + AsyncWrite,
+          H: Fn(Error<S>),
+    {
+        self.serve_incoming_modified(incoming, (), on_error)
+    }
+
+    /// Serve every transport yielded by `incoming`, modifying the requests
+    /// of each connection with a `ModifyRequest` created for it by
+    /// `new_modify`.
+    ///
+    /// See `serve_incoming` for details.
+    pub fn serve_incoming_modified<I, N, H>(self, incoming: I, new_modify: N, on_error: H)
+        -> ServeIncoming<I, S, E, B, H, N>
+    where I: Stream,
+          I::Item: AsyncRead + AsyncWrite,
+          N: NewModify,
+          H: Fn(Error<S>),
+    {
+        let drain = self.builder.drain_watch();
+
+        ServeIncoming {
+            incoming,
+            server: self,
+            new_modify,
+            on_error: Arc::new(on_error),
+            drain,
+        }
+    }
+}
+
+// ===== impl ServeIncoming =====
+
+impl<I, S, E, B, H, N> Future for ServeIncoming<I, S, E, B, H, N>
+where I: Stream,
+      I::Item: AsyncRead + AsyncWrite,
+      S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      E: Executor<Background<<S::Service as Service>::Future, B>>,
+      E: Executor<ConnectionTask<I::Item, S, E, B, H, N::Modify>>,
+      E: Clone,
+      B: Body + 'static,
+      H: Fn(Error<S>),
+      N: NewModify,
+{
+    type Item = ();
+    type Error = I::Error;
+
+    fn poll(&mut self) -> Poll<(), I::Error> {
+        loop {
+            if let Some(ref mut drain) = self.drain {
+                if drain.poll_signal() {
+                    debug!("drain signaled; no longer accepting connections");
+                    return Ok(Async::Ready(()));
+                }
+            }
+
+            let io = match try_ready!(self.incoming.poll()) {
+                Some(io) => io,
+                None => return Ok(Async::Ready(())),
+            };
+
+            let task = ConnectionTask {
+                connection: self.server.serve_new_modified(io, &self.new_modify),
+                on_error: self.on_error.clone(),
+            };
+
+            if let Err(_) = self.server.executor.execute(task) {
+                (self.on_error)(Error::Execute);
+            }
+        }
+    }
+}
+
+// ===== impl ConnectionTask =====
+
+impl<T, S, E, B, H, F> Future for ConnectionTask<T, S, E, B, H, F>
+where T: AsyncRead + AsyncWrite,
+      S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      E: Executor<Background<<S::Service as Service>::Future, B>>,
+      B: Body + 'static,
+      H: Fn(Error<S>),
+      F: ModifyRequest,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        let on_error = &self.on_error;
+        let closed = try_ready!(self.connection.poll().map_err(|e| on_error(e)));
+        trace!("connection task complete; {:?}", closed);
+        Ok(Async::Ready(()))
+    }
+}
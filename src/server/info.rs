@@ -0,0 +1,52 @@
+use std::net::SocketAddr;
+
+/// Describes the transport an HTTP/2.0 connection was accepted on.
+///
+/// When provided to `Server::serve_with_info`, a clone of the
+/// `ConnectionInfo` is inserted into the extensions of every request received
+/// on the connection, before the request is modified or dispatched.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionInfo {
+    remote_addr: Option<SocketAddr>,
+    local_addr: Option<SocketAddr>,
+    alpn_protocol: Option<Vec<u8>>,
+}
+
+// ===== impl ConnectionInfo =====
+
+impl ConnectionInfo {
+    /// Returns a new, empty `ConnectionInfo`.
+    pub fn new() -> Self {
+        ConnectionInfo::default()
+    }
+
+    /// Returns the address of the remote peer, if known.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// Sets the address of the remote peer.
+    pub fn set_remote_addr(&mut self, addr: SocketAddr) {
+        self.remote_addr = Some(addr);
+    }
+
+    /// Returns the local address the connection was accepted on, if known.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Sets the local address the connection was accepted on.
+    pub fn set_local_addr(&mut self, addr: SocketAddr) {
+        self.local_addr = Some(addr);
+    }
+
+    /// Returns the protocol negotiated via TLS ALPN, if any.
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn_protocol.as_ref().map(|p| &p[..])
+    }
+
+    /// Sets the protocol negotiated via TLS ALPN.
+    pub fn set_alpn_protocol(&mut self, protocol: Vec<u8>) {
+        self.alpn_protocol = Some(protocol);
+    }
+}
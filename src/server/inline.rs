@@ -0,0 +1,132 @@
+use {Body, RecvBody};
+use super::{Background, Closed, Connection, Error, Server};
+
+use futures::{Async, Future, Poll, Stream};
+use futures::future::{Executor, ExecuteError};
+use futures::stream::FuturesUnordered;
+use http::{Request, Response};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tower::{NewService, Service};
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// A connection whose streams are driven by the connection's own task.
+///
+/// Created by `Server::serve_inline`.
+pub struct InlineConnection<T, S, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService,
+      B: Body,
+{
+    connection: Connection<T, S, Inline<Task<S, B>>, B, ()>,
+    tasks: Tasks<Task<S, B>>,
+    closed: Option<Closed>,
+}
+
+/// The `Background` task of a stream served by an `InlineConnection`.
+type Task<S, B> = Background<<<S as NewService>::Service as Service>::Future, B>;
+
+type Tasks<F> = Rc<RefCell<FuturesUnordered<F>>>;
+
+/// Queues the tasks "spawned" by a connection so that the connection's task
+/// can drive them.
+struct Inline<F> {
+    tasks: Tasks<F>,
+}
+
+// ===== impl Server =====
+
+impl<S, E, B> Server<S, E, B>
+where S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      B: Body,
+{
+    /// Produces a future that serves a connection without spawning any
+    /// tasks.
+    ///
+    /// The response futures and bodies of the connection's streams are
+    /// driven by the returned future itself, rather than spawned onto the
+    /// server's executor, which is not used. This suits single-threaded
+    /// executors and tests. The number of streams processed at once may be
+    /// limited with `Builder::max_in_flight`.
+    pub fn serve_inline<T>(&self, io: T) -> InlineConnection<T, S, B>
+    where T: AsyncRead + AsyncWrite,
+    {
+        let tasks = Rc::new(RefCell::new(FuturesUnordered::new()));
+        let executor = Inline { tasks: tasks.clone() };
+
+        InlineConnection {
+            connection: self.serve_on(io, None, (), (), executor),
+            tasks,
+            closed: None,
+        }
+    }
+}
+
+// ===== impl InlineConnection =====
+
+impl<T, S, B> Future for InlineConnection<T, S, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      B: Body + 'static,
+{
+    type Item = Closed;
+    type Error = Error<S>;
+
+    fn poll(&mut self) -> Poll<Closed, Error<S>> {
+        if self.closed.is_none() {
+            if let Async::Ready(closed) = self.connection.poll()? {
+                self.closed = Some(closed);
+            }
+        }
+
+        // Drive the streams' tasks, including any queued by polling the
+        // connection above.
+        loop {
+            let res = self.tasks.borrow_mut().poll();
+
+            match res {
+                // Background tasks report their own errors.
+                Ok(Async::Ready(Some(()))) | Err(()) => continue,
+                Ok(Async::Ready(None)) => break,
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+            }
+        }
+
+        match self.closed {
+            Some(closed) => Ok(Async::Ready(closed)),
+            None => Ok(Async::NotReady),
+        }
+    }
+}
+
+impl<T, S, B> fmt::Debug for InlineConnection<T, S, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService,
+      B: Body,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("InlineConnection")
+            .field("tasks", &self.tasks.borrow().len())
+            .field("closed", &self.closed)
+            .finish()
+    }
+}
+
+// ===== impl Inline =====
+
+impl<F> Clone for Inline<F> {
+    fn clone(&self) -> Self {
+        Inline { tasks: self.tasks.clone() }
+    }
+}
+
+impl<F> Executor<F> for Inline<F>
+where F: Future<Item = (), Error = ()>,
+{
+    fn execute(&self, future: F) -> Result<(), ExecuteError<F>> {
+        self.tasks.borrow_mut().push(future);
+        Ok(())
+    }
+}
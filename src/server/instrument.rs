@@ -0,0 +1,121 @@
+use FlushStats;
+use super::Closed;
+use super::stats;
+
+use futures::Poll;
+use h2::Reason;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+/// Observes the activity of a server's connections and streams.
+///
+/// Every method has a default implementation that does nothing, so
+/// implementations need only override the events they are interested in. An
+/// `Instrument` is shared by all connections of a `Server`, so it must be
+/// thread-safe.
+pub trait Instrument: Send + Sync + 'static {
+    /// Called when a connection completes the HTTP/2.0 handshake.
+    fn on_handshake(&self) {}
+
+    /// Called when a stream is received from the peer.
+    fn on_stream_open(&self) {}
+
+    /// Called when the server is done with a stream.
+    ///
+    /// `reason` is `None` if the response was sent successfully, or the
+    /// reason the stream was reset or failed otherwise.
+    fn on_stream_close(&self, _reason: Option<Reason>) {}
+
+    /// Called when the server is done sending a response body, whether it
+    /// was sent completely or sending it failed.
+    ///
+    /// This is not called for responses without a body, or if either side
+    /// resets the stream while the body is being sent.
+    fn on_body_sent(&self, _stats: &FlushStats) {}
+
+    /// Called when a connection starts shutting down by sending a GOAWAY.
+    fn on_goaway(&self) {}
+
+    /// Called when a connection closes without error, with how it ended and
+    /// the number of streams it completed.
+    fn on_connection_close(&self, _closed: &Closed) {}
+
+    /// Called when bytes are written to a connection's transport.
+    fn on_bytes_sent(&self, _n: usize) {}
+
+    /// Called when bytes are read from a connection's transport.
+    fn on_bytes_received(&self, _n: usize) {}
+}
+
+/// An `Instrument` shared by a server's connections and tasks.
+pub(super) type Shared = Arc<Instrument>;
+
+/// Wraps a connection's transport, reporting the bytes read and written to an
+/// `Instrument` and the connection's `Stats`.
+pub(super) struct Io<T> {
+    inner: T,
+    instrument: Option<Shared>,
+    stats: stats::Shared,
+}
+
+// ===== impl Io =====
+
+impl<T> Io<T> {
+    pub fn new(inner: T, instrument: Option<Shared>, stats: stats::Shared) -> Self {
+        Io { inner, instrument, stats }
+    }
+}
+
+impl<T: Read> Read for Io<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.stats.bytes_received(n);
+
+        if let Some(ref instrument) = self.instrument {
+            instrument.on_bytes_received(n);
+        }
+
+        Ok(n)
+    }
+}
+
+impl<T: Write> Write for Io<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.stats.bytes_sent(n);
+
+        if let Some(ref instrument) = self.instrument {
+            instrument.on_bytes_sent(n);
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for Io<T> {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.inner.prepare_uninitialized_buffer(buf)
+    }
+}
+
+impl<T: AsyncWrite> AsyncWrite for Io<T> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Io<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Io")
+            .field("inner", &self.inner)
+            .field("instrument", &self.instrument.is_some())
+            .finish()
+    }
+}
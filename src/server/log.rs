@@ -0,0 +1,99 @@
+use h2::Reason;
+use http::{Method, Request, StatusCode};
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A record of a single stream processed by a `Server`.
+///
+/// Passed to the callback configured with `Builder::access_log` once the
+/// server is done with the stream.
+#[derive(Debug)]
+pub struct Log {
+    method: Method,
+    path: String,
+    status: Option<StatusCode>,
+    duration: Duration,
+    bytes_sent: usize,
+    reset: Option<Reason>,
+}
+
+/// Callback invoked with the `Log` of each stream.
+pub(super) type LogHandler = Arc<Fn(&Log) + Send + Sync>;
+
+/// A `Log` for a stream that is still being processed.
+pub(super) struct Pending {
+    handler: LogHandler,
+    method: Method,
+    path: String,
+    status: Option<StatusCode>,
+    start: Instant,
+}
+
+// ===== impl Log =====
+
+impl Log {
+    /// Returns the request method.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the request path.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the response status, if a response was sent.
+    pub fn status(&self) -> Option<StatusCode> {
+        self.status
+    }
+
+    /// Returns the time elapsed between the stream being received and the
+    /// server being done with it.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the number of response body bytes sent.
+    pub fn bytes_sent(&self) -> usize {
+        self.bytes_sent
+    }
+
+    /// Returns the reason the stream was reset or failed, if it did not
+    /// complete successfully.
+    pub fn reset_reason(&self) -> Option<Reason> {
+        self.reset
+    }
+}
+
+// ===== impl Pending =====
+
+impl Pending {
+    pub fn new<B>(handler: LogHandler, request: &Request<B>) -> Self {
+        Pending {
+            handler,
+            method: request.method().clone(),
+            path: request.uri().path().to_owned(),
+            status: None,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn set_status(&mut self, status: StatusCode) {
+        self.status = Some(status);
+    }
+
+    /// Completes the `Log` and passes it to the handler.
+    pub fn finish(self, bytes_sent: usize, reset: Option<Reason>) {
+        let log = Log {
+            method: self.method,
+            path: self.path,
+            status: self.status,
+            duration: self.start.elapsed(),
+            bytes_sent,
+            reset,
+        };
+
+        (self.handler)(&log);
+    }
+}
@@ -0,0 +1,92 @@
+use {Body, RecvBody};
+use super::{Builder, Connection, ConnectionInfo, Server};
+
+use http::{Request, Response};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tower::NewService;
+
+use std::marker::PhantomData;
+
+/// Creates the `NewService` used for a connection, given information about
+/// that connection.
+///
+/// This allows each connection's service to capture details such as the peer
+/// address or negotiated protocol when it is constructed.
+pub trait MakeService {
+    /// The `NewService` used to obtain the connection's service.
+    type NewService: NewService;
+
+    /// Returns the `NewService` for a connection described by `info`.
+    fn make_service(&self, info: &ConnectionInfo) -> Self::NewService;
+}
+
+/// Serves connections using a service created for each connection by a
+/// `MakeService`.
+pub struct MakeServer<M, E, B> {
+    make: M,
+    builder: Builder,
+    executor: E,
+    _p: PhantomData<B>,
+}
+
+// ===== impl MakeService =====
+
+impl<F, N> MakeService for F
+where F: Fn(&ConnectionInfo) -> N,
+      N: NewService,
+{
+    type NewService = N;
+
+    fn make_service(&self, info: &ConnectionInfo) -> N {
+        (*self)(info)
+    }
+}
+
+// ===== impl MakeServer =====
+
+impl<M, E, B> MakeServer<M, E, B>
+where M: MakeService,
+      M::NewService: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      E: Clone,
+      B: Body,
+{
+    /// Create a new `MakeServer` that uses `make` to obtain a `NewService` for
+    /// each connection, configured by `builder`.
+    pub fn new(make: M, builder: Builder, executor: E) -> Self {
+        MakeServer {
+            make,
+            builder,
+            executor,
+            _p: PhantomData,
+        }
+    }
+
+    /// Serve the connection `io`, described by `info`.
+    ///
+    /// The connection's service is obtained from the `NewService` returned by
+    /// `MakeService::make_service` for `info`. A clone of `info` is also
+    /// inserted into the extensions of every request, as with
+    /// `Server::serve_with_info`.
+    pub fn serve_with_info<T>(&self, io: T, info: ConnectionInfo)
+        -> Connection<T, M::NewService, E, B, ()>
+    where T: AsyncRead + AsyncWrite,
+    {
+        let new_service = self.make.make_service(&info);
+        let server = Server::new(new_service, self.builder.clone(), self.executor.clone());
+        server.serve_with_info(io, info)
+    }
+}
+
+impl<M, E, B> Clone for MakeServer<M, E, B>
+where M: Clone,
+      E: Clone,
+{
+    fn clone(&self) -> Self {
+        MakeServer {
+            make: self.make.clone(),
+            builder: self.builder.clone(),
+            executor: self.executor.clone(),
+            _p: PhantomData,
+        }
+    }
+}
@@ -1,15 +1,98 @@
-use {flush, Body, RecvBody};
-
-use futures::{Future, Poll, Stream};
-use futures::future::{Executor, Either, Join, MapErr};
+use {content_length, flush, Body, BufferPool, FlushStats, RecvBody};
+use body::BodyBuf;
+use buf::SendBuf;
+use flush::FlushError;
+use reset::Reset;
+
+use bytes::{Buf, IntoBuf};
+use futures::{Async, Future, Poll, Stream};
+use futures::future::{Executor, ExecuteErrorKind, Either, Join, MapErr};
+use futures::task;
 use h2::{self, Reason};
-use h2::server::{Connection as Accept, Handshake, SendResponse};
+use h2::server::{Connection as H2Connection, Handshake, SendResponse};
 use http::{self, Request, Response};
+use http::header::HeaderValue;
 use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_timer::{Sleep, Timer};
 use tower::{NewService, Service};
 
-use std::{error, fmt};
+use std::{cmp, error, fmt, mem};
 use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Duration;
+
+use self::date::Date;
+use self::drain::Watch;
+use self::in_flight::InFlight;
+use self::state::Control;
+
+mod accept;
+mod authority;
+mod backpressure;
+mod bind;
+mod boxed;
+mod builder;
+mod cancellation;
+mod completion;
+mod concurrency;
+mod date;
+mod deadline;
+pub mod drain;
+mod executor;
+mod fallback;
+pub mod h2c;
+mod in_flight;
+mod incoming;
+mod info;
+mod inline;
+mod instrument;
+mod log;
+mod make;
+pub mod parts;
+mod peer_settings;
+mod priority;
+mod proxy;
+mod rate;
+mod ready;
+mod recover;
+mod sanitize;
+mod shared;
+mod state;
+mod stats;
+mod stream_id;
+#[cfg(feature = "tls")]
+pub mod tls;
+
+pub use self::accept::{Accept, Reject};
+pub use self::backpressure::Backpressure;
+pub use self::bind::{bind, Bind};
+pub use self::boxed::{BoxError, ServeBoxed};
+pub use self::builder::{Builder, ExecuteErrorPolicy, NotReadyPolicy};
+pub use self::cancellation::Cancellation;
+pub use self::completion::StreamCounts;
+pub use self::concurrency::ConcurrencyLimit;
+pub use self::deadline::Deadline;
+pub use self::executor::{BoxBackground, BoxedExecutor};
+pub use self::fallback::{Fallback, FallbackError, Served, ServeWithFallback};
+pub use self::h2c::ServeH2c;
+pub use self::incoming::{ServeIncoming, ConnectionTask};
+
+use self::builder::ErrorHandler;
+pub use self::info::ConnectionInfo;
+pub use self::inline::InlineConnection;
+pub use self::instrument::Instrument;
+pub use self::log::Log;
+pub use self::make::{MakeServer, MakeService};
+pub use self::peer_settings::PeerSettings;
+pub use self::priority::PriorityExecutor;
+pub use self::proxy::{ProxyError, ServeProxied};
+pub use self::ready::ReadyNewService;
+pub use self::recover::Recover;
+pub use self::shared::{Never, SharedService};
+pub use self::state::{ConnectionHandle, ConnectionState};
+pub use self::stats::Stats;
+pub use self::stream_id::StreamId;
+pub use rewind::Rewind;
 
 /// Attaches service implementations to h2 connections.
 pub struct Server<S, E, B>
@@ -17,20 +100,79 @@ where S: NewService,
       B: Body,
 {
     new_service: S,
-    builder: h2::server::Builder,
+    builder: Builder,
     executor: E,
+    recover: Option<recover::Shared<S::Error, B>>,
+    reset_reason: Option<ResetFn<S::Error>>,
+    goaway_reason: Option<GoawayFn<S::Error>>,
     _p: PhantomData<B>,
 }
 
+/// A `Server` that spawns its tasks onto a type-erased executor.
+pub type BoxServer<S, B> = Server<S, BoxedExecutor, B>;
+
+/// A `Connection` of a `BoxServer`.
+pub type BoxConnection<T, S, B> = Connection<T, S, BoxedExecutor, B, ()>;
+
 /// Drives connection-level I/O .
-pub struct Connection<T, S, E, B, F>
+pub struct Connection<T, S, E, B, F, R = ()>
 where T: AsyncRead + AsyncWrite,
       S: NewService,
       B: Body,
 {
     state: State<T, S, B>,
     executor: E,
+    handshake_timeout: Option<Sleep>,
+    info: Option<ConnectionInfo>,
+    peer_settings: peer_settings::Shared,
+    max_header_list_size: Option<u32>,
+    in_flight: InFlight,
+    concurrency: Option<ConcurrencyLimit>,
+    backpressure: Option<backpressure::Shared>,
+    completion_tx: completion::Sender,
+    completions: completion::Receiver,
+    handle: ConnectionHandle,
+    stats: stats::Shared,
+    on_background_error: Option<ErrorHandler>,
+    stream_idle_timeout: Option<(Timer, Duration)>,
+    response_timeout: Option<(Timer, Duration, Reject)>,
+    deadline_header: Option<(Timer, http::header::HeaderName, Duration)>,
+    drain: Option<Watch>,
+
+    /// Counts the connection as live for the drain until it is dropped.
+    drain_guard: Option<drain::Guard>,
+
+    draining: bool,
+    max_age: Option<Sleep>,
+    idle_timeout: Option<(Timer, Duration)>,
+    idle: Option<Sleep>,
+    idle_expired: bool,
+    aborted: bool,
+    peer_closed: bool,
+    remaining_requests: Option<u64>,
+    stream_rate: Option<rate::TokenBucket>,
+    last_stream_id: StreamId,
+    instrument: Option<instrument::Shared>,
+    access_log: Option<log::LogHandler>,
+    coalesce_watermark: usize,
+    max_queued_chunks: usize,
+    max_send_buffer: Option<usize>,
+    buffer_pool: Option<Arc<BufferPool>>,
+    not_ready: NotReadyPolicy,
+    accept: Option<accept::Shared>,
+    authorities: Option<Arc<authority::Authorities>>,
+    connect: bool,
+    priority: Option<builder::PriorityFn>,
+    strip_connection_headers: bool,
+    execute_error: ExecuteErrorPolicy,
+    date: Option<Date>,
+    server_header: Option<HeaderValue>,
+    retry_spawn: Option<Background<<S::Service as Service>::Future, B, R>>,
+    recover: Option<recover::Shared<S::Error, B>>,
+    reset_reason: Option<ResetFn<S::Error>>,
+    goaway_reason: Option<GoawayFn<S::Error>>,
     modify: F,
+    modify_response: R,
 }
 
 /// Modify a received request
@@ -39,6 +181,51 @@ pub trait Modify {
     fn modify(&mut self, request: &mut Request<()>);
 }
 
+/// Modify a received request, given metadata about its body
+///
+/// Every `Modify` is also a `ModifyRequest` that ignores the metadata. To use
+/// a closure that receives the metadata, wrap it in a `WithMeta`.
+pub trait ModifyRequest {
+    /// Modify a request before calling the service.
+    fn modify(&mut self, request: &mut Request<()>, meta: &RequestMeta);
+}
+
+/// Metadata about the body of a request, passed to `ModifyRequest`.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestMeta {
+    end_stream: bool,
+    content_length: Option<u64>,
+}
+
+/// A `ModifyRequest` that calls a closure with each request and its
+/// `RequestMeta`.
+#[derive(Clone, Debug)]
+pub struct WithMeta<F> {
+    f: F,
+}
+
+/// Creates the `ModifyRequest` used for each connection
+///
+/// A fresh `ModifyRequest` is created for every connection, so it may keep
+/// per-connection state, such as counters or an authentication cache, without
+/// sharing it with the server's other connections.
+pub trait NewModify {
+    /// The `ModifyRequest` of a connection.
+    type Modify: ModifyRequest;
+
+    /// Returns the `ModifyRequest` for a new connection.
+    fn new_modify(&self) -> Self::Modify;
+}
+
+/// Modify a response produced by the service
+///
+/// A clone of the `ModifyResponse` is handed to the `Background` task of each
+/// stream, which applies it immediately before sending the response head.
+pub trait ModifyResponse {
+    /// Modify a response before sending it to the peer.
+    fn modify(&mut self, response: &mut Response<()>);
+}
+
 enum State<T, S, B>
 where T: AsyncRead + AsyncWrite,
       S: NewService,
@@ -46,15 +233,29 @@ where T: AsyncRead + AsyncWrite,
 {
     /// Establish the HTTP/2.0 connection and get a service to process inbound
     /// requests.
-    Init(Init<T, B::Data, S::Future, S::InitError>),
+    Init(Init<ServerIo<T>, BodyBuf<B>, S::Future, S::InitError>),
 
     /// Both the HTTP/2.0 connection and the service are ready.
     Ready {
-        connection: Accept<T, B::Data>,
+        connection: H2Connection<ServerIo<T>, BodyBuf<B>>,
         service: S::Service,
     },
+
+    /// The service failed, so a GOAWAY has been sent and the connection is
+    /// draining before the error is returned.
+    GoAway {
+        connection: H2Connection<ServerIo<T>, BodyBuf<B>>,
+        error: Error<S>,
+    },
+
+    /// The connection is closed.
+    Done,
 }
 
+/// The transport of a `Connection`, wrapped to observe the bytes read and
+/// written.
+type ServerIo<T> = instrument::Io<peer_settings::Io<T>>;
+
 type Init<T, B, S, E> =
     Join<
         MapErr<Handshake<T, B>, MapErrA<E>>,
@@ -63,32 +264,149 @@ type Init<T, B, S, E> =
 type MapErrA<E> = fn(h2::Error) -> Either<h2::Error, E>;
 type MapErrB<E> = fn(E) -> Either<h2::Error, E>;
 
+/// Chooses the reason to reset a stream with when its response future fails.
+type ResetFn<E> = Arc<Fn(&E) -> Reason + Send + Sync>;
+
+/// Chooses whether a service that fails to become ready closes the connection
+/// immediately, and with which reason.
+type GoawayFn<E> = Arc<Fn(&E) -> Option<Reason> + Send + Sync>;
+
 /// Task used to process requests
-pub struct Background<T, B>
-where B: Body,
+pub struct Background<T, B, R = ()>
+where T: Future,
+      B: Body,
 {
     state: BackgroundState<T, B>,
+    modify_response: R,
+    in_flight: Option<in_flight::Guard>,
+
+    /// Counts the task as live for the drain until it completes.
+    drain: Option<drain::Guard>,
+
+    permit: Option<concurrency::Permit>,
+    completion: Option<completion::Sender>,
+    on_error: Option<ErrorHandler>,
+    reset: Option<Reset>,
+    instrument: Option<instrument::Shared>,
+    stats: stats::Shared,
+    log: Option<log::Pending>,
+    coalesce_watermark: usize,
+    max_queued_chunks: usize,
+    max_send_buffer: Option<usize>,
+
+    /// The body bytes buffered by the stream, as last reported to `stats`.
+    send_buffered: usize,
+
+    buffer_pool: Option<Arc<BufferPool>>,
+    cancellation: Option<Reset>,
+
+    /// Whether the request was a `HEAD` request, whose response body is empty
+    /// regardless of its `content-length`.
+    head: bool,
+
+    date: Option<Date>,
+    server_header: Option<HeaderValue>,
+
+    /// Resets the stream with `CANCEL` if the response has not been sent by
+    /// the request's deadline.
+    deadline: Option<Sleep>,
+
+    /// Rejects the stream if the response future has not completed by
+    /// `Builder::response_timeout`.
+    response_timeout: Option<Sleep>,
+    response_timeout_reject: Reject,
+
+    id: StreamId,
+
+    /// The priority a `PriorityExecutor` spawns the task with.
+    priority: u8,
+
+    /// Whether connection-specific response headers are removed, rather than
+    /// failing the stream.
+    strip_connection_headers: bool,
+
+    /// Converts a failed response future into a response to send.
+    recover: Option<recover::Shared<T::Error, B>>,
+
+    /// Chooses the reason to reset the stream with if the response future
+    /// fails. Streams are reset with `INTERNAL_ERROR` if this is `None`.
+    reset_reason: Option<ResetFn<T::Error>>,
 }
 
 enum BackgroundState<T, B>
 where B: Body,
 {
     Respond {
-        respond: SendResponse<B::Data>,
+        respond: SendResponse<BodyBuf<B>>,
         response: T,
     },
     Flush(flush::Flush<B>),
 }
 
+/// How a `Connection` ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Closed {
+    /// The peer closed the connection, either with a GOAWAY or by closing
+    /// the transport.
+    ///
+    /// The connection completes only once the `Background` tasks of its
+    /// streams have finished. h2 does not expose the GOAWAY frames it
+    /// receives, so a GOAWAY with `NO_ERROR` cannot be told apart from the
+    /// transport closing, and its debug data is not available. A GOAWAY with
+    /// any other reason fails the connection with `Error::Protocol`, whose
+    /// reason is that of the GOAWAY.
+    PeerClosed {
+        /// The streams completed by the connection.
+        streams: StreamCounts,
+    },
+
+    /// The server shut the connection down gracefully, because of a drain,
+    /// `Connection::graceful_shutdown`, or the connection's maximum age or
+    /// number of requests.
+    Drained {
+        /// The number of streams whose `Background` tasks were still in
+        /// flight when the connection closed, and so could not complete.
+        in_flight_aborted: usize,
+
+        /// The streams completed by the connection.
+        streams: StreamCounts,
+    },
+
+    /// The server shut the connection down gracefully because it had no
+    /// streams in flight for `Builder::idle_timeout`.
+    Idle {
+        /// The streams completed by the connection.
+        streams: StreamCounts,
+    },
+
+    /// The connection was closed immediately by `ConnectionHandle::abort`.
+    Aborted {
+        /// The number of streams whose `Background` tasks were still in
+        /// flight when the connection closed, and so could not complete.
+        in_flight_aborted: usize,
+
+        /// The streams completed by the connection.
+        streams: StreamCounts,
+    },
+}
+
 /// Error produced by a `Connection`.
-#[derive(Debug)]
 pub enum Error<S>
 where S: NewService,
 {
     /// Error produced during the HTTP/2.0 handshake.
     Handshake(h2::Error),
 
-    /// Error produced by the HTTP/2.0 stream
+    /// Error produced by the HTTP/2.0 connection.
+    ///
+    /// This is always a connection-level error. h2 handles errors on
+    /// individual streams itself by resetting them, and the connection keeps
+    /// serving its other streams.
+    ///
+    /// There is no variant for stream-level errors, as h2 never reports them
+    /// to the connection: they are only observed on the stream itself, as a
+    /// failed `RecvBody` or as a `BackgroundError` passed to
+    /// `Builder::on_background_error`.
     Protocol(h2::Error),
 
     /// Error produced when obtaining the service
@@ -99,6 +417,48 @@ where S: NewService,
 
     /// Error produced when attempting to spawn a task
     Execute,
+
+    /// The peer did not complete the HTTP/2.0 handshake in time.
+    HandshakeTimeout,
+}
+
+/// Error encountered by a `Background` task while sending a response.
+#[derive(Debug)]
+pub enum BackgroundError {
+    /// The response head could not be sent.
+    SendResponse(h2::Error),
+
+    /// The response body could not be sent.
+    Flush(h2::Error),
+
+    /// The request's header list was larger than the configured
+    /// `max_header_list_size`, so it was answered with
+    /// `431 Request Header Fields Too Large` instead of being dispatched.
+    HeaderListSize {
+        /// The size of the request's header list, in octets.
+        size: usize,
+
+        /// The configured maximum size.
+        limit: u32,
+    },
+
+    /// The service's response had a connection-specific header, which
+    /// HTTP/2.0 forbids, so the stream was reset instead of sending it.
+    ///
+    /// Such headers are removed rather than rejected if
+    /// `Builder::strip_connection_headers` is enabled.
+    ConnectionHeader(http::header::HeaderName),
+
+    /// The response body did not match its `content-length` header, so the
+    /// stream was reset.
+    ContentLength {
+        /// The length declared by the `content-length` header.
+        declared: u64,
+
+        /// The number of bytes the body had yielded when the mismatch was
+        /// detected.
+        sent: u64,
+    },
 }
 
 // ===== impl Server =====
@@ -107,14 +467,79 @@ impl<S, E, B> Server<S, E, B>
 where S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
       B: Body,
 {
-    pub fn new(new_service: S, builder: h2::server::Builder, executor: E) -> Self {
+    /// Create a new `Server` that uses `new_service` to obtain a service for
+    /// each connection, configured by `builder`.
+    pub fn new(new_service: S, builder: Builder, executor: E) -> Self {
         Server {
             new_service,
             executor,
             builder,
+            recover: None,
+            reset_reason: None,
+            goaway_reason: None,
+            _p: PhantomData,
+        }
+    }
+
+    /// Erase the type of the server's executor.
+    ///
+    /// The returned server spawns its tasks onto the same executor, boxing
+    /// each of them.
+    pub fn box_executor(self) -> BoxServer<S, B>
+    where E: Executor<BoxBackground> + Send + Sync + 'static,
+    {
+        Server {
+            new_service: self.new_service,
+            builder: self.builder,
+            executor: BoxedExecutor::new(self.executor).erase(),
+            recover: self.recover,
+            reset_reason: self.reset_reason,
+            goaway_reason: self.goaway_reason,
             _p: PhantomData,
         }
     }
+
+    /// Answer requests whose response future fails with the response
+    /// produced by `recover`, rather than resetting their streams.
+    ///
+    /// If `recover` hands the error back, the stream is reset with the
+    /// reason chosen by `Server::reset_reason` as usual.
+    pub fn recover<V>(&mut self, recover: V) -> &mut Self
+    where V: Recover<S::Error, B>,
+    {
+        self.recover = Some(Arc::new(recover));
+        self
+    }
+
+    /// Reset streams whose response future fails with the reason returned by
+    /// `f` for the error.
+    ///
+    /// By default, such streams are reset with `INTERNAL_ERROR`. This is
+    /// configured on the `Server` rather than the `Builder`, as it depends on
+    /// the service's error type.
+    pub fn reset_reason<F>(&mut self, f: F) -> &mut Self
+    where F: Fn(&S::Error) -> Reason + Send + Sync + 'static,
+    {
+        self.reset_reason = Some(Arc::new(f));
+        self
+    }
+
+    /// Choose how a connection closes when its service fails to become ready.
+    ///
+    /// If `f` returns `None` for the error, which is the default, a graceful
+    /// shutdown is started and streams that were already accepted are
+    /// processed before the connection closes. Otherwise, a GOAWAY with the
+    /// returned `Reason` is sent and the connection closes without waiting
+    /// for those streams.
+    ///
+    /// h2 does not support sending debug data with a GOAWAY, so only the
+    /// reason is sent to the peer.
+    pub fn goaway_reason<F>(&mut self, f: F) -> &mut Self
+    where F: Fn(&S::Error) -> Option<Reason> + Send + Sync + 'static,
+    {
+        self.goaway_reason = Some(Arc::new(f));
+        self
+    }
 }
 
 
@@ -130,25 +555,160 @@ where S: NewService<Request = http::Request<RecvBody>, Response = Response<B>>,
         self.serve_modified(io, ())
     }
 
+    /// Produces a future that is satisfied once the h2 connection has been
+    /// initialized, along with a handle that controls the connection.
+    ///
+    /// The handle may be kept after the connection is spawned, for example to
+    /// shut it down gracefully or abort it.
+    pub fn serve_with_handle<T>(&self, io: T)
+        -> (Connection<T, S, E, B, ()>, ConnectionHandle)
+    where T: AsyncRead + AsyncWrite,
+    {
+        let connection = self.serve(io);
+        let handle = connection.handle();
+        (connection, handle)
+    }
+
+    /// Produces a future that is satisfied once the h2 connection has been
+    /// initialized, inserting a clone of `info` into the extensions of every
+    /// request received on the connection.
+    pub fn serve_with_info<T>(&self, io: T, info: ConnectionInfo)
+        -> Connection<T, S, E, B, ()>
+    where T: AsyncRead + AsyncWrite,
+    {
+        self.serve_connection(io, Some(info), (), ())
+    }
+
     pub fn serve_modified<T, F>(&self, io: T, modify: F) -> Connection<T, S, E, B, F>
     where T: AsyncRead + AsyncWrite,
-          F: Modify,
+          F: ModifyRequest,
+    {
+        self.serve_modified2(io, modify, ())
+    }
+
+    /// Produces a future that is satisfied once the h2 connection has been
+    /// initialized, modifying every request with a `ModifyRequest` created
+    /// for the connection by `new_modify`.
+    pub fn serve_new_modified<T, N>(&self, io: T, new_modify: &N)
+        -> Connection<T, S, E, B, N::Modify>
+    where T: AsyncRead + AsyncWrite,
+          N: NewModify,
+    {
+        self.serve_modified(io, new_modify.new_modify())
+    }
+
+    /// Produces a future that is satisfied once the h2 connection has been
+    /// initialized, modifying every request with `modify` before it is
+    /// dispatched and every response with `modify_response` before it is
+    /// sent.
+    pub fn serve_modified2<T, F, R>(&self, io: T, modify: F, modify_response: R)
+        -> Connection<T, S, E, B, F, R>
+    where T: AsyncRead + AsyncWrite,
+          F: ModifyRequest,
+          R: ModifyResponse + Clone,
+    {
+        self.serve_connection(io, None, modify, modify_response)
+    }
+
+    fn serve_connection<T, F, R>(
+        &self,
+        io: T,
+        info: Option<ConnectionInfo>,
+        modify: F,
+        modify_response: R,
+    ) -> Connection<T, S, E, B, F, R>
+    where T: AsyncRead + AsyncWrite,
     {
         // Clone a handle to the executor so that it can be moved into the
         // connection handle
         let executor = self.executor.clone();
+        self.serve_on(io, info, modify, modify_response, executor)
+    }
+}
 
+impl<S, E, B> Server<S, E, B>
+where S: NewService<Request = http::Request<RecvBody>, Response = Response<B>>,
+      B: Body,
+{
+    /// Builds a connection that spawns its streams' tasks onto `executor`.
+    fn serve_on<T, F, R, X>(
+        &self,
+        io: T,
+        info: Option<ConnectionInfo>,
+        modify: F,
+        modify_response: R,
+        executor: X,
+    ) -> Connection<T, S, X, B, F, R>
+    where T: AsyncRead + AsyncWrite,
+    {
         let service = self.new_service.new_service()
             .map_err(Either::B as MapErrB<S::InitError>);
 
-        // TODO we should specify initial settings here!
-        let handshake = self.builder.handshake(io)
+        let instrument = self.builder.instrument_handle();
+        let stats = stats::Shared::default();
+        let peer_settings = peer_settings::Shared::default();
+        let io = peer_settings::Io::new(io, peer_settings.clone());
+        let io = instrument::Io::new(io, instrument.clone(), stats.clone());
+
+        let handshake = self.builder.h2().handshake(io)
             .map_err(Either::A as MapErrA<S::InitError>);
 
+        let in_flight = InFlight::new(self.builder.max_in_flight_limit());
+        let handle = ConnectionHandle::new(self.builder.settings(), in_flight.clone(), stats.clone());
+        let (completion_tx, completions) = completion::channel();
+        let drain = self.builder.drain_watch();
+
         Connection {
             state: State::Init(handshake.join(service)),
             executor,
+            handshake_timeout: self.builder.handshake_timer(),
+            info,
+            peer_settings,
+            max_header_list_size: self.builder.header_list_size_limit(),
+            in_flight,
+            concurrency: self.builder.concurrency(),
+            backpressure: self.builder.backpressure(),
+            completion_tx,
+            completions,
+            handle,
+            stats,
+            on_background_error: self.builder.background_error_handler(),
+            stream_idle_timeout: self.builder.stream_idle_timer(),
+            response_timeout: self.builder.response_timer(),
+            deadline_header: self.builder.deadline_timer(),
+            drain_guard: drain.as_ref().map(Watch::guard),
+            drain,
+            draining: false,
+            max_age: self.builder.max_age_timer(),
+            idle_timeout: self.builder.idle_timer(),
+            idle: None,
+            idle_expired: false,
+            aborted: false,
+            peer_closed: false,
+            remaining_requests: self.builder.max_requests(),
+            stream_rate: self.builder.stream_rate().map(|limit| limit.bucket()),
+            last_stream_id: StreamId::default(),
+            instrument,
+            access_log: self.builder.access_log_handler(),
+            coalesce_watermark: self.builder.coalesce_watermark_size(),
+            max_queued_chunks: self.builder.max_queued(),
+            max_send_buffer: self.builder.max_send_buffer_size(),
+            buffer_pool: self.builder.buffer_pool_ref(),
+            not_ready: self.builder.not_ready(),
+            accept: self.builder.accept_filter(),
+            authorities: self.builder.authorities(),
+            connect: self.builder.connect_enabled(),
+            priority: self.builder.priority_fn(),
+            strip_connection_headers: self.builder.strip_connection_headers_enabled(),
+            execute_error: self.builder.execute_error(),
+            date: self.builder.date(),
+            server_header: self.builder.server_header_value(),
+            retry_spawn: None,
+            recover: self.recover.clone(),
+            reset_reason: self.reset_reason.clone(),
+            goaway_reason: self.goaway_reason.clone(),
             modify,
+            modify_response,
         }
     }
 }
@@ -165,6 +725,9 @@ where
             new_service: self.new_service.clone(),
             executor: self.executor.clone(),
             builder: self.builder.clone(),
+            recover: self.recover.clone(),
+            reset_reason: self.reset_reason.clone(),
+            goaway_reason: self.goaway_reason.clone(),
             _p: PhantomData,
         }
     }
@@ -172,211 +735,1492 @@ where
 
 // ===== impl Connection =====
 
-impl<T, S, E, B, F> Connection<T, S, E, B, F>
+impl<T, S, E, B, F, R> Connection<T, S, E, B, F, R>
 where T: AsyncRead + AsyncWrite,
       S: NewService<Request = http::Request<RecvBody>, Response = Response<B>>,
       B: Body,
 {
-    fn is_ready(&self) -> bool {
-        use self::State::*;
+    /// Returns a handle that samples the state of the connection, even once
+    /// the `Connection` has been spawned.
+    pub fn handle(&self) -> ConnectionHandle {
+        self.handle.clone()
+    }
+
+    /// Returns a snapshot of the state of the connection.
+    pub fn state(&self) -> ConnectionState {
+        self.handle.state()
+    }
+
+    /// Returns the counters of the connection's activity so far.
+    pub fn stats(&self) -> Stats {
+        self.stats.get()
+    }
+
+    /// Returns the settings the peer sent with its connection preface, once
+    /// they have been received.
+    pub fn peer_settings(&self) -> Option<PeerSettings> {
+        self.peer_settings.get()
+    }
+
+    /// Start an HTTP/2.0 graceful shutdown.
+    ///
+    /// A GOAWAY frame is sent to the peer and no new streams are accepted.
+    /// Streams that were already received continue to be processed and the
+    /// `Connection` future completes once they have all finished.
+    ///
+    /// The `Connection` must continue to be polled until shutdown completes.
+    pub fn graceful_shutdown(&mut self) {
+        self.draining = true;
 
         match self.state {
-            Ready { .. } => true,
-            _ => false,
+            State::Init(_) => {
+                // The connection was never established, so there is nothing
+                // to drain.
+            }
+            State::Ready { ref mut connection, .. } => {
+                if let Some(ref instrument) = self.instrument {
+                    instrument.on_goaway();
+                }
+
+                self.handle.goaway(Reason::NO_ERROR);
+                connection.graceful_shutdown();
+                return;
+            }
+            State::GoAway { .. } | State::Done => return,
         }
+
+        self.state = State::Done;
     }
 
-    fn try_ready(&mut self) -> Poll<(), Error<S>> {
+    fn poll_init(&mut self) -> Poll<(), Error<S>> {
         use self::State::*;
 
-        let (connection, service) = match self.state {
-            Init(ref mut join) => try_ready!(join.poll().map_err(Error::from_init)),
+        let ready = match self.state {
+            Init(ref mut join) => join.poll().map_err(Error::from_init)?,
             _ => unreachable!(),
         };
 
+        let (connection, service) = match ready {
+            Async::Ready(ready) => ready,
+            Async::NotReady => {
+                self.poll_handshake_timeout()?;
+                return Ok(Async::NotReady);
+            }
+        };
+
+        self.handshake_timeout = None;
         self.state = Ready { connection, service };
+        self.handle.opened();
+
+        if let Some(ref instrument) = self.instrument {
+            instrument.on_handshake();
+        }
 
         Ok(().into())
     }
-}
 
-impl<T, S, E, B, F> Future for Connection<T, S, E, B, F>
-where T: AsyncRead + AsyncWrite,
-      S: NewService<Request = http::Request<RecvBody>, Response = Response<B>>,
-      E: Executor<Background<<S::Service as Service>::Future, B>>,
-      B: Body + 'static,
-      F: Modify,
-{
-    type Item = ();
-    type Error = Error<S>;
+    /// Fails if the handshake timeout has elapsed.
+    fn poll_handshake_timeout(&mut self) -> Result<(), Error<S>> {
+        let res = match self.handshake_timeout {
+            Some(ref mut timeout) => timeout.poll(),
+            None => return Ok(()),
+        };
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        if !self.is_ready() {
-            // Advance the initialization of the service and HTTP/2.0 connection
-            try_ready!(self.try_ready());
+        match res {
+            Ok(Async::Ready(())) => {
+                debug!("handshake timed out");
+                Err(Error::HandshakeTimeout)
+            }
+            Ok(Async::NotReady) => Ok(()),
+            Err(e) => {
+                warn!("handshake timer failed; ignoring timeout: {:?}", e);
+                self.handshake_timeout = None;
+                Ok(())
+            }
+        }
+    }
+
+    /// Starts a graceful shutdown once a drain has been signaled, or once the
+    /// connection has reached its maximum age.
+    fn poll_drain(&mut self) {
+        if self.draining {
+            return;
+        }
+
+        let signaled = match self.drain {
+            Some(ref mut watch) => watch.poll_signal(),
+            None => false,
+        };
+
+        if signaled {
+            debug!("drain signaled; shutting down connection");
+            self.draining = true;
+            self.graceful_shutdown();
+            return;
         }
 
-        let (connection, service) = match self.state {
-            State::Ready { ref mut connection, ref mut service } => {
-                (connection, service)
+        let res = match self.max_age {
+            Some(ref mut max_age) => max_age.poll(),
+            None => return,
+        };
+
+        let expired = match res {
+            Ok(Async::Ready(())) => true,
+            Ok(Async::NotReady) => false,
+            Err(e) => {
+                // The `Builder` sizes its timer for the configured age, so
+                // this is not `TooLong`.
+                warn!("max age timer failed; ignoring max age: {:?}", e);
+                self.max_age = None;
+                false
             }
-            _ => unreachable!(),
         };
 
-        loop {
-            // Make sure the service is ready
-            let ready = service.poll_ready()
-                // TODO: Don't dump the error
-                .map_err(Error::Service);
+        if expired {
+            debug!("max connection age reached; shutting down connection");
+            self.max_age = None;
+            self.draining = true;
+            self.graceful_shutdown();
+        }
+    }
 
-            try_ready!(ready);
+    /// Starts a graceful shutdown once the connection has had no streams in
+    /// flight for the idle timeout.
+    fn poll_idle(&mut self) {
+        if self.draining {
+            return;
+        }
 
-            let next = connection.poll()
-                .map_err(Error::Protocol);
+        match self.state {
+            State::Ready { .. } => {}
+            _ => return,
+        }
 
-            let (request, respond) = match try_ready!(next) {
-                Some(next) => next,
-                None => return Ok(().into()),
-            };
+        if self.idle_timeout.is_none() {
+            return;
+        }
 
-            let (parts, body) = request.into_parts();
+        // Be notified when the last stream in flight completes, so that the
+        // timeout may be restarted.
+        self.in_flight.register();
 
-            // This is really unfortunate, but the `http` currently lacks the
-            // APIs to do this better :(
-            let mut request = Request::from_parts(parts, ());
-            self.modify.modify(&mut request);
+        if self.in_flight.active() > 0 {
+            self.idle = None;
+            return;
+        }
 
-            let (parts, _) = request.into_parts();
-            let request = Request::from_parts(parts, RecvBody::new(body));
+        if self.idle.is_none() {
+            self.idle = self.idle_timeout.as_ref()
+                .map(|&(ref timer, duration)| timer.sleep(duration));
+        }
 
-            // Dispatch the request to the service
-            let response = service.call(request);
+        let res = match self.idle {
+            Some(ref mut idle) => idle.poll(),
+            None => return,
+        };
 
-            // Spawn a new task to process the response future
-            if let Err(_) = self.executor.execute(Background::new(respond, response)) {
-                return Err(Error::Execute)
+        let expired = match res {
+            Ok(Async::Ready(())) => true,
+            Ok(Async::NotReady) => false,
+            Err(e) => {
+                warn!("idle timer failed; ignoring idle timeout: {:?}", e);
+                self.idle_timeout = None;
+                self.idle = None;
+                false
             }
+        };
+
+        if expired {
+            debug!("connection idle; shutting down connection");
+            self.idle = None;
+            self.idle_expired = true;
+            self.graceful_shutdown();
         }
     }
-}
-
-// ===== impl Modify =====
 
-impl<T> Modify for T
-where T: FnMut(&mut Request<()>)
-{
-    fn modify(&mut self, request: &mut Request<()>) {
-        (*self)(request);
-    }
-}
+    fn poll_goaway(&mut self) -> Poll<(), Error<S>> {
+        match self.state {
+            State::GoAway { ref mut connection, .. } => {
+                try_ready!(connection.poll_close().map_err(Error::Protocol));
+            }
+            _ => unreachable!(),
+        }
 
-impl Modify for () {
-    fn modify(&mut self, _: &mut Request<()>) {
+        match mem::replace(&mut self.state, State::Done) {
+            State::GoAway { error, .. } => Err(error),
+            _ => unreachable!(),
+        }
     }
 }
 
-// ===== impl Background =====
-
-impl<T, B> Background<T, B>
-where T: Future,
-      B: Body,
+impl<T, S, E, B, F, R> Connection<T, S, E, B, F, R>
+where T: AsyncRead + AsyncWrite,
+      S: NewService<Request = http::Request<RecvBody>, Response = Response<B>>,
+      E: Executor<Background<<S::Service as Service>::Future, B, R>>,
+      B: Body + 'static,
+      F: ModifyRequest,
+      R: ModifyResponse + Clone,
 {
-    fn new(respond: SendResponse<B::Data>, response: T) -> Self {
-        Background {
-            state: BackgroundState::Respond {
-                respond,
-                response,
-            },
+    fn poll_connection(&mut self) -> Poll<(), Error<S>> {
+        loop {
+            self.poll_drain();
+            self.poll_idle();
+
+            match self.state {
+                State::Init(..) => try_ready!(self.poll_init()),
+                State::Ready { .. } => return self.poll_main(),
+                State::GoAway { .. } => return self.poll_goaway(),
+                State::Done => return Ok(().into()),
+            }
         }
     }
-}
 
-impl<T, B> Future for Background<T, B>
-where T: Future<Item = Response<B>>,
-      B: Body,
-{
-    type Item = ();
-    type Error = ();
+    fn poll_main(&mut self) -> Poll<(), Error<S>> {
+        let error = match self.state {
+            State::Ready { ref mut connection, ref mut service } => loop {
+                // Retry spawning a `Background` task that the executor did not
+                // have the capacity for before accepting any more streams.
+                if let Some(background) = self.retry_spawn.take() {
+                    let retry = spawn(&self.executor, self.execute_error, background)
+                        .map_err(|()| Error::Execute)?;
+
+                    if retry.is_some() {
+                        self.retry_spawn = retry;
+                        task::current().notify();
+                        try_ready!(connection.poll_close().map_err(Error::Protocol));
+                        return Ok(().into());
+                    }
+                }
 
-    fn poll(&mut self) -> Poll<(), ()> {
-        use self::BackgroundState::*;
+                // Make sure the service is ready
+                let ready = match service.poll_ready() {
+                    Ok(ready) => ready.is_ready(),
+                    Err(e) => break Error::Service(e),
+                };
+
+                if !ready {
+                    match self.not_ready {
+                        NotReadyPolicy::Wait => {
+                            // Keep driving the connection without accepting
+                            // new streams until the service is ready.
+                            try_ready!(connection.poll_close().map_err(Error::Protocol));
+                            return Ok(().into());
+                        }
+                        NotReadyPolicy::Refuse => {
+                            let next = connection.poll()
+                                .map_err(Error::Protocol);
+
+                            match try_ready!(next) {
+                                Some((request, mut respond)) => {
+                                    debug!("service not ready; refusing stream");
+                                    self.stats.stream_accepted(header_list_size(&request));
+                                    respond.send_reset(Reason::REFUSED_STREAM);
+                                    self.stats.reset(Reason::REFUSED_STREAM);
+                                    continue;
+                                }
+                                None => return Ok(().into()),
+                            }
+                        }
+                    }
+                }
 
-        loop {
-            let flush = match self.state {
-                Respond { ref mut respond, ref mut response } => {
-                    use flush::Flush;
+                // If too many streams are in flight, keep driving the
+                // connection without accepting new streams until a `Background`
+                // task completes.
+                if self.in_flight.poll_acquire().is_not_ready() {
+                    try_ready!(connection.poll_close().map_err(Error::Protocol));
+                    return Ok(().into());
+                }
 
-                    let response = try_ready!(response.poll().map_err(|_| {
-                        // TODO: do something better the error?
-                        let reason = Reason::INTERNAL_ERROR;
-                        respond.send_reset(reason);
-                    }));
+                // Likewise if too many streams are in flight across all the
+                // connections sharing the `ConcurrencyLimit`.
+                let limited = self.concurrency.as_ref()
+                    .map_or(false, |limit| limit.poll_acquire().is_not_ready());
 
-                    let (parts, body) = response.into_parts();
+                if limited {
+                    try_ready!(connection.poll_close().map_err(Error::Protocol));
+                    return Ok(().into());
+                }
 
-                    // Check if the response is immediately an end-of-stream.
-                    let end_stream = body.is_end_stream();
-                    trace!("send_response eos={} {:?}", end_stream, parts);
+                // Likewise if the executor has fallen behind on spawning
+                // tasks, so that new streams wait in h2 rather than in the
+                // executor's queue.
+                let paused = self.backpressure.as_ref()
+                    .map_or(false, |&(ref backpressure, max)| {
+                        backpressure.poll_queue(max).is_not_ready()
+                    });
+
+                if paused {
+                    trace!("executor queue is full; pausing accept");
+                    try_ready!(connection.poll_close().map_err(Error::Protocol));
+                    return Ok(().into());
+                }
 
-                    // Try sending the response.
-                    let response = Response::from_parts(parts, ());
-                    match respond.send_response(response, end_stream) {
-                        Ok(stream) => {
-                            if end_stream {
-                                // Nothing more to do
-                                return Ok(().into());
-                            }
+                let next = connection.poll()
+                    .map_err(Error::Protocol);
 
-                            // Transition to flushing the body
-                            Flush::new(body, stream)
-                        }
-                        Err(_) => {
-                            // TODO: Do something with the error?
-                            return Ok(().into());
+                let (request, mut respond) = match try_ready!(next) {
+                    Some(next) => next,
+                    None => return Ok(().into()),
+                };
+
+                let header_list_size = header_list_size(&request);
+                self.stats.stream_accepted(header_list_size);
+
+                // Refuse streams opened faster than the rate limit allows,
+                // before any work is done for them.
+                let limited = self.stream_rate.as_mut()
+                    .and_then(|bucket| bucket.acquire().err());
+
+                if let Some(reason) = limited {
+                    debug!("stream rate limit exceeded; resetting stream; reason={:?}", reason);
+                    respond.send_reset(reason);
+                    self.stats.reset(reason);
+                    continue;
+                }
+
+                if let Some(ref instrument) = self.instrument {
+                    instrument.on_stream_open();
+                }
+
+                self.handle.stream_accepted();
+
+                let id = self.last_stream_id.next();
+                self.last_stream_id = id;
+                trace!("accepted stream; stream={}", id);
+
+                // Once the maximum number of requests has been accepted, stop
+                // accepting new streams and let this one and the others in
+                // flight complete.
+                if let Some(ref mut remaining) = self.remaining_requests {
+                    *remaining = remaining.saturating_sub(1);
+
+                    if *remaining == 0 && !self.draining {
+                        debug!("max requests reached; shutting down connection");
+                        self.draining = true;
+
+                        if let Some(ref instrument) = self.instrument {
+                            instrument.on_goaway();
                         }
+
+                        self.handle.goaway(Reason::NO_ERROR);
+                        connection.graceful_shutdown();
                     }
                 }
-                Flush(ref mut flush) => return flush.poll(),
-            };
 
-            self.state = Flush(flush);
-        }
-    }
-}
+                let (parts, body) = request.into_parts();
+
+                let content_length = content_length::parse(&parts.headers);
+                let meta = RequestMeta {
+                    end_stream: body.is_end_stream(),
+                    content_length,
+                };
+
+                // This is really unfortunate, but the `http` currently lacks the
+                // APIs to do this better :(
+                let mut request = Request::from_parts(parts, ());
+
+                // Answer requests whose headers are too large with a 431,
+                // rather than failing the whole connection. This is decided,
+                // and logged, on the request as the peer sent it: neither the
+                // extensions nor headers added by `modify` count against the
+                // peer's limit.
+                let too_large = self.max_header_list_size
+                    .and_then(|limit| if header_list_size > limit as usize { Some(limit) } else { None });
+
+                if let Some(limit) = too_large {
+                    trace!("header list too large; stream={} size={}", id, header_list_size);
+                    report(&self.on_background_error, BackgroundError::HeaderListSize {
+                        size: header_list_size,
+                        limit,
+                    });
+
+                    let mut log = self.access_log.as_ref()
+                        .map(|handler| log::Pending::new(handler.clone(), &request));
+
+                    let reject = Reject::Status(http::StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE);
+                    let reason = reject_stream(&mut respond, reject, &mut log, &self.stats);
+                    close(&self.instrument, &mut log, &mut None, 0, reason);
+                    continue;
+                }
 
-// ===== impl Error =====
+                if let Some(ref info) = self.info {
+                    request.extensions_mut().insert(info.clone());
+                }
 
-impl<S> Error<S>
-where S: NewService,
-{
-    fn from_init(err: Either<h2::Error, S::InitError>) -> Self {
-        match err {
-            Either::A(err) => Error::Handshake(err),
-            Either::B(err) => Error::NewService(err),
-        }
-    }
-}
+                request.extensions_mut().insert(id);
 
-impl<S> fmt::Display for Error<S>
-where
-    Error<S>: error::Error,
-    S: NewService,
-    S: fmt::Debug,
-    S::InitError: error::Error,
-    S::Error: error::Error,
+                if let Some(settings) = self.peer_settings.get() {
+                    request.extensions_mut().insert(settings);
+                }
+
+                let cancellation = Reset::new();
+                request.extensions_mut().insert(Cancellation::new(cancellation.clone()));
+
+                // Deadlines longer than the timer accepts are shortened to
+                // its maximum, so that they are still enforced.
+                let timeout = self.deadline_header.as_ref()
+                    .and_then(|&(_, ref name, max)| {
+                        deadline::parse(request.headers(), name)
+                            .map(|timeout| cmp::min(timeout, max))
+                    });
+
+                if let Some(timeout) = timeout {
+                    request.extensions_mut().insert(deadline::after(timeout));
+                }
+
+                self.modify.modify(&mut request, &meta);
+
+                let mut log = self.access_log.as_ref()
+                    .map(|handler| log::Pending::new(handler.clone(), &request));
+
+                let misdirected = self.authorities.as_ref()
+                    .map_or(false, |authorities| !authorities.allows(&request));
+
+                if misdirected {
+                    debug!("request for unknown authority; stream={} authority={:?}",
+                           id, request.uri().authority_part());
+                    let reject = Reject::Status(http::StatusCode::MISDIRECTED_REQUEST);
+                    let reason = reject_stream(&mut respond, reject, &mut log, &self.stats);
+                    close(&self.instrument, &mut log, &mut None, 0, reason);
+                    continue;
+                }
+
+                if !self.connect && request.method() == http::Method::CONNECT {
+                    debug!("CONNECT is not enabled; stream={}", id);
+                    let reject = Reject::Status(http::StatusCode::METHOD_NOT_ALLOWED);
+                    let reason = reject_stream(&mut respond, reject, &mut log, &self.stats);
+                    close(&self.instrument, &mut log, &mut None, 0, reason);
+                    continue;
+                }
+
+                // Give the `Accept` a chance to reject the request before
+                // anything else is done with it.
+                let rejected = self.accept.as_ref()
+                    .and_then(|accept| accept.accept(&request).err());
+
+                if let Some(reject) = rejected {
+                    trace!("rejecting stream; stream={}", id);
+                    let reason = reject_stream(&mut respond, reject, &mut log, &self.stats);
+                    close(&self.instrument, &mut log, &mut None, 0, reason);
+                    continue;
+                }
+
+                let priority = self.priority.as_ref().map_or(0, |priority| priority(&request));
+
+                let mut body = RecvBody::new(body);
+
+                // Allow the body to reset the stream, for example if it goes
+                // idle, or if it does not match its `content-length`.
+                let reset = Reset::new();
+                body.set_reset(reset.clone());
+
+                if let Some(ref pool) = self.buffer_pool {
+                    body.set_buffer_pool(pool.clone());
+                }
+
+                if let Some((ref timer, duration)) = self.stream_idle_timeout {
+                    body.set_idle_timeout(timer.clone(), duration, reset.clone());
+                }
+
+                if let Some(len) = content_length {
+                    body.set_content_length(len, reset.clone());
+                }
+
+                if let Some(ref watch) = self.drain {
+                    body.set_drain(watch.clone(), reset.clone());
+                }
+
+                let head = request.method() == http::Method::HEAD;
+
+                let (parts, _) = request.into_parts();
+                let request = Request::from_parts(parts, body);
+
+                // Dispatch the request to the service
+                let response = service.call(request);
+
+                // Restart the idle timeout once this stream completes.
+                self.idle = None;
+
+                // Spawn a new task to process the response future
+                let modify_response = self.modify_response.clone();
+                let mut background = Background::new(respond, response, modify_response);
+                background.in_flight = Some(self.in_flight.acquire());
+                background.drain = self.drain.as_ref().map(Watch::guard);
+                background.permit = self.concurrency.as_ref().map(|limit| limit.acquire());
+                background.completion = Some(self.completion_tx.clone());
+                background.on_error = self.on_background_error.clone();
+                background.reset = Some(reset);
+                background.instrument = self.instrument.clone();
+                background.stats = self.stats.clone();
+                background.log = log;
+                background.cancellation = Some(cancellation);
+                background.coalesce_watermark = self.coalesce_watermark;
+                background.max_queued_chunks = self.max_queued_chunks;
+                background.max_send_buffer = self.max_send_buffer;
+                background.buffer_pool = self.buffer_pool.clone();
+                background.head = head;
+                background.priority = priority;
+                background.strip_connection_headers = self.strip_connection_headers;
+                background.recover = self.recover.clone();
+                background.reset_reason = self.reset_reason.clone();
+                background.id = id;
+                background.date = self.date.clone();
+                background.server_header = self.server_header.clone();
+                background.deadline = timeout.and_then(|timeout| {
+                    self.deadline_header.as_ref().map(|&(ref timer, _, _)| timer.sleep(timeout))
+                });
+                if let Some((ref timer, duration, reject)) = self.response_timeout {
+                    background.response_timeout = Some(timer.sleep(duration));
+                    background.response_timeout_reject = reject;
+                }
+
+                self.retry_spawn = spawn(&self.executor, self.execute_error, background)
+                    .map_err(|()| Error::Execute)?;
+            },
+            _ => unreachable!(),
+        };
+
+        // The service can no longer process requests. Stop accepting new
+        // streams, but let the in-flight ones complete before returning the
+        // error, unless the error asks for the connection to be closed
+        // immediately.
+        let goaway_reason = match (&error, &self.goaway_reason) {
+            (&Error::Service(ref e), &Some(ref f)) => f(e),
+            _ => None,
+        };
+
+        let connection = match mem::replace(&mut self.state, State::Done) {
+            State::Ready { mut connection, .. } => {
+                if let Some(ref instrument) = self.instrument {
+                    instrument.on_goaway();
+                }
+
+                match goaway_reason {
+                    Some(reason) => {
+                        debug!("service failed; closing connection; reason={:?}", reason);
+                        self.handle.goaway(reason);
+                        connection.abrupt_shutdown(reason);
+                    }
+                    None => {
+                        self.handle.goaway(Reason::NO_ERROR);
+                        connection.graceful_shutdown();
+                    }
+                }
+
+                connection
+            }
+            _ => unreachable!(),
+        };
+
+        self.state = State::GoAway { connection, error };
+        self.poll_goaway()
+    }
+}
+
+impl<T, S, E, B, F, R> Future for Connection<T, S, E, B, F, R>
+where T: AsyncRead + AsyncWrite,
+      S: NewService<Request = http::Request<RecvBody>, Response = Response<B>>,
+      E: Executor<Background<<S::Service as Service>::Future, B, R>>,
+      B: Body + 'static,
+      F: ModifyRequest,
+      R: ModifyResponse + Clone,
+{
+    type Item = Closed;
+    type Error = Error<S>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        // Count the streams completed since the last poll, so that the
+        // channel's buffer does not grow for the life of the connection.
+        self.completions.poll();
+
+        match self.handle.poll_control() {
+            Control::None => {}
+            Control::Shutdown => {
+                debug!("shutdown requested by handle");
+                self.graceful_shutdown();
+            }
+            Control::Abort => {
+                debug!("abort requested by handle; closing connection");
+                self.aborted = true;
+                self.state = State::Done;
+            }
+        }
+
+        let res = if self.peer_closed {
+            Ok(Async::Ready(()))
+        } else {
+            self.poll_connection()
+        };
+
+        if let Ok(Async::NotReady) = res {
+            return Ok(Async::NotReady);
+        }
+
+        if res.is_ok() && !self.aborted && !self.idle_expired && !self.draining {
+            // h2 has no streams left once the peer has closed the connection,
+            // but the `Background` tasks of the last streams may still be
+            // finishing. Wait for them, so that the connection does not
+            // complete before their responses have been flushed.
+            self.peer_closed = true;
+            self.in_flight.register();
+
+            if self.in_flight.active() > 0 {
+                trace!("peer closed connection; waiting for {} in-flight streams",
+                       self.in_flight.active());
+                return Ok(Async::NotReady);
+            }
+        }
+
+        self.handle.closed();
+        res?;
+
+        self.completions.poll();
+        let streams = self.completions.counts();
+
+        let closed = if self.aborted {
+            Closed::Aborted {
+                in_flight_aborted: self.in_flight.active(),
+                streams,
+            }
+        } else if self.idle_expired {
+            Closed::Idle { streams }
+        } else if self.draining {
+            Closed::Drained {
+                in_flight_aborted: self.in_flight.active(),
+                streams,
+            }
+        } else {
+            Closed::PeerClosed { streams }
+        };
+
+        debug!("connection closed; {:?}", closed);
+
+        if let Some(ref instrument) = self.instrument {
+            instrument.on_connection_close(&closed);
+        }
+
+        Ok(Async::Ready(closed))
+    }
+}
+
+// ===== impl Closed =====
+
+impl Closed {
+    /// Returns the number of streams the connection completed.
+    pub fn streams(&self) -> StreamCounts {
+        match *self {
+            Closed::PeerClosed { streams } |
+            Closed::Drained { streams, .. } |
+            Closed::Idle { streams } |
+            Closed::Aborted { streams, .. } => streams,
+        }
+    }
+}
+
+// ===== impl Modify =====
+
+impl<T> Modify for T
+where T: FnMut(&mut Request<()>)
+{
+    fn modify(&mut self, request: &mut Request<()>) {
+        (*self)(request);
+    }
+}
+
+impl Modify for () {
+    fn modify(&mut self, _: &mut Request<()>) {
+    }
+}
+
+// ===== impl ModifyRequest =====
+
+impl<T: Modify> ModifyRequest for T {
+    fn modify(&mut self, request: &mut Request<()>, _meta: &RequestMeta) {
+        Modify::modify(self, request);
+    }
+}
+
+// ===== impl NewModify =====
+
+impl<F, M> NewModify for F
+where F: Fn() -> M,
+      M: ModifyRequest,
+{
+    type Modify = M;
+
+    fn new_modify(&self) -> M {
+        (*self)()
+    }
+}
+
+impl NewModify for () {
+    type Modify = ();
+
+    fn new_modify(&self) {
+    }
+}
+
+// ===== impl RequestMeta =====
+
+impl RequestMeta {
+    /// Returns `true` if the request has no body, because the peer ended the
+    /// stream with the request's headers.
+    pub fn is_end_stream(&self) -> bool {
+        self.end_stream
+    }
+
+    /// Returns the length declared by the request's `content-length` header,
+    /// if it has a valid one.
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+}
+
+// ===== impl WithMeta =====
+
+impl<F> WithMeta<F>
+where F: FnMut(&mut Request<()>, &RequestMeta),
+{
+    /// Wrap `f` so that it is called with every request and its
+    /// `RequestMeta`.
+    pub fn new(f: F) -> Self {
+        WithMeta { f }
+    }
+}
+
+impl<F> ModifyRequest for WithMeta<F>
+where F: FnMut(&mut Request<()>, &RequestMeta),
+{
+    fn modify(&mut self, request: &mut Request<()>, meta: &RequestMeta) {
+        (self.f)(request, meta);
+    }
+}
+
+// ===== impl ModifyResponse =====
+
+impl<T> ModifyResponse for T
+where T: FnMut(&mut Response<()>)
+{
+    fn modify(&mut self, response: &mut Response<()>) {
+        (*self)(response);
+    }
+}
+
+impl ModifyResponse for () {
+    fn modify(&mut self, _: &mut Response<()>) {
+    }
+}
+
+// ===== impl Background =====
+
+impl<T, B, R> Background<T, B, R>
+where T: Future,
+      B: Body,
+{
+    pub(super) fn priority(&self) -> u8 {
+        self.priority
+    }
+}
+
+impl<T, B, R> Background<T, B, R>
+where T: Future,
+      B: Body,
+{
+    fn new(respond: SendResponse<BodyBuf<B>>, response: T, modify_response: R) -> Self {
+        Background {
+            state: BackgroundState::Respond {
+                respond,
+                response,
+            },
+            modify_response,
+            in_flight: None,
+            drain: None,
+            permit: None,
+            completion: None,
+            on_error: None,
+            reset: None,
+            instrument: None,
+            stats: stats::Shared::default(),
+            log: None,
+            coalesce_watermark: 0,
+            max_queued_chunks: 0,
+            max_send_buffer: None,
+            send_buffered: 0,
+            buffer_pool: None,
+            cancellation: None,
+            head: false,
+            date: None,
+            server_header: None,
+            deadline: None,
+            response_timeout: None,
+            response_timeout_reject: Reject::Reset(Reason::CANCEL),
+            id: StreamId::default(),
+            priority: 0,
+            strip_connection_headers: false,
+            recover: None,
+            reset_reason: None,
+        }
+    }
+
+    /// Returns `true` once the request's deadline has elapsed.
+    fn poll_deadline(&mut self) -> bool {
+        let res = match self.deadline {
+            Some(ref mut deadline) => deadline.poll(),
+            None => return false,
+        };
+
+        match res {
+            Ok(Async::Ready(())) => {
+                self.deadline = None;
+                true
+            }
+            Ok(Async::NotReady) => false,
+            Err(e) => {
+                warn!("deadline timer failed; ignoring deadline: {:?}", e);
+                self.deadline = None;
+                false
+            }
+        }
+    }
+
+    /// Returns `true` once the response future has taken longer than the
+    /// response timeout.
+    fn poll_response_timeout(&mut self) -> bool {
+        let res = match self.response_timeout {
+            Some(ref mut timeout) => timeout.poll(),
+            None => return false,
+        };
+
+        match res {
+            Ok(Async::Ready(())) => {
+                self.response_timeout = None;
+                true
+            }
+            Ok(Async::NotReady) => false,
+            Err(e) => {
+                warn!("response timer failed; ignoring timeout: {:?}", e);
+                self.response_timeout = None;
+                false
+            }
+        }
+    }
+
+    /// Refuse the stream without processing it.
+    fn refuse(mut self) {
+        use self::BackgroundState::*;
+
+        let reason = Reason::REFUSED_STREAM;
+
+        match self.state {
+            Respond { ref mut respond, .. } => respond.send_reset(reason),
+            Flush(ref mut flush) => flush.send_reset(reason),
+        }
+
+        self.stats.reset(reason);
+        close(&self.instrument, &mut self.log, &mut self.completion, 0, Some(reason));
+    }
+}
+
+impl<T, B, R> Drop for Background<T, B, R>
+where T: Future,
+      B: Body,
+{
+    fn drop(&mut self) {
+        // A stream reset while its body was buffered no longer buffers it.
+        if self.send_buffered > 0 {
+            self.stats.send_buffered(self.send_buffered, 0);
+        }
+    }
+}
+
+impl<T, B, R> Future for Background<T, B, R>
+where T: Future<Item = Response<B>>,
+      B: Body,
+      R: ModifyResponse,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        use self::BackgroundState::*;
+
+        let local_reset = if self.poll_deadline() {
+            debug!("deadline elapsed; canceling stream; stream={}", self.id);
+
+            if let Some(ref cancellation) = self.cancellation {
+                cancellation.reset(Reason::CANCEL);
+            }
+
+            Some(Reason::CANCEL)
+        } else {
+            self.reset.as_ref().and_then(Reset::poll_reset)
+        };
+
+        if let Some(reason) = local_reset {
+            debug!("resetting stream; stream={} reason={:?}", self.id, reason);
+
+            let bytes_sent = match self.state {
+                Respond { ref mut respond, .. } => {
+                    respond.send_reset(reason);
+                    0
+                }
+                Flush(ref mut flush) => {
+                    flush.send_reset(reason);
+                    flush.bytes_sent()
+                }
+            };
+
+            self.stats.reset(reason);
+
+            close(&self.instrument, &mut self.log, &mut self.completion, bytes_sent, Some(reason));
+            return Ok(().into());
+        }
+
+        // If the peer reset the stream, stop processing it and notify the
+        // service's `Cancellation`.
+        let peer_reset = match self.state {
+            Respond { ref mut respond, .. } => respond.poll_reset(),
+            Flush(ref mut flush) => flush.poll_reset(),
+        };
+
+        match peer_reset {
+            Ok(Async::Ready(reason)) => {
+                debug!("stream reset by peer; stream={} reason={:?}", self.id, reason);
+                self.stats.reset_by_peer();
+
+                if let Some(ref cancellation) = self.cancellation {
+                    cancellation.reset(reason);
+                }
+
+                let bytes_sent = match self.state {
+                    Flush(ref flush) => flush.bytes_sent(),
+                    Respond { .. } => 0,
+                };
+
+                close(&self.instrument, &mut self.log, &mut self.completion, bytes_sent, Some(reason));
+                return Ok(().into());
+            }
+            Ok(Async::NotReady) => {}
+            Err(e) => trace!("error polling for stream reset; stream={}: {:?}", self.id, e),
+        }
+
+        let responding = match self.state {
+            Respond { .. } => true,
+            Flush(_) => false,
+        };
+
+        if responding && self.poll_response_timeout() {
+            debug!("response timed out; stream={}", self.id);
+
+            if let Some(ref cancellation) = self.cancellation {
+                cancellation.reset(Reason::CANCEL);
+            }
+
+            let reason = match self.state {
+                Respond { ref mut respond, .. } =>
+                    reject_stream(respond, self.response_timeout_reject, &mut self.log, &self.stats),
+                Flush(_) => unreachable!(),
+            };
+
+            close(&self.instrument, &mut self.log, &mut self.completion, 0, reason);
+            return Ok(().into());
+        }
+
+        loop {
+            let flush = match self.state {
+                Respond { ref mut respond, ref mut response } => {
+                    use flush::Flush;
+
+                    let id = self.id;
+                    let instrument = &self.instrument;
+                    let stats = &self.stats;
+                    let log = &mut self.log;
+                    let completion = &mut self.completion;
+                    let reset_reason = &self.reset_reason;
+                    let res = match response.poll() {
+                        Ok(Async::Ready(response)) => Ok(response),
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(e) => match self.recover {
+                            Some(ref recover) => {
+                                debug!("response failed; recovering; stream={}", id);
+                                recover.recover(e)
+                            }
+                            None => Err(e),
+                        },
+                    };
+
+                    let response = match res {
+                        Ok(response) => response,
+                        Err(e) => {
+                            let reason = match *reset_reason {
+                                Some(ref f) => f(&e),
+                                None => Reason::INTERNAL_ERROR,
+                            };
+                            debug!("response failed; resetting stream; stream={} reason={:?}", id, reason);
+                            respond.send_reset(reason);
+                            stats.reset(reason);
+                            close(instrument, log, completion, 0, Some(reason));
+                            return Err(());
+                        }
+                    };
+
+                    let (parts, mut body) = response.into_parts();
+                    let content_length = if self.head || !has_body(parts.status) {
+                        None
+                    } else {
+                        content_length::parse(&parts.headers)
+                    };
+
+                    // Check if the response is immediately an end-of-stream.
+                    // If it declares a non-zero length, the body is flushed
+                    // anyway so that the mismatch is detected.
+                    let end_stream = body.is_end_stream() &&
+                        content_length.map_or(true, |len| len == 0);
+                    trace!("send_response stream={} eos={} {:?}", self.id, end_stream, parts);
+
+                    // Try sending the response.
+                    let mut response = Response::from_parts(parts, ());
+                    self.modify_response.modify(&mut response);
+                    insert_headers(&mut response, &self.date, &self.server_header);
+
+                    if self.strip_connection_headers {
+                        sanitize::strip(response.headers_mut());
+                    } else if let Err(name) = sanitize::check(response.headers()) {
+                        debug!("connection-specific response header; resetting stream; stream={} header={}",
+                               id, name);
+                        let reason = Reason::INTERNAL_ERROR;
+                        respond.send_reset(reason);
+                        stats.reset(reason);
+                        close(instrument, log, completion, 0, Some(reason));
+                        report(&self.on_error, BackgroundError::ConnectionHeader(name));
+                        return Ok(().into());
+                    }
+
+                    if let Some(ref mut log) = *log {
+                        log.set_status(response.status());
+                    }
+
+                    // Poll the body once before sending the headers, so that
+                    // a body whose only chunk is already available is sent
+                    // along with them rather than being flushed.
+                    let first = if end_stream {
+                        None
+                    } else {
+                        match body.poll_data() {
+                            Ok(Async::Ready(data)) => Some(data.map(IntoBuf::into_buf)),
+                            Ok(Async::NotReady) => None,
+                            Err(e) => {
+                                let reason = failure_reason(&e);
+                                debug!("response body failed; resetting stream; stream={} reason={:?}",
+                                       id, reason);
+                                respond.send_reset(reason);
+                                stats.reset(reason);
+                                close(instrument, log, completion, 0, Some(reason));
+                                report(&self.on_error, BackgroundError::Flush(e));
+                                return Ok(().into());
+                            }
+                        }
+                    };
+
+                    let single = match first {
+                        Some(Some(ref buf)) => body.is_end_stream() &&
+                            content_length.map_or(true, |len| len == buf.remaining() as u64),
+                        _ => false,
+                    };
+
+                    stats.response_sent(&response);
+
+                    match respond.send_response(response, end_stream) {
+                        Ok(mut stream) => {
+                            if end_stream {
+                                // Nothing more to do
+                                close(instrument, log, completion, 0, None);
+                                return Ok(().into());
+                            }
+
+                            if single {
+                                let buf = first.and_then(|first| first).expect("single chunk");
+                                let len = buf.remaining();
+                                trace!("sending single chunk; stream={} len={}", id, len);
+
+                                let res = stream.send_data(SendBuf::buf(buf), true);
+
+                                let (sent, reason) = match res {
+                                    Ok(()) => (len, None),
+                                    Err(ref e) => (0, Some(failure_reason(e))),
+                                };
+
+                                if let Some(ref instrument) = *instrument {
+                                    instrument.on_body_sent(&FlushStats::single(sent));
+                                }
+
+                                close(instrument, log, completion, sent, reason);
+
+                                if let Err(e) = res {
+                                    report(&self.on_error, BackgroundError::Flush(e));
+                                }
+
+                                return Ok(().into());
+                            }
+
+                            // Transition to flushing the body
+                            let mut flush = Flush::new(body, stream);
+                            flush.set_coalesce_watermark(self.coalesce_watermark);
+                            flush.set_max_queued_chunks(self.max_queued_chunks);
+                            if let Some(max) = self.max_send_buffer {
+                                flush.set_max_buffered(max);
+                            }
+                            if let Some(ref pool) = self.buffer_pool {
+                                flush.set_buffer_pool(pool.clone());
+                            }
+                            flush.set_stream_id(self.id.as_u64());
+                            if let Some(len) = content_length {
+                                flush.set_content_length(len);
+                            }
+                            if let Some(first) = first {
+                                flush.set_first(first);
+                            }
+                            flush
+                        }
+                        Err(e) => {
+                            close(instrument, log, completion, 0, Some(failure_reason(&e)));
+                            report(&self.on_error, BackgroundError::SendResponse(e));
+                            return Ok(().into());
+                        }
+                    }
+                }
+                Flush(ref mut flush) => {
+                    let res = flush.poll();
+
+                    let buffered = match res {
+                        Ok(Async::NotReady) => flush.buffered(),
+                        _ => 0,
+                    };
+                    self.stats.send_buffered(self.send_buffered, buffered);
+                    self.send_buffered = buffered;
+
+                    let bytes_sent = flush.bytes_sent();
+                    let log = &mut self.log;
+                    let completion = &mut self.completion;
+
+                    if let Some(ref instrument) = self.instrument {
+                        match res {
+                            Ok(Async::NotReady) => {}
+                            _ => instrument.on_body_sent(&flush.stats()),
+                        }
+                    }
+
+                    match res {
+                        Ok(Async::Ready(())) => {
+                            close(&self.instrument, log, completion, bytes_sent, None);
+                        }
+                        Ok(Async::NotReady) => {}
+                        Err(ref e) => {
+                            // The body's length was wrong, so the stream was
+                            // reset by the server rather than failing.
+                            if let FlushError::ContentLength { .. } = *e {
+                                self.stats.reset(e.reason());
+                            }
+
+                            let reason = Some(e.reason());
+                            close(&self.instrument, log, completion, bytes_sent, reason);
+                        }
+                    }
+
+                    let on_error = &self.on_error;
+                    return res.map_err(|e| {
+                        let error = match e {
+                            FlushError::H2(e) => BackgroundError::Flush(e),
+                            FlushError::ContentLength { declared, sent } =>
+                                BackgroundError::ContentLength { declared, sent },
+                        };
+                        report(on_error, error);
+                    });
+                }
+            };
+
+            self.response_timeout = None;
+            self.state = Flush(flush);
+        }
+    }
+}
+
+/// Report an error encountered by a `Background` task.
+fn report(on_error: &Option<ErrorHandler>, error: BackgroundError) {
+    debug!("background task failed: {}", error);
+
+    if let Some(ref on_error) = *on_error {
+        on_error(error);
+    }
+}
+
+/// Notify the `Instrument` and access log, if any, that a `Background` task's
+/// stream is closed.
+fn close(
+    instrument: &Option<instrument::Shared>,
+    log: &mut Option<log::Pending>,
+    completion: &mut Option<completion::Sender>,
+    bytes_sent: usize,
+    reason: Option<Reason>,
+) {
+    if let Some(ref instrument) = *instrument {
+        instrument.on_stream_close(reason);
+    }
+
+    if let Some(completion) = completion.take() {
+        completion.complete(reason);
+    }
+
+    if let Some(log) = log.take() {
+        log.finish(bytes_sent, reason);
+    }
+}
+
+/// Spawn a `Background` task onto `executor`, handling failures according to
+/// `policy`.
+///
+/// Returns the task if spawning it should be retried, or an error if the
+/// connection should fail.
+fn spawn<E, T, B, R>(
+    executor: &E,
+    policy: ExecuteErrorPolicy,
+    background: Background<T, B, R>,
+) -> Result<Option<Background<T, B, R>>, ()>
+where E: Executor<Background<T, B, R>>,
+      T: Future,
+      B: Body,
+{
+    let err = match executor.execute(background) {
+        Ok(()) => return Ok(None),
+        Err(err) => err,
+    };
+
+    let at_capacity = match err.kind() {
+        ExecuteErrorKind::NoCapacity => true,
+        _ => false,
+    };
+
+    match policy {
+        ExecuteErrorPolicy::Fail => Err(()),
+        ExecuteErrorPolicy::Retry if at_capacity => {
+            debug!("executor at capacity; retrying spawn");
+            Ok(Some(err.into_future()))
+        }
+        ExecuteErrorPolicy::Refuse | ExecuteErrorPolicy::Retry => {
+            debug!("failed to spawn background task; refusing stream");
+            err.into_future().refuse();
+            Ok(None)
+        }
+    }
+}
+
+/// Reject a stream refused by an `Accept`, returning the reason the stream
+/// was reset with, if any.
+fn reject_stream<B>(
+    respond: &mut SendResponse<B>,
+    reject: Reject,
+    log: &mut Option<log::Pending>,
+    stats: &stats::Shared,
+) -> Option<Reason>
+where B: ::bytes::IntoBuf,
+{
+    match reject {
+        Reject::Status(status) => {
+            debug!("request rejected; status={}", status);
+
+            if let Some(ref mut log) = *log {
+                log.set_status(status);
+            }
+
+            let mut response = Response::new(());
+            *response.status_mut() = status;
+            stats.response_sent(&response);
+
+            match respond.send_response(response, true) {
+                Ok(_) => None,
+                Err(e) => Some(failure_reason(&e)),
+            }
+        }
+        Reject::Reset(reason) => {
+            debug!("request rejected; resetting stream; reason={:?}", reason);
+            respond.send_reset(reason);
+            stats.reset(reason);
+            Some(reason)
+        }
+    }
+}
+
+/// Returns the size of `request`'s header list, as defined by
+/// `SETTINGS_MAX_HEADER_LIST_SIZE`, including its pseudo-headers.
+fn header_list_size<B>(request: &Request<B>) -> usize {
+    // h2 does not report the size of the header block it decoded, so the size
+    // is computed as RFC 7540, section 6.5.2 defines it: each field is
+    // counted as its name and value plus 32 octets of overhead.
+    const OVERHEAD: usize = 32;
+
+    let uri = request.uri();
+
+    let mut size = ":method".len() + request.method().as_str().len() + OVERHEAD;
+    size += ":path".len() + uri.path_and_query().map_or(1, |p| p.as_str().len()) + OVERHEAD;
+
+    if let Some(scheme) = uri.scheme_part() {
+        size += ":scheme".len() + scheme.as_str().len() + OVERHEAD;
+    }
+
+    if let Some(authority) = uri.authority_part() {
+        size += ":authority".len() + authority.as_str().len() + OVERHEAD;
+    }
+
+    for (name, value) in request.headers() {
+        size += name.as_str().len() + value.len() + OVERHEAD;
+    }
+
+    size
+}
+
+/// Inserts the `date` and `server` headers into `response`, unless the
+/// service already set them.
+fn insert_headers(
+    response: &mut Response<()>,
+    date: &Option<Date>,
+    server: &Option<HeaderValue>,
+) {
+    use http::header::{DATE, SERVER};
+
+    let headers = response.headers_mut();
+
+    if let Some(ref date) = *date {
+        if !headers.contains_key(DATE) {
+            headers.insert(DATE, date.value());
+        }
+    }
+
+    if let Some(ref server) = *server {
+        if !headers.contains_key(SERVER) {
+            headers.insert(SERVER, server.clone());
+        }
+    }
+}
+
+/// Returns `false` if responses with `status` never have a body, so that their
+/// `content-length` does not describe the body.
+fn has_body(status: http::StatusCode) -> bool {
+    use http::StatusCode;
+
+    !status.is_informational() &&
+        status != StatusCode::NO_CONTENT &&
+        status != StatusCode::NOT_MODIFIED
+}
+
+/// Returns the reason a stream failed with `err`.
+fn failure_reason(err: &h2::Error) -> Reason {
+    err.reason().unwrap_or(Reason::INTERNAL_ERROR)
+}
+
+// ===== impl Error =====
+
+impl<S> Error<S>
+where S: NewService,
+{
+    fn from_init(err: Either<h2::Error, S::InitError>) -> Self {
+        match err {
+            Either::A(err) => Error::Handshake(err),
+            Either::B(err) => Error::NewService(err),
+        }
+    }
+}
+
+impl<S> Error<S>
+where S: NewService,
+{
+    /// Returns `true` if the HTTP/2.0 handshake failed or timed out.
+    pub fn is_handshake(&self) -> bool {
+        match *self {
+            Error::Handshake(_) | Error::HandshakeTimeout => true,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the HTTP/2.0 connection failed after the handshake.
+    pub fn is_protocol(&self) -> bool {
+        match *self {
+            Error::Protocol(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the service could not be obtained, or failed to
+    /// become ready.
+    pub fn is_service(&self) -> bool {
+        match *self {
+            Error::NewService(_) | Error::Service(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the h2 error that caused the connection to fail, if any.
+    pub fn as_h2(&self) -> Option<&h2::Error> {
+        match *self {
+            Error::Handshake(ref why) | Error::Protocol(ref why) => Some(why),
+            _ => None,
+        }
+    }
+}
+
+/// An `h2::Error` of an established connection is a `Protocol` error.
+impl<S> From<h2::Error> for Error<S>
+where S: NewService,
+{
+    fn from(src: h2::Error) -> Self {
+        Error::Protocol(src)
+    }
+}
+
+impl<S> fmt::Debug for Error<S>
+where
+    S: NewService,
+    S::InitError: fmt::Debug,
+    S::Error: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Handshake(ref why) =>
+                f.debug_tuple("Handshake").field(why).finish(),
+            Error::Protocol(ref why) =>
+                f.debug_tuple("Protocol").field(why).finish(),
+            Error::NewService(ref why) =>
+                f.debug_tuple("NewService").field(why).finish(),
+            Error::Service(ref why) =>
+                f.debug_tuple("Service").field(why).finish(),
+            Error::Execute =>
+                f.debug_tuple("Execute").finish(),
+            Error::HandshakeTimeout =>
+                f.debug_tuple("HandshakeTimeout").finish(),
+        }
+    }
+}
+
+impl<S> fmt::Display for Error<S>
+where
+    S: NewService,
+    S::InitError: fmt::Display,
+    S::Error: fmt::Display,
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::Handshake(ref why) =>
                 write!(f, "Error occurred during HTTP/2.0 handshake: {}", why),
             Error::Protocol(ref why) =>
-                write!(f, "Error produced by HTTP/2.0 stream: {}", why),
+                write!(f, "Error produced by HTTP/2.0 connection: {}", why),
             Error::NewService(ref why) =>
                 write!(f, "Error occurred while obtaining service: {}", why),
             Error::Service(ref why) =>
                 write!(f, "Error returned by service: {}", why),
             Error::Execute =>
                 write!(f, "Error occurred while attempting to spawn a task"),
+            Error::HandshakeTimeout =>
+                write!(f, "Timed out waiting for the HTTP/2.0 handshake"),
         }
     }
 }
@@ -384,7 +2228,6 @@ where
 impl<S> error::Error for Error<S>
 where
     S: NewService,
-    S: fmt::Debug,
     S::InitError: error::Error,
     S::Error: error::Error,
 {
@@ -395,17 +2238,161 @@ where
             Error::NewService(ref why) => Some(why),
             Error::Service(ref why) => Some(why),
             Error::Execute => None,
+            Error::HandshakeTimeout => None,
         }
     }
 
     fn description(&self) -> &str {
         match *self {
             Error::Handshake(_) =>  "error occurred during HTTP/2.0 handshake",
-            Error::Protocol(_) => "error produced by HTTP/2.0 stream",
+            Error::Protocol(_) => "error produced by HTTP/2.0 connection",
             Error::NewService(_) => "error occured while obtaining service",
             Error::Service(_) => "error returned by service",
             Error::Execute => "error occurred while attempting to spawn a task",
+            Error::HandshakeTimeout => "timed out waiting for the HTTP/2.0 handshake",
+        }
+    }
+
+}
+
+// ===== impl BackgroundError =====
+
+impl fmt::Display for BackgroundError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BackgroundError::SendResponse(ref why) =>
+                write!(f, "Error sending response: {}", why),
+            BackgroundError::Flush(ref why) =>
+                write!(f, "Error sending response body: {}", why),
+            BackgroundError::HeaderListSize { size, limit } =>
+                write!(f, "Error receiving request: header list of {} octets exceeds the limit of {}",
+                       size, limit),
+            BackgroundError::ConnectionHeader(ref name) =>
+                write!(f, "Error sending response: connection-specific header `{}` is not allowed in HTTP/2.0",
+                       name),
+            BackgroundError::ContentLength { declared, sent } =>
+                write!(f, "Error sending response body: content-length is {} but {} bytes were sent",
+                       declared, sent),
+        }
+    }
+}
+
+impl error::Error for BackgroundError {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            BackgroundError::SendResponse(ref why) => Some(why),
+            BackgroundError::Flush(ref why) => Some(why),
+            BackgroundError::HeaderListSize { .. } => None,
+            BackgroundError::ConnectionHeader(_) => None,
+            BackgroundError::ContentLength { .. } => None,
         }
     }
 
-}
\ No newline at end of file
+    fn description(&self) -> &str {
+        match *self {
+            BackgroundError::SendResponse(_) => "error sending response",
+            BackgroundError::Flush(_) => "error sending response body",
+            BackgroundError::HeaderListSize { .. } => "request header list too large",
+            BackgroundError::ConnectionHeader(_) => "connection-specific response header",
+            BackgroundError::ContentLength { .. } => "response body does not match content-length",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{header_list_size, Builder, Server};
+    use RecvBody;
+    use client;
+    use mock;
+
+    use futures::{future, Async, Future, Poll};
+    use h2;
+    use http::{HeaderValue, Request, Response, StatusCode};
+    use tokio_core::reactor::Core;
+    use tower::{NewService, Service};
+
+    use std::io;
+
+    struct Empty;
+
+    impl Service for Empty {
+        type Request = Request<RecvBody>;
+        type Response = Response<&'static [u8]>;
+        type Error = h2::Error;
+        type Future = future::FutureResult<Self::Response, h2::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), h2::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Self::Request) -> Self::Future {
+            future::ok(Response::new(&b""[..]))
+        }
+    }
+
+    impl NewService for Empty {
+        type Request = Request<RecvBody>;
+        type Response = Response<&'static [u8]>;
+        type Error = h2::Error;
+        type InitError = io::Error;
+        type Service = Empty;
+        type Future = future::FutureResult<Empty, io::Error>;
+
+        fn new_service(&self) -> Self::Future {
+            future::ok(Empty)
+        }
+    }
+
+    #[test]
+    fn header_list_size_counts_pseudo_headers_and_fields() {
+        let request = Request::builder()
+            .uri("http://test.example/")
+            .header("x-a", "bc")
+            .body(())
+            .unwrap();
+
+        // :method GET, :path /, :scheme http, :authority test.example, x-a bc
+        let expected = (7 + 3 + 32) + (5 + 1 + 32) + (7 + 4 + 32) + (10 + 12 + 32) + (3 + 2 + 32);
+        assert_eq!(header_list_size(&request), expected);
+    }
+
+    #[test]
+    fn headers_added_by_modify_do_not_count_against_the_limit() {
+        let mut core = Core::new().unwrap();
+
+        let mut builder = Builder::new();
+        builder.max_header_list_size(1024);
+        let server = Server::new(Empty, builder, core.handle());
+
+        let (client_io, server_io) = mock::duplex();
+
+        let large = HeaderValue::from_str(&"a".repeat(2048)).unwrap();
+        let modify = move |request: &mut Request<()>| {
+            request.headers_mut().insert("x-added", large.clone());
+        };
+
+        let connection = server.serve_modified(server_io, modify)
+            .map(|_| ())
+            .map_err(|e| panic!("server failed: {:?}", e));
+        core.handle().spawn(connection);
+
+        let request = Request::builder()
+            .uri("http://test.example/")
+            .body(())
+            .unwrap();
+
+        let status = client::Connection::handshake(client_io, core.handle())
+            .map_err(|e| format!("handshake failed: {:?}", e))
+            .and_then(move |mut client: client::Connection<_, _, ()>| {
+                client.call(request)
+                    .map_err(|e| format!("request failed: {:?}", e))
+                    .map(move |response| {
+                        drop(client);
+                        response.status()
+                    })
+            });
+
+        assert_eq!(core.run(status).unwrap(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,228 @@
+//! Serving a connection with custom dispatching.
+//!
+//! `Builder::serve_parts` splits a connection into a `Driver`, which performs
+//! the connection's I/O, and an `Incoming` stream of the requests it receives.
+//! Frameworks may process those requests however they like, for example with
+//! their own executor, queues, or priorities, instead of dispatching them to a
+//! `NewService`.
+//!
+//! Only the HTTP/2.0 settings of the `Builder` apply to connections served
+//! this way.
+
+use {Body, RecvBody};
+use body::BodyBuf;
+use super::{Background, Builder, Never};
+
+use futures::{Async, AsyncSink, Future, Poll, Sink, Stream};
+use futures::sync::mpsc;
+use h2::{self, Reason};
+use h2::server::{Connection, Handshake, SendResponse};
+use http::{Request, Response};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use std::fmt;
+
+/// Performs the I/O of a connection served by `Builder::serve_parts`.
+///
+/// The driver must be polled for the connection to make progress, including
+/// for the streams yielded by `Incoming`. It completes once the connection
+/// has closed.
+pub struct Driver<T, B>
+where B: Body,
+{
+    state: State<T, B>,
+    tx: Option<mpsc::Sender<(Request<RecvBody>, Respond<B>)>>,
+}
+
+/// The requests received on a connection served by `Builder::serve_parts`.
+///
+/// The stream ends once the connection stops accepting streams. Dropping it
+/// starts a graceful shutdown of the connection.
+pub struct Incoming<B>
+where B: Body,
+{
+    rx: mpsc::Receiver<(Request<RecvBody>, Respond<B>)>,
+}
+
+/// Sends the response to a request yielded by `Incoming`.
+pub struct Respond<B>
+where B: Body,
+{
+    inner: SendResponse<BodyBuf<B>>,
+}
+
+enum State<T, B>
+where B: Body,
+{
+    Handshake(Handshake<T, BodyBuf<B>>),
+    Ready(Connection<T, BodyBuf<B>>),
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Split a connection into a `Driver` that performs its I/O and a stream
+    /// of the requests it receives.
+    ///
+    /// Up to `buffer` received requests are queued for `Incoming`. Once the
+    /// queue is full, the connection stops accepting new streams until
+    /// `Incoming` is polled.
+    pub fn serve_parts<T, B>(&self, io: T, buffer: usize) -> (Driver<T, B>, Incoming<B>)
+    where T: AsyncRead + AsyncWrite,
+          B: Body,
+    {
+        let (tx, rx) = mpsc::channel(buffer);
+
+        let driver = Driver {
+            state: State::Handshake(self.h2().handshake(io)),
+            tx: Some(tx),
+        };
+
+        (driver, Incoming { rx })
+    }
+}
+
+// ===== impl Driver =====
+
+impl<T, B> Driver<T, B>
+where T: AsyncRead + AsyncWrite,
+      B: Body,
+{
+    /// Accepts streams and queues them for `Incoming` for as long as there is
+    /// room in the queue.
+    fn poll_connection(
+        connection: &mut Connection<T, BodyBuf<B>>,
+        tx: &mut Option<mpsc::Sender<(Request<RecvBody>, Respond<B>)>>,
+    ) -> Poll<(), h2::Error> {
+        loop {
+            let ready = match *tx {
+                Some(ref mut tx) => tx.poll_ready().ok().map(|ready| ready.is_ready()),
+                None => return connection.poll_close(),
+            };
+
+            match ready {
+                Some(true) => {}
+                Some(false) => return connection.poll_close(),
+                None => {
+                    debug!("incoming dropped; shutting down connection");
+                    *tx = None;
+                    connection.graceful_shutdown();
+                    continue;
+                }
+            }
+
+            let (request, respond) = match try_ready!(connection.poll()) {
+                Some(next) => next,
+                None => {
+                    *tx = None;
+                    return Ok(Async::Ready(()));
+                }
+            };
+
+            let (parts, body) = request.into_parts();
+            let request = Request::from_parts(parts, RecvBody::new(body));
+            let respond = Respond { inner: respond };
+
+            if let Some(ref mut tx) = *tx {
+                match tx.start_send((request, respond)) {
+                    Ok(AsyncSink::Ready) => {}
+                    Ok(AsyncSink::NotReady((_, respond))) => {
+                        respond.send_reset(Reason::REFUSED_STREAM);
+                    }
+                    Err(err) => {
+                        let (_, respond) = err.into_inner();
+                        respond.send_reset(Reason::REFUSED_STREAM);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T, B> Future for Driver<T, B>
+where T: AsyncRead + AsyncWrite,
+      B: Body,
+{
+    type Item = ();
+    type Error = h2::Error;
+
+    fn poll(&mut self) -> Poll<(), h2::Error> {
+        loop {
+            let connection = match self.state {
+                State::Handshake(ref mut handshake) => try_ready!(handshake.poll()),
+                State::Ready(ref mut connection) => {
+                    return Self::poll_connection(connection, &mut self.tx);
+                }
+            };
+
+            self.state = State::Ready(connection);
+        }
+    }
+}
+
+impl<T, B> fmt::Debug for Driver<T, B>
+where B: Body,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Handshake(..) => "Handshake",
+            State::Ready(..) => "Ready",
+        };
+
+        f.debug_struct("Driver")
+            .field("state", &state)
+            .finish()
+    }
+}
+
+// ===== impl Incoming =====
+
+impl<B> Stream for Incoming<B>
+where B: Body,
+{
+    type Item = (Request<RecvBody>, Respond<B>);
+    type Error = Never;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Never> {
+        // The receiver never fails.
+        Ok(self.rx.poll().unwrap_or(Async::Ready(None)))
+    }
+}
+
+impl<B> fmt::Debug for Incoming<B>
+where B: Body,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Incoming").finish()
+    }
+}
+
+// ===== impl Respond =====
+
+impl<B> Respond<B>
+where B: Body,
+{
+    /// Returns a task that sends the response produced by `response` and
+    /// flushes its body.
+    ///
+    /// If `response` fails, the stream is reset with `INTERNAL_ERROR`. The
+    /// task must be spawned or polled for the response to be sent.
+    pub fn respond<F>(self, response: F) -> Background<F, B>
+    where F: Future<Item = Response<B>>,
+    {
+        Background::new(self.inner, response, ())
+    }
+
+    /// Reset the stream without sending a response.
+    pub fn send_reset(mut self, reason: Reason) {
+        self.inner.send_reset(reason);
+    }
+}
+
+impl<B> fmt::Debug for Respond<B>
+where B: Body,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Respond").finish()
+    }
+}
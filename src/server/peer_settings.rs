@@ -0,0 +1,241 @@
+use futures::Poll;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+
+/// The HTTP/2.0 settings the peer sent with its connection preface.
+///
+/// Once they have been received, a copy of the `PeerSettings` is inserted
+/// into the extensions of every request received on the connection.
+///
+/// h2 does not expose the settings it receives, so they are read from the
+/// first SETTINGS frame as it passes through the transport. Settings the peer
+/// changes later in the connection are not reflected. Settings the peer did
+/// not send are `None`, in which case their HTTP/2.0 defaults apply.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PeerSettings {
+    header_table_size: Option<u32>,
+    enable_push: Option<bool>,
+    max_concurrent_streams: Option<u32>,
+    initial_window_size: Option<u32>,
+    max_frame_size: Option<u32>,
+    max_header_list_size: Option<u32>,
+}
+
+/// Shares the `PeerSettings` read by an `Io` with its connection.
+#[derive(Clone, Default)]
+pub(super) struct Shared {
+    settings: Arc<Mutex<Option<PeerSettings>>>,
+}
+
+/// Reads the peer's settings from the start of a server's transport.
+pub(super) struct Io<T> {
+    inner: T,
+    sniff: Option<Sniff>,
+}
+
+/// The bytes of the connection preface and the first frame read so far.
+struct Sniff {
+    buf: Vec<u8>,
+    shared: Shared,
+}
+
+/// The connection preface sent by clients, which precedes their SETTINGS.
+const PREFACE_LEN: usize = 24;
+
+const FRAME_HEADER_LEN: usize = 9;
+
+const SETTINGS_FRAME_TYPE: u8 = 0x4;
+
+/// SETTINGS frames larger than this are not read.
+const MAX_SETTINGS_LEN: usize = 1024;
+
+// ===== impl PeerSettings =====
+
+impl PeerSettings {
+    /// Parses the payload of a SETTINGS frame.
+    fn parse(payload: &[u8]) -> Self {
+        let mut settings = PeerSettings::default();
+
+        for setting in payload.chunks(6) {
+            if setting.len() < 6 {
+                break;
+            }
+
+            let id = (setting[0] as u16) << 8 | setting[1] as u16;
+            let value = (setting[2] as u32) << 24 |
+                (setting[3] as u32) << 16 |
+                (setting[4] as u32) << 8 |
+                setting[5] as u32;
+
+            match id {
+                0x1 => settings.header_table_size = Some(value),
+                0x2 => settings.enable_push = Some(value != 0),
+                0x3 => settings.max_concurrent_streams = Some(value),
+                0x4 => settings.initial_window_size = Some(value),
+                0x5 => settings.max_frame_size = Some(value),
+                0x6 => settings.max_header_list_size = Some(value),
+                _ => {}
+            }
+        }
+
+        settings
+    }
+
+    /// Returns `SETTINGS_HEADER_TABLE_SIZE`, if the peer sent it.
+    pub fn header_table_size(&self) -> Option<u32> {
+        self.header_table_size
+    }
+
+    /// Returns `SETTINGS_ENABLE_PUSH`, if the peer sent it.
+    pub fn enable_push(&self) -> Option<bool> {
+        self.enable_push
+    }
+
+    /// Returns `SETTINGS_MAX_CONCURRENT_STREAMS`, if the peer sent it.
+    pub fn max_concurrent_streams(&self) -> Option<u32> {
+        self.max_concurrent_streams
+    }
+
+    /// Returns `SETTINGS_INITIAL_WINDOW_SIZE`, if the peer sent it.
+    pub fn initial_window_size(&self) -> Option<u32> {
+        self.initial_window_size
+    }
+
+    /// Returns `SETTINGS_MAX_FRAME_SIZE`, if the peer sent it.
+    pub fn max_frame_size(&self) -> Option<u32> {
+        self.max_frame_size
+    }
+
+    /// Returns `SETTINGS_MAX_HEADER_LIST_SIZE`, if the peer sent it.
+    pub fn max_header_list_size(&self) -> Option<u32> {
+        self.max_header_list_size
+    }
+}
+
+// ===== impl Shared =====
+
+impl Shared {
+    /// Returns the peer's settings, if they have been received.
+    pub fn get(&self) -> Option<PeerSettings> {
+        *self.settings.lock().unwrap()
+    }
+
+    fn set(&self, settings: PeerSettings) {
+        *self.settings.lock().unwrap() = Some(settings);
+    }
+}
+
+impl fmt::Debug for Shared {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.get(), f)
+    }
+}
+
+// ===== impl Io =====
+
+impl<T> Io<T> {
+    pub fn new(inner: T, shared: Shared) -> Self {
+        let sniff = Sniff {
+            buf: Vec::with_capacity(PREFACE_LEN + FRAME_HEADER_LEN),
+            shared,
+        };
+
+        Io {
+            inner,
+            sniff: Some(sniff),
+        }
+    }
+}
+
+impl<T: Read> Read for Io<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+
+        let done = match self.sniff {
+            Some(ref mut sniff) => sniff.read(&buf[..n]),
+            None => false,
+        };
+
+        if done {
+            self.sniff = None;
+        }
+
+        Ok(n)
+    }
+}
+
+impl<T: Write> Write for Io<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for Io<T> {
+    unsafe fn prepare_uninitialized_buffer(&self, buf: &mut [u8]) -> bool {
+        self.inner.prepare_uninitialized_buffer(buf)
+    }
+}
+
+impl<T: AsyncWrite> AsyncWrite for Io<T> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Io<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Io")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+// ===== impl Sniff =====
+
+impl Sniff {
+    /// Buffers `bytes` read from the transport.
+    ///
+    /// Returns `true` once no more bytes need to be read, either because the
+    /// settings were parsed or because the connection does not start with a
+    /// SETTINGS frame.
+    fn read(&mut self, bytes: &[u8]) -> bool {
+        if bytes.is_empty() {
+            return true;
+        }
+
+        self.buf.extend_from_slice(bytes);
+
+        let header = PREFACE_LEN + FRAME_HEADER_LEN;
+
+        if self.buf.len() < header {
+            return false;
+        }
+
+        let len = (self.buf[PREFACE_LEN] as usize) << 16 |
+            (self.buf[PREFACE_LEN + 1] as usize) << 8 |
+            self.buf[PREFACE_LEN + 2] as usize;
+        let kind = self.buf[PREFACE_LEN + 3];
+
+        if kind != SETTINGS_FRAME_TYPE || len > MAX_SETTINGS_LEN {
+            debug!("connection does not start with a SETTINGS frame; kind={}", kind);
+            return true;
+        }
+
+        if self.buf.len() < header + len {
+            return false;
+        }
+
+        let settings = PeerSettings::parse(&self.buf[header..header + len]);
+        trace!("received peer settings; {:?}", settings);
+        self.shared.set(settings);
+
+        true
+    }
+}
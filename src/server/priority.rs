@@ -0,0 +1,293 @@
+use Body;
+use super::{Background, Backpressure};
+use super::executor::{self, BoxBackground};
+
+use futures::{Async, Future, Poll};
+use futures::future::{Executor, ExecuteError};
+use futures::task::{self, Task};
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Spawns `Background` tasks in order of priority, running at most a fixed
+/// number of them at once.
+///
+/// Tasks are queued until one of the `workers` slots is free, and the queued
+/// task with the highest priority is spawned onto the inner executor first.
+/// Tasks of the same priority are spawned in the order they were queued. This
+/// keeps a flood of low-priority streams from starving important ones on a
+/// shared executor.
+///
+/// The priority of each stream is assigned by the function configured with
+/// `Builder::stream_priority`; h2 does not expose the priority and weight
+/// sent by the peer. Streams are otherwise given priority 0.
+///
+/// A task is spawned immediately when a slot is free and no other task is
+/// queued, and `execute` returns the error of the inner executor if it fails.
+/// A queued task that the inner executor later fails to spawn is dropped,
+/// resetting its stream.
+///
+/// The depth of the queue is reported to connections configured with
+/// `Builder::max_executor_queue`, so that they stop accepting streams while
+/// it is too deep.
+#[derive(Clone)]
+pub struct PriorityExecutor {
+    shared: Arc<Shared>,
+}
+
+/// A task spawned onto the inner executor while it holds a slot.
+struct Worker<F = BoxBackground> {
+    task: F,
+    slot: Slot,
+}
+
+/// Releases a slot when the task holding it completes or is dropped, and
+/// spawns the next queued task.
+struct Slot {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    inner: Box<Executor<BoxBackground> + Send + Sync>,
+    workers: usize,
+    queue: Mutex<Queue>,
+}
+
+struct Queue {
+    tasks: BinaryHeap<Queued>,
+    running: usize,
+    seq: u64,
+
+    /// Whether a call to `spawn_next` is spawning tasks. Slots released
+    /// meanwhile are picked up by that call instead of spawning again from
+    /// `Slot::drop`.
+    spawning: bool,
+
+    /// The connection tasks waiting for the queue to drain.
+    waiters: Vec<Task>,
+}
+
+struct Queued {
+    priority: u8,
+    seq: u64,
+    task: BoxBackground,
+}
+
+// ===== impl PriorityExecutor =====
+
+impl PriorityExecutor {
+    /// Create a new `PriorityExecutor` that runs up to `workers` tasks at
+    /// once on `inner`.
+    pub fn new<E>(inner: E, workers: usize) -> Self
+    where E: Executor<BoxBackground> + Send + Sync + 'static,
+    {
+        PriorityExecutor {
+            shared: Arc::new(Shared {
+                inner: Box::new(inner),
+                workers: workers.max(1),
+                queue: Mutex::new(Queue {
+                    tasks: BinaryHeap::new(),
+                    running: 0,
+                    seq: 0,
+                    spawning: false,
+                    waiters: Vec::new(),
+                }),
+            }),
+        }
+    }
+
+    /// Returns the number of tasks waiting for a slot.
+    pub fn queued(&self) -> usize {
+        self.shared.queue.lock().unwrap().tasks.len()
+    }
+}
+
+impl<T, B, R> Executor<Background<T, B, R>> for PriorityExecutor
+where Background<T, B, R>: Future<Item = (), Error = ()> + Send + 'static,
+      T: Future,
+      B: Body,
+{
+    fn execute(
+        &self,
+        background: Background<T, B, R>,
+    ) -> Result<(), ExecuteError<Background<T, B, R>>> {
+        let priority = background.priority();
+
+        {
+            let mut queue = self.shared.queue.lock().unwrap();
+
+            if queue.tasks.is_empty() && queue.running < self.shared.workers {
+                queue.running += 1;
+            } else {
+                let seq = queue.seq;
+                queue.seq += 1;
+                queue.tasks.push(Queued {
+                    priority,
+                    seq,
+                    task: Box::new(background),
+                });
+
+                drop(queue);
+                Shared::spawn_next(&self.shared);
+                return Ok(());
+            }
+        }
+
+        let worker = Worker {
+            task: background,
+            slot: Slot { shared: self.shared.clone() },
+        };
+
+        executor::execute_boxed(&*self.shared.inner, worker).map_err(|err| {
+            let kind = err.kind();
+            let Worker { task, slot } = err.into_future();
+
+            // Release the slot before handing the task back.
+            drop(slot);
+
+            ExecuteError::new(kind, task)
+        })
+    }
+}
+
+impl Backpressure for PriorityExecutor {
+    fn poll_queue(&self, max: usize) -> Async<()> {
+        let mut queue = self.shared.queue.lock().unwrap();
+
+        if queue.tasks.len() <= max {
+            return Async::Ready(());
+        }
+
+        let registered = queue.waiters.iter().any(|waiter| waiter.will_notify_current());
+
+        if !registered {
+            queue.waiters.push(task::current());
+        }
+
+        Async::NotReady
+    }
+}
+
+impl fmt::Debug for PriorityExecutor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let queue = self.shared.queue.lock().unwrap();
+
+        f.debug_struct("PriorityExecutor")
+            .field("workers", &self.shared.workers)
+            .field("running", &queue.running)
+            .field("queued", &queue.tasks.len())
+            .finish()
+    }
+}
+
+// ===== impl Shared =====
+
+impl Shared {
+    /// Spawns queued tasks onto the inner executor while slots are free.
+    ///
+    /// Only one call spawns at a time. Others return immediately, leaving
+    /// the spawning call to pick up the slots they would have filled, so that
+    /// a task dropped by a failing executor does not recurse into this
+    /// function through `Slot::drop`.
+    fn spawn_next(shared: &Arc<Self>) {
+        {
+            let mut queue = shared.queue.lock().unwrap();
+
+            if queue.spawning {
+                return;
+            }
+
+            queue.spawning = true;
+        }
+
+        loop {
+            let queued = {
+                let mut queue = shared.queue.lock().unwrap();
+
+                if queue.running >= shared.workers {
+                    queue.spawning = false;
+                    return;
+                }
+
+                match queue.tasks.pop() {
+                    Some(queued) => {
+                        queue.running += 1;
+
+                        // The queue shrank, so let paused connections check
+                        // it again.
+                        for waiter in queue.waiters.drain(..) {
+                            waiter.notify();
+                        }
+
+                        queued
+                    }
+                    None => {
+                        queue.spawning = false;
+                        return;
+                    }
+                }
+            };
+
+            trace!("spawning queued task; priority={}", queued.priority);
+
+            let worker = Worker {
+                task: queued.task,
+                slot: Slot { shared: shared.clone() },
+            };
+
+            // If the task cannot be spawned, dropping it releases its slot
+            // and resets its stream.
+            if let Err(e) = shared.inner.execute(Box::new(worker)) {
+                warn!("failed to spawn queued task: {:?}", e.kind());
+            }
+        }
+    }
+}
+
+// ===== impl Worker =====
+
+impl<F> Future for Worker<F>
+where F: Future<Item = (), Error = ()>,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        self.task.poll()
+    }
+}
+
+// ===== impl Slot =====
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().running -= 1;
+        Shared::spawn_next(&self.shared);
+    }
+}
+
+// ===== impl Queued =====
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Higher priorities first, then the earliest queued.
+        self.priority.cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
@@ -0,0 +1,476 @@
+use {Body, RecvBody};
+use rewind::Rewind;
+use super::{Background, Closed, Connection, ConnectionInfo, Error, Server};
+
+use bytes::BytesMut;
+use futures::{Future, Poll};
+use futures::future::Executor;
+use http::{Request, Response};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tower::{NewService, Service};
+
+use std::{error, fmt, io, mem, str};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// The start of a version 1 header.
+const V1_PREFIX: &[u8] = b"PROXY ";
+
+/// The maximum length of a version 1 header, including its CRLF.
+const V1_MAX_LEN: usize = 107;
+
+/// The signature that starts a version 2 header.
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The length of a version 2 header before its addresses.
+const V2_HEADER_LEN: usize = 16;
+
+/// Reads a PROXY protocol header from the transport, and then drives the
+/// HTTP/2.0 connection.
+///
+/// Created by `Server::serve_proxied`.
+pub struct ServeProxied<T, S, E, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService,
+      B: Body,
+{
+    state: State<T, S, E, B>,
+}
+
+/// Error produced by a `ServeProxied` future.
+pub enum ProxyError<S>
+where S: NewService,
+{
+    /// Reading the PROXY protocol header failed.
+    Io(io::Error),
+
+    /// The transport did not start with a valid PROXY protocol header.
+    InvalidHeader,
+
+    /// Error produced by the HTTP/2.0 connection.
+    Connection(Error<S>),
+}
+
+enum State<T, S, E, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService,
+      B: Body,
+{
+    /// Reading the PROXY protocol header.
+    Header {
+        io: T,
+        buf: BytesMut,
+        server: Server<S, E, B>,
+        info: ConnectionInfo,
+    },
+
+    /// Serving the HTTP/2.0 connection.
+    Serve(Connection<Rewind<T>, S, E, B, ()>),
+
+    /// Transient state used while transitioning out of `Header`.
+    Empty,
+}
+
+/// A parsed PROXY protocol header.
+struct Header {
+    /// The length of the header, in bytes.
+    len: usize,
+
+    /// The source and destination addresses of the proxied connection, or
+    /// `None` if the proxy did not relay them, as for its own health checks.
+    addrs: Option<(SocketAddr, SocketAddr)>,
+}
+
+// ===== impl Server =====
+
+impl<S, E, B> Server<S, E, B>
+where S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      B: Body,
+      E: Clone,
+{
+    /// Serve a connection accepted from a proxy that sends a PROXY protocol
+    /// header, such as HAProxy or a TCP load balancer.
+    ///
+    /// Both version 1 (text) and version 2 (binary) headers are accepted. The
+    /// source address relayed by the proxy is recorded in `info` as the
+    /// remote address, and its destination address as the local address,
+    /// before `info` is passed to `Server::serve_with_info`.
+    ///
+    /// The header is required: connections that do not start with one fail
+    /// with `ProxyError::InvalidHeader`. Only use this for connections that
+    /// can only come from a trusted proxy, as the header is not authenticated.
+    pub fn serve_proxied<T>(&self, io: T, info: ConnectionInfo) -> ServeProxied<T, S, E, B>
+    where T: AsyncRead + AsyncWrite,
+          S: Clone,
+    {
+        ServeProxied {
+            state: State::Header {
+                io,
+                buf: BytesMut::with_capacity(V1_MAX_LEN),
+                server: self.clone(),
+                info,
+            },
+        }
+    }
+}
+
+// ===== impl ServeProxied =====
+
+impl<T, S, E, B> Future for ServeProxied<T, S, E, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      E: Executor<Background<<S::Service as Service>::Future, B>> + Clone,
+      B: Body + 'static,
+{
+    type Item = Closed;
+    type Error = ProxyError<S>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let header = match self.state {
+                State::Header { ref mut io, ref mut buf, .. } => {
+                    let parsed = parse(&buf[..]).map_err(|()| {
+                        debug!("invalid PROXY protocol header");
+                        ProxyError::InvalidHeader
+                    })?;
+
+                    match parsed {
+                        Some(header) => header,
+                        None => {
+                            buf.reserve(V1_MAX_LEN);
+                            let n = try_ready!(io.read_buf(buf).map_err(ProxyError::Io));
+
+                            if n == 0 {
+                                debug!("connection closed before its PROXY protocol header");
+                                return Err(ProxyError::Io(io::ErrorKind::UnexpectedEof.into()));
+                            }
+
+                            continue;
+                        }
+                    }
+                }
+                State::Serve(ref mut connection) => {
+                    return connection.poll().map_err(ProxyError::Connection);
+                }
+                State::Empty => unreachable!(),
+            };
+
+            let connection = match mem::replace(&mut self.state, State::Empty) {
+                State::Header { io, mut buf, server, mut info } => {
+                    trace!("read PROXY protocol header; addrs={:?}", header.addrs);
+
+                    if let Some((source, destination)) = header.addrs {
+                        info.set_remote_addr(source);
+                        info.set_local_addr(destination);
+                    }
+
+                    // Bytes read past the header belong to the HTTP/2.0
+                    // connection.
+                    buf.split_to(header.len);
+                    server.serve_with_info(Rewind::new(io, buf.freeze()), info)
+                }
+                _ => unreachable!(),
+            };
+
+            self.state = State::Serve(connection);
+        }
+    }
+}
+
+// ===== impl ProxyError =====
+
+impl<S> fmt::Debug for ProxyError<S>
+where
+    Error<S>: fmt::Debug,
+    S: NewService,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProxyError::Io(ref why) =>
+                f.debug_tuple("Io").field(why).finish(),
+            ProxyError::InvalidHeader =>
+                f.debug_tuple("InvalidHeader").finish(),
+            ProxyError::Connection(ref why) =>
+                f.debug_tuple("Connection").field(why).finish(),
+        }
+    }
+}
+
+impl<S> fmt::Display for ProxyError<S>
+where
+    Error<S>: fmt::Display,
+    S: NewService,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProxyError::Io(ref why) =>
+                write!(f, "Error occurred while reading PROXY protocol header: {}", why),
+            ProxyError::InvalidHeader =>
+                write!(f, "Connection did not start with a valid PROXY protocol header"),
+            ProxyError::Connection(ref why) =>
+                write!(f, "{}", why),
+        }
+    }
+}
+
+impl<S> error::Error for ProxyError<S>
+where
+    Error<S>: error::Error,
+    S: NewService,
+{
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            ProxyError::Io(ref why) => Some(why),
+            ProxyError::InvalidHeader => None,
+            ProxyError::Connection(ref why) => Some(why),
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            ProxyError::Io(_) => "error occurred while reading PROXY protocol header",
+            ProxyError::InvalidHeader => "invalid PROXY protocol header",
+            ProxyError::Connection(_) => "error produced by HTTP/2.0 connection",
+        }
+    }
+}
+
+// ===== parsing =====
+
+/// Parses the PROXY protocol header at the start of `buf`.
+///
+/// Returns `None` if more bytes are needed to parse it.
+fn parse(buf: &[u8]) -> Result<Option<Header>, ()> {
+    if starts(buf, V1_PREFIX) {
+        parse_v1(buf)
+    } else if starts(buf, V2_SIGNATURE) {
+        parse_v2(buf)
+    } else {
+        Err(())
+    }
+}
+
+/// Returns `true` if `buf` and `prefix` agree on their common length, so that
+/// `buf` may still start with `prefix` once more bytes are read.
+fn starts(buf: &[u8], prefix: &[u8]) -> bool {
+    if buf.len() < prefix.len() {
+        prefix.starts_with(buf)
+    } else {
+        buf.starts_with(prefix)
+    }
+}
+
+/// Parses a version 1 header, such as
+/// `PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n`.
+fn parse_v1(buf: &[u8]) -> Result<Option<Header>, ()> {
+    let end = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(end) => end,
+        None if buf.len() >= V1_MAX_LEN => return Err(()),
+        None => return Ok(None),
+    };
+
+    let line = str::from_utf8(&buf[..end]).map_err(|_| ())?;
+    let mut fields = line.split(' ').skip(1);
+    let len = end + 2;
+
+    match fields.next() {
+        Some("TCP4") | Some("TCP6") => {}
+        // The proxy could not determine the addresses; the rest of the line
+        // is ignored.
+        Some("UNKNOWN") => return Ok(Some(Header { len, addrs: None })),
+        _ => return Err(()),
+    }
+
+    let source: IpAddr = field(fields.next())?;
+    let destination: IpAddr = field(fields.next())?;
+    let source_port: u16 = field(fields.next())?;
+    let destination_port: u16 = field(fields.next())?;
+
+    if fields.next().is_some() {
+        return Err(());
+    }
+
+    let addrs = (
+        SocketAddr::new(source, source_port),
+        SocketAddr::new(destination, destination_port),
+    );
+
+    Ok(Some(Header { len, addrs: Some(addrs) }))
+}
+
+fn field<T: str::FromStr>(field: Option<&str>) -> Result<T, ()> {
+    field.ok_or(())?.parse().map_err(|_| ())
+}
+
+/// Parses a version 2 header.
+fn parse_v2(buf: &[u8]) -> Result<Option<Header>, ()> {
+    if buf.len() < V2_HEADER_LEN {
+        return Ok(None);
+    }
+
+    let version = buf[12] >> 4;
+    let command = buf[12] & 0x0f;
+    let family = buf[13] >> 4;
+    let addrs_len = (buf[14] as usize) << 8 | buf[15] as usize;
+    let len = V2_HEADER_LEN + addrs_len;
+
+    if version != 2 || command > 1 {
+        return Err(());
+    }
+
+    if buf.len() < len {
+        return Ok(None);
+    }
+
+    // A `LOCAL` command is sent by the proxy on its own behalf, as for health
+    // checks, and relays no addresses.
+    if command == 0 {
+        return Ok(Some(Header { len, addrs: None }));
+    }
+
+    let addrs = &buf[V2_HEADER_LEN..len];
+
+    let addrs = match family {
+        // AF_INET
+        1 if addrs.len() >= 12 => {
+            let source = Ipv4Addr::new(addrs[0], addrs[1], addrs[2], addrs[3]);
+            let destination = Ipv4Addr::new(addrs[4], addrs[5], addrs[6], addrs[7]);
+            Some((
+                SocketAddr::new(source.into(), port(&addrs[8..10])),
+                SocketAddr::new(destination.into(), port(&addrs[10..12])),
+            ))
+        }
+        // AF_INET6
+        2 if addrs.len() >= 36 => {
+            let source = ipv6(&addrs[0..16]);
+            let destination = ipv6(&addrs[16..32]);
+            Some((
+                SocketAddr::new(source.into(), port(&addrs[32..34])),
+                SocketAddr::new(destination.into(), port(&addrs[34..36])),
+            ))
+        }
+        1 | 2 => return Err(()),
+        // AF_UNSPEC and AF_UNIX have no socket address to relay.
+        _ => None,
+    };
+
+    Ok(Some(Header { len, addrs }))
+}
+
+fn port(buf: &[u8]) -> u16 {
+    (buf[0] as u16) << 8 | buf[1] as u16
+}
+
+fn ipv6(buf: &[u8]) -> Ipv6Addr {
+    let mut octets = [0; 16];
+    octets.copy_from_slice(buf);
+    Ipv6Addr::from(octets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, V2_SIGNATURE};
+
+    use std::net::SocketAddr;
+
+    fn addrs(source: &str, destination: &str) -> Option<(SocketAddr, SocketAddr)> {
+        Some((source.parse().unwrap(), destination.parse().unwrap()))
+    }
+
+    #[test]
+    fn parse_v1_tcp4() {
+        let line = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\n";
+        let mut buf = line.to_vec();
+        buf.extend_from_slice(b"PRI * HTTP/2.0");
+
+        let header = parse(&buf).unwrap().unwrap();
+        assert_eq!(header.len, line.len());
+        assert_eq!(header.addrs, addrs("192.0.2.1:56324", "198.51.100.1:443"));
+    }
+
+    #[test]
+    fn parse_v1_tcp6() {
+        let line = b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 443\r\n";
+
+        let header = parse(line).unwrap().unwrap();
+        assert_eq!(header.len, line.len());
+        assert_eq!(header.addrs, addrs("[2001:db8::1]:56324", "[2001:db8::2]:443"));
+    }
+
+    #[test]
+    fn parse_v1_unknown() {
+        let line = b"PROXY UNKNOWN ignored\r\n";
+
+        let header = parse(line).unwrap().unwrap();
+        assert_eq!(header.len, line.len());
+        assert_eq!(header.addrs, None);
+    }
+
+    #[test]
+    fn parse_v1_partial() {
+        assert!(parse(b"PRO").unwrap().is_none());
+        assert!(parse(b"PROXY TCP4 192.0.2.1").unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_v1_invalid() {
+        assert!(parse(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324\r\n").is_err());
+        assert!(parse(b"PROXY TCP4 nope 198.51.100.1 56324 443\r\n").is_err());
+        assert!(parse(b"PROXY UDP4 192.0.2.1 198.51.100.1 56324 443\r\n").is_err());
+
+        let mut long = b"PROXY ".to_vec();
+        long.extend_from_slice(&[b'a'; 200]);
+        assert!(parse(&long).is_err());
+    }
+
+    #[test]
+    fn parse_v2_tcp4() {
+        let mut buf = V2_SIGNATURE.to_vec();
+        // Version 2, PROXY command, AF_INET over STREAM, 12 bytes of
+        // addresses.
+        buf.extend_from_slice(&[0x21, 0x11, 0, 12]);
+        buf.extend_from_slice(&[192, 0, 2, 1, 198, 51, 100, 1, 0xdc, 0x04, 0x01, 0xbb]);
+        let len = buf.len();
+        buf.extend_from_slice(b"PRI * HTTP/2.0");
+
+        let header = parse(&buf).unwrap().unwrap();
+        assert_eq!(header.len, len);
+        assert_eq!(header.addrs, addrs("192.0.2.1:56324", "198.51.100.1:443"));
+    }
+
+    #[test]
+    fn parse_v2_local() {
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend_from_slice(&[0x20, 0x00, 0, 0]);
+
+        let header = parse(&buf).unwrap().unwrap();
+        assert_eq!(header.len, buf.len());
+        assert_eq!(header.addrs, None);
+    }
+
+    #[test]
+    fn parse_v2_partial() {
+        assert!(parse(&V2_SIGNATURE[..5]).unwrap().is_none());
+
+        let mut buf = V2_SIGNATURE.to_vec();
+        buf.extend_from_slice(&[0x21, 0x11, 0, 12, 192, 0, 2]);
+        assert!(parse(&buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_v2_invalid() {
+        let mut buf = V2_SIGNATURE.to_vec();
+        // Version 3.
+        buf.extend_from_slice(&[0x31, 0x11, 0, 0]);
+        assert!(parse(&buf).is_err());
+
+        let mut buf = V2_SIGNATURE.to_vec();
+        // AF_INET with too few bytes of addresses.
+        buf.extend_from_slice(&[0x21, 0x11, 0, 4, 192, 0, 2, 1]);
+        assert!(parse(&buf).is_err());
+    }
+
+    #[test]
+    fn parse_not_proxied() {
+        assert!(parse(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n").is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use h2::Reason;
+
+use std::time::Instant;
+
+/// Limits the rate at which a connection accepts new streams.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct RateLimit {
+    per_second: u32,
+    burst: u32,
+    reason: Reason,
+}
+
+/// The token bucket of a single connection.
+///
+/// Each accepted stream takes a token. Tokens are added at `per_second`,
+/// up to `burst`, so short bursts are allowed while the sustained rate is
+/// capped.
+#[derive(Debug)]
+pub(super) struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    refilled: Instant,
+}
+
+// ===== impl RateLimit =====
+
+impl RateLimit {
+    pub fn new(per_second: u32, burst: u32) -> Self {
+        RateLimit {
+            per_second,
+            // A bucket that cannot hold a single token would refuse every
+            // stream.
+            burst: burst.max(1),
+            reason: Reason::REFUSED_STREAM,
+        }
+    }
+
+    pub fn reason(&self) -> Reason {
+        self.reason
+    }
+
+    pub fn set_reason(&mut self, reason: Reason) {
+        self.reason = reason;
+    }
+
+    /// Returns a full bucket for a new connection.
+    pub fn bucket(&self) -> TokenBucket {
+        self.bucket_at(Instant::now())
+    }
+
+    fn bucket_at(&self, now: Instant) -> TokenBucket {
+        TokenBucket {
+            limit: *self,
+            tokens: self.burst as f64,
+            refilled: now,
+        }
+    }
+}
+
+// ===== impl TokenBucket =====
+
+impl TokenBucket {
+    /// Takes a token for a new stream.
+    ///
+    /// If the bucket is empty, the reason to reset the stream with is
+    /// returned instead.
+    pub fn acquire(&mut self) -> Result<(), Reason> {
+        self.acquire_at(Instant::now())
+    }
+
+    fn acquire_at(&mut self, now: Instant) -> Result<(), Reason> {
+        let elapsed = now.duration_since(self.refilled);
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+
+        self.refilled = now;
+        self.tokens = (self.tokens + elapsed * self.limit.per_second as f64)
+            .min(self.limit.burst as f64);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(self.limit.reason)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimit;
+
+    use h2::Reason;
+
+    use std::time::{Duration, Instant};
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn burst_is_exhausted() {
+        let start = Instant::now();
+        let mut bucket = RateLimit::new(10, 3).bucket_at(start);
+
+        assert_eq!(bucket.acquire_at(start), Ok(()));
+        assert_eq!(bucket.acquire_at(start), Ok(()));
+        assert_eq!(bucket.acquire_at(start), Ok(()));
+        assert_eq!(bucket.acquire_at(start), Err(Reason::REFUSED_STREAM));
+    }
+
+    #[test]
+    fn tokens_are_refilled_at_the_rate() {
+        let start = Instant::now();
+        let mut bucket = RateLimit::new(4, 1).bucket_at(start);
+
+        assert_eq!(bucket.acquire_at(start), Ok(()));
+
+        // Half a token has been refilled.
+        assert!(bucket.acquire_at(start + ms(125)).is_err());
+
+        // The half token is kept, and another half completes it.
+        assert_eq!(bucket.acquire_at(start + ms(250)), Ok(()));
+        assert!(bucket.acquire_at(start + ms(250)).is_err());
+    }
+
+    #[test]
+    fn tokens_are_capped_at_the_burst() {
+        let start = Instant::now();
+        let mut bucket = RateLimit::new(10, 2).bucket_at(start);
+
+        assert_eq!(bucket.acquire_at(start), Ok(()));
+        assert_eq!(bucket.acquire_at(start), Ok(()));
+
+        // Far more than `burst` tokens' worth of time has elapsed.
+        let later = start + Duration::from_secs(60);
+        assert_eq!(bucket.acquire_at(later), Ok(()));
+        assert_eq!(bucket.acquire_at(later), Ok(()));
+        assert!(bucket.acquire_at(later).is_err());
+    }
+
+    #[test]
+    fn empty_burst_allows_one_stream() {
+        let start = Instant::now();
+        let mut bucket = RateLimit::new(10, 0).bucket_at(start);
+
+        assert_eq!(bucket.acquire_at(start), Ok(()));
+        assert!(bucket.acquire_at(start).is_err());
+    }
+
+    #[test]
+    fn refused_streams_use_the_configured_reason() {
+        let start = Instant::now();
+        let mut limit = RateLimit::new(1, 1);
+        limit.set_reason(Reason::ENHANCE_YOUR_CALM);
+        let mut bucket = limit.bucket_at(start);
+
+        assert_eq!(bucket.acquire_at(start), Ok(()));
+        assert_eq!(bucket.acquire_at(start), Err(Reason::ENHANCE_YOUR_CALM));
+    }
+}
@@ -0,0 +1,47 @@
+use {Body, RecvBody};
+use super::{Server, SharedService};
+
+use futures::{Async, Poll};
+use http::{Request, Response};
+use tower::{NewService, Service};
+
+/// A `NewService` that may be unable to create services for a while, for
+/// example because it limits the number of services that exist at once.
+///
+/// `tower::NewService` cannot report this, so a factory with a capacity limit
+/// implements this trait as well, which allows `Server::poll_ready` to apply
+/// backpressure before a connection is accepted.
+pub trait ReadyNewService: NewService {
+    /// Returns `Ready` when `new_service` may be called.
+    ///
+    /// If `NotReady` is returned, the current task is notified once the
+    /// factory has capacity again.
+    fn poll_ready(&mut self) -> Poll<(), Self::InitError>;
+}
+
+// ===== impl Server =====
+
+impl<S, E, B> Server<S, E, B>
+where S: ReadyNewService<Request = Request<RecvBody>, Response = Response<B>>,
+      B: Body,
+{
+    /// Returns `Ready` when the server's `NewService` is able to create a
+    /// service for another connection.
+    ///
+    /// Accept loops should wait for this before accepting another connection,
+    /// so that connections are left in the listener's backlog rather than
+    /// accepted only to fail while obtaining their service.
+    pub fn poll_ready(&mut self) -> Poll<(), S::InitError> {
+        self.new_service.poll_ready()
+    }
+}
+
+// ===== impl SharedService =====
+
+impl<S> ReadyNewService for SharedService<S>
+where S: Service + Clone,
+{
+    fn poll_ready(&mut self) -> Poll<(), Self::InitError> {
+        Ok(Async::Ready(()))
+    }
+}
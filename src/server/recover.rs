@@ -0,0 +1,29 @@
+use http::Response;
+
+use std::sync::Arc;
+
+/// Converts the error of a failed response future into a response.
+///
+/// By default, a stream whose response future fails is reset, with the
+/// reason chosen by `Server::reset_reason`, which most clients report as an
+/// opaque protocol error. A `Recover` configured with `Server::recover` can instead
+/// answer the request with a real response, such as a
+/// `500 Internal Server Error` with a body describing the failure.
+pub trait Recover<E, B>: Send + Sync + 'static {
+    /// Returns the response to send in place of `error`, or hands the error
+    /// back to reset the stream as usual.
+    fn recover(&self, error: E) -> Result<Response<B>, E>;
+}
+
+/// A `Recover` shared by a server's connections.
+pub(super) type Shared<E, B> = Arc<Recover<E, B>>;
+
+// ===== impl Recover =====
+
+impl<E, B, F> Recover<E, B> for F
+where F: Fn(E) -> Result<Response<B>, E> + Send + Sync + 'static,
+{
+    fn recover(&self, error: E) -> Result<Response<B>, E> {
+        (*self)(error)
+    }
+}
@@ -0,0 +1,151 @@
+//! Connection-specific response headers, which HTTP/2.0 forbids.
+//!
+//! Pseudo-headers need no validation here: `http::HeaderName` cannot
+//! represent them, so a service cannot set them.
+
+use http::HeaderMap;
+use http::header::{self, HeaderName};
+
+/// Headers that only apply to an HTTP/1.1 connection.
+const CONNECTION_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Removes the connection-specific headers from `headers`, including those
+/// named by its `connection` header.
+pub(super) fn strip(headers: &mut HeaderMap) {
+    let named: Vec<HeaderName> = headers.get_all(header::CONNECTION).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| name.trim().parse().ok())
+        .collect();
+
+    for name in named {
+        headers.remove(name);
+    }
+
+    for name in CONNECTION_HEADERS {
+        headers.remove(*name);
+    }
+
+    if !is_valid_te(headers) {
+        headers.remove(header::TE);
+    }
+}
+
+/// Returns the name of a connection-specific header in `headers`, if it has
+/// one.
+pub(super) fn check(headers: &HeaderMap) -> Result<(), HeaderName> {
+    for name in CONNECTION_HEADERS {
+        if headers.contains_key(*name) {
+            return Err(HeaderName::from_static(*name));
+        }
+    }
+
+    if !is_valid_te(headers) {
+        return Err(header::TE);
+    }
+
+    Ok(())
+}
+
+/// `te` is only allowed with the value `trailers`.
+fn is_valid_te(headers: &HeaderMap) -> bool {
+    headers.get_all(header::TE).iter().all(|value| value == "trailers")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, strip};
+
+    use http::HeaderMap;
+    use http::header::{self, HeaderValue};
+
+    fn headers(fields: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for &(name, value) in fields {
+            headers.append(name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn strips_connection_specific_headers() {
+        let mut h = headers(&[
+            ("connection", "close"),
+            ("keep-alive", "timeout=5"),
+            ("proxy-connection", "keep-alive"),
+            ("transfer-encoding", "chunked"),
+            ("upgrade", "websocket"),
+            ("content-type", "text/plain"),
+        ]);
+
+        assert_eq!(check(&h), Err(header::CONNECTION));
+
+        strip(&mut h);
+        assert_eq!(h.len(), 1);
+        assert!(h.contains_key(header::CONTENT_TYPE));
+        assert_eq!(check(&h), Ok(()));
+    }
+
+    #[test]
+    fn strips_headers_named_by_connection() {
+        let mut h = headers(&[
+            ("connection", "x-hop, X-Other"),
+            ("connection", " x-third "),
+            ("x-hop", "1"),
+            ("x-other", "2"),
+            ("x-third", "3"),
+            ("x-end-to-end", "4"),
+        ]);
+
+        strip(&mut h);
+        assert_eq!(h.len(), 1);
+        assert!(h.contains_key("x-end-to-end"));
+    }
+
+    #[test]
+    fn ignores_invalid_connection_tokens() {
+        let mut h = headers(&[
+            ("connection", "x-hop, not a header, "),
+            ("x-hop", "1"),
+            ("x-kept", "2"),
+        ]);
+
+        strip(&mut h);
+        assert_eq!(h.len(), 1);
+        assert!(h.contains_key("x-kept"));
+    }
+
+    #[test]
+    fn te_trailers_is_allowed() {
+        let mut h = headers(&[("te", "trailers")]);
+
+        assert_eq!(check(&h), Ok(()));
+
+        strip(&mut h);
+        assert_eq!(h.get(header::TE).unwrap(), "trailers");
+    }
+
+    #[test]
+    fn te_values_other_than_trailers_are_removed() {
+        for value in &["gzip", "trailers, deflate", "Trailers", ""] {
+            let mut h = headers(&[("te", *value)]);
+
+            assert_eq!(check(&h), Err(header::TE), "te: {:?}", value);
+
+            strip(&mut h);
+            assert!(!h.contains_key(header::TE), "te: {:?}", value);
+        }
+
+        let mut h = headers(&[("te", "trailers"), ("te", "gzip")]);
+        assert_eq!(check(&h), Err(header::TE));
+
+        strip(&mut h);
+        assert!(!h.contains_key(header::TE));
+    }
+}
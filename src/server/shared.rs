@@ -0,0 +1,73 @@
+use {Body, RecvBody};
+use super::{Builder, Server};
+
+use futures::future::{self, FutureResult};
+use http::{Request, Response};
+use tower::{NewService, Service};
+
+use std::{error, fmt};
+
+/// A `NewService` that hands every connection a clone of the same service.
+///
+/// Created by `Server::with_shared_service`.
+#[derive(Clone, Debug)]
+pub struct SharedService<S> {
+    service: S,
+}
+
+/// The error type of `SharedService`, which never fails.
+#[derive(Debug)]
+pub enum Never {}
+
+// ===== impl Server =====
+
+impl<S, E, B> Server<SharedService<S>, E, B>
+where S: Service<Request = Request<RecvBody>, Response = Response<B>> + Clone,
+      B: Body,
+{
+    /// Create a new `Server` that serves every connection with a clone of
+    /// `service`, configured by `builder`.
+    ///
+    /// Obtaining the service for a connection completes immediately.
+    pub fn with_shared_service(service: S, builder: Builder, executor: E) -> Self {
+        Server::new(SharedService::new(service), builder, executor)
+    }
+}
+
+// ===== impl SharedService =====
+
+impl<S> SharedService<S> {
+    /// Create a new `SharedService` that clones `service` for each connection.
+    pub fn new(service: S) -> Self {
+        SharedService { service }
+    }
+}
+
+impl<S> NewService for SharedService<S>
+where S: Service + Clone,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Service = S;
+    type InitError = Never;
+    type Future = FutureResult<S, Never>;
+
+    fn new_service(&self) -> Self::Future {
+        future::ok(self.service.clone())
+    }
+}
+
+// ===== impl Never =====
+
+impl fmt::Display for Never {
+    fn fmt(&self, _: &mut fmt::Formatter) -> fmt::Result {
+        match *self {}
+    }
+}
+
+impl error::Error for Never {
+    fn description(&self) -> &str {
+        match *self {}
+    }
+}
@@ -0,0 +1,302 @@
+use super::Stats;
+use super::in_flight::InFlight;
+use super::stats;
+
+use futures::task::{self, Task};
+use h2::Reason;
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// A cloneable handle that samples the state of a `Connection` and controls
+/// it.
+///
+/// The handle may be used from any thread, and outlives the connection. This
+/// lets a supervisor shut a connection down without owning its future, which
+/// is usually spawned.
+///
+/// h2 does not expose the settings sent by the peer or the current flow
+/// control windows, so only the settings advertised by the server are
+/// reported. There is no `ping` method either: h2 has no API to send PING
+/// frames or observe their ACKs.
+///
+/// Likewise, h2 does not expose the GOAWAY frames it receives. Only GOAWAYs
+/// sent by the server are reported; a peer closing the connection is seen
+/// only once the connection completes, as described on `Closed::PeerClosed`.
+#[derive(Clone)]
+pub struct ConnectionHandle {
+    inner: Arc<Mutex<Inner>>,
+    in_flight: InFlight,
+    stats: stats::Shared,
+}
+
+/// A snapshot of the state of a `Connection`.
+#[derive(Clone, Debug)]
+pub struct ConnectionState {
+    phase: Phase,
+    settings: Settings,
+    active_streams: usize,
+    streams_accepted: u64,
+    last_goaway: Option<Reason>,
+}
+
+/// The HTTP/2.0 settings a server advertises to its peers.
+///
+/// Settings that were not configured are `None`, in which case h2's defaults
+/// are advertised.
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct Settings {
+    pub initial_window_size: Option<u32>,
+    pub initial_connection_window_size: Option<u32>,
+    pub max_concurrent_streams: Option<u32>,
+    pub max_frame_size: Option<u32>,
+    pub max_header_list_size: Option<u32>,
+}
+
+/// A request made through a `ConnectionHandle`, taken by the connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Control {
+    None,
+    Shutdown,
+    Abort,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Phase {
+    Handshaking,
+    Open,
+    Draining,
+    Closed,
+}
+
+#[derive(Debug)]
+struct Inner {
+    phase: Phase,
+    settings: Settings,
+    streams_accepted: u64,
+    last_goaway: Option<Reason>,
+
+    /// Set by `ConnectionHandle::graceful_shutdown` and `abort` until the
+    /// connection takes the request.
+    control: Control,
+
+    /// The connection's task, notified of requests.
+    task: Option<Task>,
+}
+
+// ===== impl ConnectionHandle =====
+
+impl ConnectionHandle {
+    pub(super) fn new(settings: Settings, in_flight: InFlight, stats: stats::Shared) -> Self {
+        ConnectionHandle {
+            inner: Arc::new(Mutex::new(Inner {
+                phase: Phase::Handshaking,
+                settings,
+                streams_accepted: 0,
+                last_goaway: None,
+                control: Control::None,
+                task: None,
+            })),
+            in_flight,
+            stats,
+        }
+    }
+
+    /// Start an HTTP/2.0 graceful shutdown of the connection.
+    ///
+    /// This has the same effect as `Connection::graceful_shutdown`, once the
+    /// connection is next polled.
+    pub fn graceful_shutdown(&self) {
+        self.request(Control::Shutdown);
+    }
+
+    /// Close the connection immediately, without waiting for its streams.
+    ///
+    /// The `Connection` completes with `Closed::Aborted` once it is next
+    /// polled. Its transport is dropped and the `Background` tasks of its
+    /// streams fail.
+    pub fn abort(&self) {
+        self.request(Control::Abort);
+    }
+
+    /// Returns the counters of the connection's activity so far.
+    pub fn stats(&self) -> Stats {
+        self.stats.get()
+    }
+
+    fn request(&self, control: Control) {
+        let mut inner = self.inner.lock().unwrap();
+
+        // An abort is not downgraded by a later shutdown request.
+        if inner.control != Control::Abort {
+            inner.control = control;
+        }
+
+        if let Some(task) = inner.task.take() {
+            task.notify();
+        }
+    }
+
+    /// Takes the request made through the handle, if any, or registers the
+    /// current task to be notified of one.
+    pub(super) fn poll_control(&self) -> Control {
+        let mut inner = self.inner.lock().unwrap();
+
+        let control = inner.control;
+        inner.control = Control::None;
+
+        let registered = inner.task.as_ref()
+            .map_or(false, |task| task.will_notify_current());
+
+        if !registered {
+            inner.task = Some(task::current());
+        }
+
+        control
+    }
+
+    /// Returns a snapshot of the connection's current state.
+    pub fn state(&self) -> ConnectionState {
+        let inner = self.inner.lock().unwrap();
+
+        ConnectionState {
+            phase: inner.phase,
+            settings: inner.settings,
+            active_streams: self.in_flight.active(),
+            streams_accepted: inner.streams_accepted,
+            last_goaway: inner.last_goaway,
+        }
+    }
+
+    pub(super) fn opened(&self) {
+        self.inner.lock().unwrap().phase = Phase::Open;
+    }
+
+    pub(super) fn stream_accepted(&self) {
+        self.inner.lock().unwrap().streams_accepted += 1;
+    }
+
+    pub(super) fn goaway(&self, reason: Reason) {
+        let mut inner = self.inner.lock().unwrap();
+
+        if inner.phase != Phase::Closed {
+            inner.phase = Phase::Draining;
+        }
+
+        inner.last_goaway = Some(reason);
+    }
+
+    pub(super) fn closed(&self) {
+        self.inner.lock().unwrap().phase = Phase::Closed;
+    }
+}
+
+impl fmt::Debug for ConnectionHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectionHandle")
+            .field("state", &self.state())
+            .finish()
+    }
+}
+
+// ===== impl ConnectionState =====
+
+impl ConnectionState {
+    /// Returns `true` if the HTTP/2.0 handshake has not completed yet.
+    pub fn is_handshaking(&self) -> bool {
+        self.phase == Phase::Handshaking
+    }
+
+    /// Returns `true` if a GOAWAY has been sent and the connection is waiting
+    /// for its streams to complete.
+    ///
+    /// A GOAWAY received from the peer does not make the connection report
+    /// as draining.
+    pub fn is_draining(&self) -> bool {
+        self.phase == Phase::Draining
+    }
+
+    /// Returns `true` if the connection has closed.
+    pub fn is_closed(&self) -> bool {
+        self.phase == Phase::Closed
+    }
+
+    /// Returns the number of streams whose response is currently being
+    /// processed.
+    pub fn active_streams(&self) -> usize {
+        self.active_streams
+    }
+
+    /// Returns the total number of streams accepted on the connection.
+    pub fn streams_accepted(&self) -> u64 {
+        self.streams_accepted
+    }
+
+    /// Returns the reason of the last GOAWAY sent on the connection, if any.
+    ///
+    /// A graceful shutdown is reported as `NO_ERROR`. GOAWAYs received from
+    /// the peer are not reported, because h2 does not expose them.
+    pub fn last_goaway(&self) -> Option<Reason> {
+        self.last_goaway
+    }
+
+    /// Returns the `SETTINGS_INITIAL_WINDOW_SIZE` advertised to the peer, if
+    /// it was configured.
+    pub fn initial_window_size(&self) -> Option<u32> {
+        self.settings.initial_window_size
+    }
+
+    /// Returns the initial connection-level flow control window, if it was
+    /// configured.
+    ///
+    /// This is not a setting; the window is enlarged with a WINDOW_UPDATE
+    /// frame once the connection starts.
+    pub fn initial_connection_window_size(&self) -> Option<u32> {
+        self.settings.initial_connection_window_size
+    }
+
+    /// Returns the `SETTINGS_MAX_CONCURRENT_STREAMS` advertised to the peer,
+    /// if it was configured.
+    pub fn max_concurrent_streams(&self) -> Option<u32> {
+        self.settings.max_concurrent_streams
+    }
+
+    /// Returns the `SETTINGS_MAX_FRAME_SIZE` advertised to the peer, if it
+    /// was configured.
+    pub fn max_frame_size(&self) -> Option<u32> {
+        self.settings.max_frame_size
+    }
+
+    /// Returns the `SETTINGS_MAX_HEADER_LIST_SIZE` advertised to the peer,
+    /// if it was configured.
+    pub fn max_header_list_size(&self) -> Option<u32> {
+        self.settings.max_header_list_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConnectionHandle;
+    use super::super::Builder;
+    use super::super::in_flight::InFlight;
+    use super::super::stats;
+
+    #[test]
+    fn state_reports_configured_windows() {
+        let mut builder = Builder::new();
+        builder
+            .initial_window_size(1 << 20)
+            .initial_connection_window_size(4 << 20);
+
+        let handle = ConnectionHandle::new(
+            builder.settings(),
+            InFlight::new(None),
+            stats::Shared::default(),
+        );
+
+        let state = handle.state();
+        assert_eq!(state.initial_window_size(), Some(1 << 20));
+        assert_eq!(state.initial_connection_window_size(), Some(4 << 20));
+        assert_eq!(state.max_concurrent_streams(), None);
+    }
+}
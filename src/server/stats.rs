@@ -0,0 +1,147 @@
+use h2::Reason;
+use http::Response;
+
+use std::sync::{Arc, Mutex};
+
+/// Cumulative counters of a `Connection`'s activity.
+///
+/// Header sizes are counted as defined by `SETTINGS_MAX_HEADER_LIST_SIZE`:
+/// the uncompressed size of each field plus 32 octets of overhead. h2 does not
+/// expose the size of the HPACK-encoded header blocks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    streams_accepted: u64,
+    streams_refused: u64,
+    streams_reset_by_peer: u64,
+    streams_reset_locally: u64,
+    bytes_received: u64,
+    bytes_sent: u64,
+    header_bytes_received: u64,
+    header_bytes_sent: u64,
+    send_buffered: u64,
+}
+
+/// The `Stats` of a connection, shared with its transport and `Background`
+/// tasks.
+#[derive(Clone, Debug, Default)]
+pub(super) struct Shared {
+    inner: Arc<Mutex<Stats>>,
+}
+
+// ===== impl Stats =====
+
+impl Stats {
+    /// Returns the number of streams received from the peer.
+    pub fn streams_accepted(&self) -> u64 {
+        self.streams_accepted
+    }
+
+    /// Returns the number of streams the server reset with `REFUSED_STREAM`,
+    /// which the peer may safely retry.
+    ///
+    /// These are also counted by `streams_reset_locally`.
+    pub fn streams_refused(&self) -> u64 {
+        self.streams_refused
+    }
+
+    /// Returns the number of streams the peer reset before the server was
+    /// done with them.
+    pub fn streams_reset_by_peer(&self) -> u64 {
+        self.streams_reset_by_peer
+    }
+
+    /// Returns the number of streams the server reset.
+    pub fn streams_reset_locally(&self) -> u64 {
+        self.streams_reset_locally
+    }
+
+    /// Returns the number of bytes read from the transport.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    /// Returns the number of bytes written to the transport.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// Returns the size of the request headers received.
+    pub fn header_bytes_received(&self) -> u64 {
+        self.header_bytes_received
+    }
+
+    /// Returns the size of the response headers sent.
+    pub fn header_bytes_sent(&self) -> u64 {
+        self.header_bytes_sent
+    }
+
+    /// Returns the number of response body bytes currently buffered but not
+    /// sent yet, by the streams whose body is still being sent.
+    ///
+    /// Unlike the other counters, this is not cumulative.
+    pub fn send_buffered(&self) -> u64 {
+        self.send_buffered
+    }
+}
+
+// ===== impl Shared =====
+
+impl Shared {
+    pub fn get(&self) -> Stats {
+        *self.inner.lock().unwrap()
+    }
+
+    pub fn stream_accepted(&self, header_list_size: usize) {
+        let mut stats = self.inner.lock().unwrap();
+        stats.streams_accepted += 1;
+        stats.header_bytes_received += header_list_size as u64;
+    }
+
+    /// Records that the server reset a stream with `reason`.
+    pub fn reset(&self, reason: Reason) {
+        let mut stats = self.inner.lock().unwrap();
+        stats.streams_reset_locally += 1;
+
+        if reason == Reason::REFUSED_STREAM {
+            stats.streams_refused += 1;
+        }
+    }
+
+    pub fn reset_by_peer(&self) {
+        self.inner.lock().unwrap().streams_reset_by_peer += 1;
+    }
+
+    pub fn response_sent(&self, response: &Response<()>) {
+        self.inner.lock().unwrap().header_bytes_sent += header_list_size(response) as u64;
+    }
+
+    pub fn bytes_received(&self, n: usize) {
+        self.inner.lock().unwrap().bytes_received += n as u64;
+    }
+
+    pub fn bytes_sent(&self, n: usize) {
+        self.inner.lock().unwrap().bytes_sent += n as u64;
+    }
+
+    /// Records that the bytes buffered by a stream changed from `from` to
+    /// `to`.
+    pub fn send_buffered(&self, from: usize, to: usize) {
+        let mut stats = self.inner.lock().unwrap();
+        stats.send_buffered = stats.send_buffered + to as u64 - from as u64;
+    }
+}
+
+/// Returns the size of `response`'s header list, including its `:status`
+/// pseudo-header.
+fn header_list_size(response: &Response<()>) -> usize {
+    // Each field is counted as its name and value plus 32 octets of overhead.
+    const OVERHEAD: usize = 32;
+
+    let mut size = ":status".len() + 3 + OVERHEAD;
+
+    for (name, value) in response.headers() {
+        size += name.as_str().len() + value.len() + OVERHEAD;
+    }
+
+    size
+}
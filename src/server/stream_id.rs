@@ -0,0 +1,32 @@
+use std::fmt;
+
+/// Identifies a stream among the streams accepted on its connection.
+///
+/// A `StreamId` is inserted into the extensions of every request received by a
+/// `Server`, and is included in the log messages emitted while processing the
+/// stream, so that application logs may be correlated with them.
+///
+/// IDs are assigned sequentially, starting at 1, in the order streams are
+/// accepted. They are not the HTTP/2.0 stream identifiers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId(u64);
+
+// ===== impl StreamId =====
+
+impl StreamId {
+    /// Returns the ID that follows this one.
+    pub(super) fn next(&self) -> StreamId {
+        StreamId(self.0 + 1)
+    }
+
+    /// Returns the ID as an integer.
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
@@ -0,0 +1,224 @@
+//! Serving HTTP/2.0 over TLS.
+//!
+//! This module is only available when the `tls` feature is enabled.
+
+use {Body, RecvBody};
+use super::{Background, Closed, Connection, ConnectionInfo, Error, Server};
+
+use futures::{Future, Poll};
+use futures::future::Executor;
+use http::{Request, Response};
+use rustls::{ServerConfig, ServerSession, Session};
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_rustls::{AcceptAsync, ServerConfigExt, TlsStream};
+use tower::{NewService, Service};
+
+use std::{error, fmt, io, mem};
+use std::sync::Arc;
+
+/// The ALPN protocol identifier for HTTP/2.0 over TLS.
+const H2: &'static str = "h2";
+
+/// Accepts TLS connections that negotiated `h2` via ALPN and serves them with
+/// a `Server`.
+pub struct AlpnAccept<S, E, B>
+where S: NewService,
+      B: Body,
+{
+    server: Server<S, E, B>,
+    config: Arc<ServerConfig>,
+}
+
+/// Performs the TLS handshake and then drives the HTTP/2.0 connection.
+pub struct AcceptFuture<T, S, E, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService,
+      B: Body,
+{
+    state: State<T, S, E, B>,
+}
+
+enum State<T, S, E, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService,
+      B: Body,
+{
+    /// Performing the TLS handshake.
+    Handshake {
+        handshake: AcceptAsync<T>,
+        server: Server<S, E, B>,
+        info: ConnectionInfo,
+    },
+
+    /// Serving the HTTP/2.0 connection over the established TLS session.
+    Serve(Connection<TlsStream<T, ServerSession>, S, E, B, ()>),
+
+    /// Transient state used while transitioning out of `Handshake`.
+    Empty,
+}
+
+/// Error produced by an `AcceptFuture`.
+pub enum AcceptError<S>
+where S: NewService,
+{
+    /// The TLS handshake failed.
+    Tls(io::Error),
+
+    /// The peer did not negotiate `h2` via ALPN.
+    ///
+    /// Contains the protocol that was negotiated instead, if any.
+    Alpn(Option<String>),
+
+    /// Error produced by the HTTP/2.0 connection.
+    Connection(Error<S>),
+}
+
+// ===== impl AlpnAccept =====
+
+impl<S, E, B> AlpnAccept<S, E, B>
+where S: NewService<Request = Request<RecvBody>, Response = Response<B>> + Clone,
+      E: Clone,
+      B: Body,
+{
+    /// Create a new `AlpnAccept`.
+    ///
+    /// `config` is updated to advertise `h2` as the only ALPN protocol.
+    pub fn new(server: Server<S, E, B>, mut config: ServerConfig) -> Self {
+        config.set_protocols(&[H2.to_owned()]);
+
+        AlpnAccept {
+            server,
+            config: Arc::new(config),
+        }
+    }
+
+    /// Accept a TLS connection on `io` and serve HTTP/2.0 on it.
+    pub fn accept<T>(&self, io: T) -> AcceptFuture<T, S, E, B>
+    where T: AsyncRead + AsyncWrite,
+    {
+        self.accept_with_info(io, ConnectionInfo::new())
+    }
+
+    /// Accept a TLS connection on `io` and serve HTTP/2.0 on it.
+    ///
+    /// Once the protocol has been negotiated, it is recorded in `info`, which
+    /// is then passed to `Server::serve_with_info`.
+    pub fn accept_with_info<T>(&self, io: T, info: ConnectionInfo)
+        -> AcceptFuture<T, S, E, B>
+    where T: AsyncRead + AsyncWrite,
+    {
+        let handshake = self.config.accept_async(io);
+
+        AcceptFuture {
+            state: State::Handshake {
+                handshake,
+                server: self.server.clone(),
+                info,
+            },
+        }
+    }
+}
+
+// ===== impl AcceptFuture =====
+
+impl<T, S, E, B> Future for AcceptFuture<T, S, E, B>
+where T: AsyncRead + AsyncWrite,
+      S: NewService<Request = Request<RecvBody>, Response = Response<B>>,
+      E: Executor<Background<<S::Service as Service>::Future, B>> + Clone,
+      B: Body + 'static,
+{
+    type Item = Closed;
+    type Error = AcceptError<S>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let tls = match self.state {
+                State::Handshake { ref mut handshake, .. } => {
+                    try_ready!(handshake.poll().map_err(AcceptError::Tls))
+                }
+                State::Serve(ref mut connection) => {
+                    return connection.poll().map_err(AcceptError::Connection);
+                }
+                State::Empty => unreachable!(),
+            };
+
+            let negotiated = tls.get_ref().1.get_alpn_protocol()
+                .map(ToOwned::to_owned);
+
+            if negotiated.as_ref().map(String::as_str) != Some(H2) {
+                debug!("peer did not negotiate h2; negotiated={:?}", negotiated);
+                return Err(AcceptError::Alpn(negotiated));
+            }
+
+            let connection = match mem::replace(&mut self.state, State::Empty) {
+                State::Handshake { server, mut info, .. } => {
+                    info.set_alpn_protocol(H2.as_bytes().to_vec());
+                    server.serve_with_info(tls, info)
+                }
+                _ => unreachable!(),
+            };
+
+            self.state = State::Serve(connection);
+        }
+    }
+}
+
+// ===== impl AcceptError =====
+
+impl<S> fmt::Debug for AcceptError<S>
+where
+    Error<S>: fmt::Debug,
+    S: NewService,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AcceptError::Tls(ref why) =>
+                f.debug_tuple("Tls").field(why).finish(),
+            AcceptError::Alpn(ref protocol) =>
+                f.debug_tuple("Alpn").field(protocol).finish(),
+            AcceptError::Connection(ref why) =>
+                f.debug_tuple("Connection").field(why).finish(),
+        }
+    }
+}
+
+impl<S> fmt::Display for AcceptError<S>
+where
+    Error<S>: fmt::Display,
+    S: NewService,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AcceptError::Tls(ref why) =>
+                write!(f, "Error occurred during TLS handshake: {}", why),
+            AcceptError::Alpn(Some(ref protocol)) =>
+                write!(f, "Peer negotiated unsupported protocol: {}", protocol),
+            AcceptError::Alpn(None) =>
+                write!(f, "Peer did not negotiate a protocol via ALPN"),
+            AcceptError::Connection(ref why) =>
+                write!(f, "{}", why),
+        }
+    }
+}
+
+impl<S> error::Error for AcceptError<S>
+where
+    Error<S>: error::Error,
+    S: NewService,
+{
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            AcceptError::Tls(ref why) => Some(why),
+            AcceptError::Alpn(_) => None,
+            AcceptError::Connection(ref why) => Some(why),
+        }
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            AcceptError::Tls(_) => "error occurred during TLS handshake",
+            AcceptError::Alpn(_) => "peer did not negotiate h2 via ALPN",
+            AcceptError::Connection(_) => "error produced by HTTP/2.0 connection",
+        }
+    }
+}
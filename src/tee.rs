@@ -0,0 +1,219 @@
+use {Body, RecvBody, RecvError};
+
+use bytes::Bytes;
+use futures::{Async, Poll, Stream};
+use futures::task::{self, Task};
+use h2::{self, Reason};
+use http::HeaderMap;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// One of the two bodies returned by `RecvBody::tee`.
+///
+/// Each chunk received from the remote is yielded by both bodies. Either body
+/// may be read ahead of the other, until the chunks buffered for the other
+/// body reach the `tee`'s buffer cap. If one body is dropped, the other
+/// continues on its own.
+pub struct Tee {
+    shared: Arc<Mutex<Shared>>,
+    side: usize,
+}
+
+struct Shared {
+    body: RecvBody,
+    max_buffer: usize,
+    sides: [Side; 2],
+    done: bool,
+    error: Option<Reason>,
+    trailers: Option<Option<HeaderMap>>,
+}
+
+/// The state of one of the bodies.
+struct Side {
+    /// Chunks received by the other body that this one has yet to yield.
+    buffer: VecDeque<Bytes>,
+    buffered: usize,
+    task: Option<Task>,
+    dropped: bool,
+}
+
+// ===== impl Tee =====
+
+impl Tee {
+    pub(crate) fn new(body: RecvBody, max_buffer: usize) -> (Tee, Tee) {
+        let shared = Arc::new(Mutex::new(Shared {
+            body,
+            max_buffer,
+            sides: [Side::new(), Side::new()],
+            done: false,
+            error: None,
+            trailers: None,
+        }));
+
+        let a = Tee { shared: shared.clone(), side: 0 };
+        let b = Tee { shared, side: 1 };
+
+        (a, b)
+    }
+}
+
+impl Body for Tee {
+    type Data = Bytes;
+
+    fn is_end_stream(&self) -> bool {
+        let shared = self.shared.lock().unwrap();
+        shared.sides[self.side].buffer.is_empty() && shared.body.is_end_stream()
+    }
+
+    fn poll_data(&mut self) -> Poll<Option<Bytes>, h2::Error> {
+        let mut shared = self.shared.lock().unwrap();
+        let me = self.side;
+        let other = 1 - me;
+
+        if let Some(bytes) = shared.sides[me].buffer.pop_front() {
+            shared.sides[me].buffered -= bytes.len();
+            shared.sides[other].notify();
+            return Ok(Async::Ready(Some(bytes)));
+        }
+
+        if let Some(reason) = shared.error {
+            return Err(reason.into());
+        }
+
+        if shared.done {
+            return Ok(Async::Ready(None));
+        }
+
+        // Wait for the other body to catch up before reading further ahead.
+        if !shared.sides[other].dropped && shared.sides[other].buffered >= shared.max_buffer {
+            trace!("tee buffer full; waiting for the other body");
+            shared.sides[me].task = Some(task::current());
+            return Ok(Async::NotReady);
+        }
+
+        let res = shared.body.poll_data();
+
+        match res {
+            Ok(Async::Ready(Some(data))) => {
+                let bytes = Bytes::from(data);
+
+                if !shared.sides[other].dropped {
+                    shared.sides[other].buffered += bytes.len();
+                    shared.sides[other].buffer.push_back(bytes.clone());
+                }
+
+                shared.sides[other].notify();
+                Ok(Async::Ready(Some(bytes)))
+            }
+            Ok(Async::Ready(None)) => {
+                shared.done = true;
+                shared.sides[other].notify();
+                Ok(Async::Ready(None))
+            }
+            Ok(Async::NotReady) => {
+                // Only the task that last polled the stream is notified when
+                // data arrives, so it wakes this one once it receives a chunk.
+                shared.sides[me].task = Some(task::current());
+                Ok(Async::NotReady)
+            }
+            Err(e) => {
+                shared.error = Some(e.reason().unwrap_or(Reason::INTERNAL_ERROR));
+                shared.sides[other].notify();
+                Err(e)
+            }
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, h2::Error> {
+        let mut shared = self.shared.lock().unwrap();
+
+        if let Some(ref trailers) = shared.trailers {
+            return Ok(Async::Ready(trailers.clone()));
+        }
+
+        if let Some(reason) = shared.error {
+            return Err(reason.into());
+        }
+
+        let res = shared.body.poll_trailers();
+
+        match res {
+            Ok(Async::Ready(trailers)) => {
+                shared.trailers = Some(trailers.clone());
+                shared.sides[1 - self.side].notify();
+                Ok(Async::Ready(trailers))
+            }
+            Ok(Async::NotReady) => {
+                shared.sides[self.side].task = Some(task::current());
+                Ok(Async::NotReady)
+            }
+            Err(e) => {
+                shared.error = Some(e.reason().unwrap_or(Reason::INTERNAL_ERROR));
+                shared.sides[1 - self.side].notify();
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Yields the body's data chunks, like the `Stream` implementation of
+/// `RecvBody`.
+impl Stream for Tee {
+    type Item = Bytes;
+    type Error = RecvError;
+
+    fn poll(&mut self) -> Poll<Option<Bytes>, RecvError> {
+        self.poll_data().map_err(RecvError::from)
+    }
+}
+
+impl Drop for Tee {
+    fn drop(&mut self) {
+        let mut shared = match self.shared.lock() {
+            Ok(shared) => shared,
+            Err(_) => return,
+        };
+
+        {
+            let side = &mut shared.sides[self.side];
+            side.dropped = true;
+            side.buffer.clear();
+            side.buffered = 0;
+        }
+
+        // The other body may be waiting for this one to catch up.
+        shared.sides[1 - self.side].notify();
+    }
+}
+
+impl fmt::Debug for Tee {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let shared = self.shared.lock().unwrap();
+
+        f.debug_struct("Tee")
+            .field("buffered", &shared.sides[self.side].buffered)
+            .field("max_buffer", &shared.max_buffer)
+            .finish()
+    }
+}
+
+// ===== impl Side =====
+
+impl Side {
+    fn new() -> Self {
+        Side {
+            buffer: VecDeque::new(),
+            buffered: 0,
+            task: None,
+            dropped: false,
+        }
+    }
+
+    fn notify(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.notify();
+        }
+    }
+}